@@ -3,5 +3,19 @@
 pub mod macros;
 pub mod prelude;
 
+pub mod collection;
+pub mod collection_collaborator;
+pub mod collection_post;
+pub mod comment;
+pub mod comment_revision;
+pub mod permission_grant;
+pub mod post;
+pub mod post_favorite;
+pub mod post_revision;
+pub mod post_tag;
 pub mod sea_orm_active_enums;
+pub mod tag;
+pub mod tag_alias;
+pub mod tag_implication;
 pub mod user_account;
+pub mod user_preference;
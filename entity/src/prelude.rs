@@ -1,3 +1,17 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.6.0
 
+pub use super::collection::Entity as Collection;
+pub use super::collection_collaborator::Entity as CollectionCollaborator;
+pub use super::collection_post::Entity as CollectionPost;
+pub use super::comment::Entity as Comment;
+pub use super::comment_revision::Entity as CommentRevision;
+pub use super::permission_grant::Entity as PermissionGrant;
+pub use super::post::Entity as Post;
+pub use super::post_favorite::Entity as PostFavorite;
+pub use super::post_revision::Entity as PostRevision;
+pub use super::post_tag::Entity as PostTag;
+pub use super::tag::Entity as Tag;
+pub use super::tag_alias::Entity as TagAlias;
+pub use super::tag_implication::Entity as TagImplication;
 pub use super::user_account::Entity as UserAccount;
+pub use super::user_preference::Entity as UserPreference;
@@ -7,9 +7,68 @@ use sea_orm::{
     IntoActiveValue, Set,
 };
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 
 #[derive(
-    Debug, Clone, PartialEq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, PolarClass,
+    Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, PolarClass,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "media_format")]
+pub enum MediaFormat {
+    #[sea_orm(string_value = "gif")]
+    Gif,
+    #[sea_orm(string_value = "jpeg")]
+    Jpeg,
+    #[sea_orm(string_value = "png")]
+    Png,
+    #[sea_orm(string_value = "webp")]
+    Webp,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, PolarClass,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "post_rating")]
+pub enum PostRating {
+    #[sea_orm(string_value = "explicit")]
+    Explicit,
+    #[sea_orm(string_value = "questionable")]
+    Questionable,
+    #[sea_orm(string_value = "safe")]
+    Safe,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, PolarClass,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "post_status")]
+pub enum PostStatus {
+    #[sea_orm(string_value = "active")]
+    Active,
+    #[sea_orm(string_value = "hidden")]
+    Hidden,
+    #[sea_orm(string_value = "pending")]
+    Pending,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, PolarClass,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "tag_category")]
+pub enum TagCategory {
+    #[sea_orm(string_value = "artist")]
+    Artist,
+    #[sea_orm(string_value = "character")]
+    Character,
+    #[sea_orm(string_value = "copyright")]
+    Copyright,
+    #[sea_orm(string_value = "general")]
+    General,
+    #[sea_orm(string_value = "meta")]
+    Meta,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, PolarClass,
 )]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "user_role")]
 pub enum UserRole {
@@ -27,6 +86,65 @@ pub enum UserRole {
     Moderator,
 }
 
+impl UserRole {
+    /// Every role ordered from least to most privileged.
+    ///
+    /// This is the only place the role hierarchy is defined; new roles only need to be slotted in here.
+    pub const HIERARCHY: [UserRole; 6] = [
+        UserRole::Member,
+        UserRole::Contributor,
+        UserRole::Creator,
+        UserRole::Maintainer,
+        UserRole::Moderator,
+        UserRole::Admin,
+    ];
+
+    /// The position of this role in `HIERARCHY`. Higher ranks are more privileged.
+    pub fn rank(&self) -> usize {
+        Self::HIERARCHY
+            .iter()
+            .position(|role| role == self)
+            .expect("role missing from hierarchy")
+    }
+
+    /// Every role less privileged than this one.
+    pub fn below(&self) -> Vec<UserRole> {
+        Self::HIERARCHY[..self.rank()].to_vec()
+    }
+
+    /// Whether this role is strictly more privileged than `other`.
+    pub fn outranks(&self, other: &UserRole) -> bool {
+        self.rank() > other.rank()
+    }
+
+    /// Whether this role is at least as privileged as `other`.
+    pub fn at_least(&self, other: &UserRole) -> bool {
+        self.rank() >= other.rank()
+    }
+
+    /// The most terms a search by a user with this role may contain.
+    pub fn max_search_terms(&self) -> usize {
+        match self {
+            UserRole::Member => 6,
+            UserRole::Contributor | UserRole::Creator => 10,
+            UserRole::Maintainer | UserRole::Moderator => 20,
+            UserRole::Admin => 40,
+        }
+    }
+}
+
+impl PartialOrd for UserRole {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UserRole {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
 impl IntoActiveValue<UserRole> for Option<UserRole> {
     fn into_active_value(self) -> ActiveValue<UserRole> {
         match self {
@@ -35,3 +153,52 @@ impl IntoActiveValue<UserRole> for Option<UserRole> {
         }
     }
 }
+
+impl MediaFormat {
+    /// The MIME type files of this format are served with.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            MediaFormat::Gif => "image/gif",
+            MediaFormat::Jpeg => "image/jpeg",
+            MediaFormat::Png => "image/png",
+            MediaFormat::Webp => "image/webp",
+        }
+    }
+
+    /// The file extension files of this format are stored with.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MediaFormat::Gif => "gif",
+            MediaFormat::Jpeg => "jpg",
+            MediaFormat::Png => "png",
+            MediaFormat::Webp => "webp",
+        }
+    }
+}
+
+impl IntoActiveValue<PostRating> for Option<PostRating> {
+    fn into_active_value(self) -> ActiveValue<PostRating> {
+        match self {
+            Some(value) => Set(value),
+            None => NotSet,
+        }
+    }
+}
+
+impl IntoActiveValue<PostStatus> for Option<PostStatus> {
+    fn into_active_value(self) -> ActiveValue<PostStatus> {
+        match self {
+            Some(value) => Set(value),
+            None => NotSet,
+        }
+    }
+}
+
+impl IntoActiveValue<TagCategory> for Option<TagCategory> {
+    fn into_active_value(self) -> ActiveValue<TagCategory> {
+        match self {
+            Some(value) => Set(value),
+            None => NotSet,
+        }
+    }
+}
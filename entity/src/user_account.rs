@@ -42,12 +42,23 @@ pub struct UpdateUserAccount {
     pub role: Option<UserRole>,
 }
 
-#[derive(Copy, Clone, Debug, EnumIter)]
-pub enum Relation {}
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::post::Entity")]
+    Post,
+    #[sea_orm(has_one = "super::user_preference::Entity")]
+    UserPreference,
+}
+
+impl Related<super::post::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Post.def()
+    }
+}
 
-impl RelationTrait for Relation {
-    fn def(&self) -> RelationDef {
-        panic!("No RelationDef")
+impl Related<super::user_preference::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserPreference.def()
     }
 }
 
@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE TABLE permission_grant (
+    id UUID PRIMARY KEY NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    user_id UUID NOT NULL REFERENCES user_account (id) ON DELETE CASCADE,
+    permission TEXT NOT NULL,
+    resource_type TEXT,
+    resource_id UUID,
+    CHECK ((resource_type IS NULL) = (resource_id IS NULL)),
+    expires_at TIMESTAMPTZ,
+    granted_by UUID REFERENCES user_account (id) ON DELETE SET NULL
+);
+
+CREATE INDEX permission_grant_user_id_idx ON permission_grant (user_id);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS post;
+
+DROP TYPE IF EXISTS post_status;
+
+DROP TYPE IF EXISTS post_rating;
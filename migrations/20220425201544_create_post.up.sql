@@ -0,0 +1,20 @@
+-- Add up migration script here
+CREATE TYPE post_rating AS ENUM ('safe', 'questionable', 'explicit');
+
+CREATE TYPE post_status AS ENUM ('pending', 'active', 'hidden');
+
+CREATE TABLE post (
+    id UUID PRIMARY KEY NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    uploader_id UUID NOT NULL REFERENCES user_account (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    source_urls JSONB NOT NULL DEFAULT '[]',
+    rating post_rating NOT NULL DEFAULT 'safe',
+    status post_status NOT NULL DEFAULT 'active'
+);
+
+CREATE INDEX post_uploader_id_idx ON post (uploader_id);
+
+SELECT manage_updated_at('post');
@@ -0,0 +1,12 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS post_tag;
+
+DROP FUNCTION IF EXISTS update_tag_post_count();
+
+DROP TABLE IF EXISTS tag_implication;
+
+DROP TABLE IF EXISTS tag_alias;
+
+DROP TABLE IF EXISTS tag;
+
+DROP TYPE IF EXISTS tag_category;
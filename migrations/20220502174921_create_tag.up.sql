@@ -0,0 +1,56 @@
+-- Add up migration script here
+CREATE TYPE tag_category AS ENUM ('artist', 'character', 'copyright', 'general', 'meta');
+
+CREATE TABLE tag (
+    id UUID PRIMARY KEY NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    name TEXT NOT NULL UNIQUE,
+    category tag_category NOT NULL DEFAULT 'general',
+    post_count INTEGER NOT NULL DEFAULT 0
+);
+
+SELECT manage_updated_at('tag');
+
+-- Alternative names that resolve to a canonical tag
+CREATE TABLE tag_alias (
+    name TEXT PRIMARY KEY NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    tag_id UUID NOT NULL REFERENCES tag (id) ON DELETE CASCADE
+);
+
+CREATE INDEX tag_alias_tag_id_idx ON tag_alias (tag_id);
+
+-- Tagging a post with `tag_id` also tags it with `implied_tag_id`
+CREATE TABLE tag_implication (
+    tag_id UUID NOT NULL REFERENCES tag (id) ON DELETE CASCADE,
+    implied_tag_id UUID NOT NULL REFERENCES tag (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (tag_id, implied_tag_id),
+    CHECK (tag_id <> implied_tag_id)
+);
+
+CREATE TABLE post_tag (
+    post_id UUID NOT NULL REFERENCES post (id) ON DELETE CASCADE,
+    tag_id UUID NOT NULL REFERENCES tag (id) ON DELETE CASCADE,
+    PRIMARY KEY (post_id, tag_id)
+);
+
+CREATE INDEX post_tag_tag_id_idx ON post_tag (tag_id);
+
+-- Keeps `tag.post_count` in step with `post_tag`
+CREATE OR REPLACE FUNCTION update_tag_post_count() RETURNS trigger AS $$
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        UPDATE tag SET post_count = post_count + 1 WHERE id = NEW.tag_id;
+        RETURN NEW;
+    ELSIF (TG_OP = 'DELETE') THEN
+        UPDATE tag SET post_count = post_count - 1 WHERE id = OLD.tag_id;
+        RETURN OLD;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER update_tag_post_count AFTER INSERT OR DELETE ON post_tag
+    FOR EACH ROW EXECUTE PROCEDURE update_tag_post_count();
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX post_created_at_idx;
+
+ALTER TABLE post DROP COLUMN score;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE post ADD COLUMN score INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX post_score_idx ON post (score);
+
+CREATE INDEX post_created_at_idx ON post (created_at);
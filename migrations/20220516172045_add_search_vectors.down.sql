@@ -0,0 +1,14 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS set_search_vector ON tag;
+
+DROP TRIGGER IF EXISTS set_search_vector ON post;
+
+ALTER TABLE tag DROP COLUMN search_vector;
+
+ALTER TABLE post DROP COLUMN search_vector;
+
+ALTER TABLE tag DROP COLUMN wiki;
+
+DROP FUNCTION IF EXISTS manage_search_vector(_tbl regclass, _primary text, _secondary text);
+
+DROP FUNCTION IF EXISTS set_search_vector();
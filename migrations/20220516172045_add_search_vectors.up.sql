@@ -0,0 +1,55 @@
+-- Add up migration script here
+
+-- Sets up a trigger for the given table to keep a `tsvector` column called
+-- `search_vector` in step with two text columns, the first weighted above the
+-- second
+--
+-- # Example
+--
+-- ```sql
+-- CREATE TABLE articles (title TEXT, body TEXT, search_vector tsvector NOT NULL DEFAULT ''::tsvector);
+--
+-- SELECT manage_search_vector('articles', 'title', 'body');
+-- ```
+CREATE OR REPLACE FUNCTION manage_search_vector(_tbl regclass, _primary text, _secondary text) RETURNS VOID AS $$
+BEGIN
+    EXECUTE format('CREATE TRIGGER set_search_vector BEFORE INSERT OR UPDATE OF %I, %I ON %s
+                    FOR EACH ROW EXECUTE PROCEDURE set_search_vector(%L, %L)',
+                   _primary, _secondary, _tbl, _primary, _secondary);
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION set_search_vector() RETURNS trigger AS $$
+DECLARE
+    primary_text TEXT;
+    secondary_text TEXT;
+BEGIN
+    EXECUTE format('SELECT ($1).%I::text, ($1).%I::text', TG_ARGV[0], TG_ARGV[1])
+        INTO primary_text, secondary_text
+        USING NEW;
+    NEW.search_vector :=
+        setweight(to_tsvector('english', coalesce(primary_text, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(secondary_text, '')), 'B');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- The wiki of a tag, describing what it is used for
+ALTER TABLE tag ADD COLUMN wiki TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE post ADD COLUMN search_vector tsvector NOT NULL DEFAULT ''::tsvector;
+
+ALTER TABLE tag ADD COLUMN search_vector tsvector NOT NULL DEFAULT ''::tsvector;
+
+SELECT manage_search_vector('post', 'title', 'description');
+
+SELECT manage_search_vector('tag', 'name', 'wiki');
+
+-- Fill in existing rows through the triggers
+UPDATE post SET title = title;
+
+UPDATE tag SET name = name;
+
+CREATE INDEX post_search_vector_idx ON post USING GIN (search_vector);
+
+CREATE INDEX tag_search_vector_idx ON tag USING GIN (search_vector);
@@ -0,0 +1,9 @@
+-- Add down migration script here
+ALTER TABLE post
+    DROP COLUMN media_hash,
+    DROP COLUMN media_format,
+    DROP COLUMN media_size,
+    DROP COLUMN width,
+    DROP COLUMN height;
+
+DROP TYPE IF EXISTS media_format;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TYPE media_format AS ENUM ('gif', 'jpeg', 'png', 'webp');
+
+-- Posts created without an upload (e.g. imported ones) have no media yet
+ALTER TABLE post
+    ADD COLUMN media_hash TEXT UNIQUE,
+    ADD COLUMN media_format media_format,
+    ADD COLUMN media_size BIGINT,
+    ADD COLUMN width INTEGER,
+    ADD COLUMN height INTEGER;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE post
+    DROP COLUMN media_dhash;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- The 64-bit difference hash of the media, for finding near-duplicates. Posts uploaded before this
+-- are hashed in the background when the server starts.
+ALTER TABLE post
+    ADD COLUMN media_dhash BIGINT;
@@ -0,0 +1,7 @@
+-- Add down migration script here
+ALTER TABLE post
+    DROP COLUMN camera,
+    DROP COLUMN software,
+    DROP COLUMN taken_at,
+    DROP COLUMN embedded_artist,
+    DROP COLUMN embedded_copyright;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- Read from the metadata embedded in the uploaded media, before it's stripped
+ALTER TABLE post
+    ADD COLUMN camera TEXT,
+    ADD COLUMN software TEXT,
+    ADD COLUMN taken_at TIMESTAMP,
+    ADD COLUMN embedded_artist TEXT,
+    ADD COLUMN embedded_copyright TEXT;
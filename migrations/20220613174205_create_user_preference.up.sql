@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Users without a row here see every rating; see `preferences::ContentPreferences`
+CREATE TABLE user_preference (
+    user_id UUID PRIMARY KEY NOT NULL REFERENCES user_account (id) ON DELETE CASCADE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    show_safe BOOLEAN NOT NULL DEFAULT TRUE,
+    show_questionable BOOLEAN NOT NULL DEFAULT TRUE,
+    show_explicit BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+SELECT manage_updated_at('user_preference');
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE user_preference
+    DROP COLUMN tag_blacklist,
+    DROP COLUMN mark_blacklisted;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- One search query per line; see `preferences::parse_blacklist`
+ALTER TABLE user_preference
+    ADD COLUMN tag_blacklist TEXT NOT NULL DEFAULT '',
+    ADD COLUMN mark_blacklisted BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,16 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS collection_collaborator;
+
+DROP TABLE IF EXISTS collection_post;
+
+DROP FUNCTION IF EXISTS update_collection_post_count();
+
+DROP TABLE IF EXISTS collection;
+
+DROP TABLE IF EXISTS post_favorite;
+
+DROP FUNCTION IF EXISTS update_post_favorite_count();
+
+ALTER TABLE user_preference DROP COLUMN public_favorites;
+
+ALTER TABLE post DROP COLUMN favorite_count;
@@ -0,0 +1,89 @@
+-- Add up migration script here
+ALTER TABLE post ADD COLUMN favorite_count INTEGER NOT NULL DEFAULT 0;
+
+-- Favorites are private unless made public; see `collections::Favorites`
+ALTER TABLE user_preference ADD COLUMN public_favorites BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE post_favorite (
+    user_id UUID NOT NULL REFERENCES user_account (id) ON DELETE CASCADE,
+    post_id UUID NOT NULL REFERENCES post (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, post_id)
+);
+
+CREATE INDEX post_favorite_post_id_idx ON post_favorite (post_id);
+
+CREATE INDEX post_favorite_user_id_created_at_idx ON post_favorite (user_id, created_at);
+
+-- Keeps `post.favorite_count` in step with `post_favorite`
+CREATE OR REPLACE FUNCTION update_post_favorite_count() RETURNS trigger AS $$
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        UPDATE post SET favorite_count = favorite_count + 1 WHERE id = NEW.post_id;
+        RETURN NEW;
+    ELSIF (TG_OP = 'DELETE') THEN
+        UPDATE post SET favorite_count = favorite_count - 1 WHERE id = OLD.post_id;
+        RETURN OLD;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER update_post_favorite_count AFTER INSERT OR DELETE ON post_favorite
+    FOR EACH ROW EXECUTE PROCEDURE update_post_favorite_count();
+
+CREATE TABLE collection (
+    id UUID PRIMARY KEY NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    owner_id UUID NOT NULL REFERENCES user_account (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    is_public BOOLEAN NOT NULL DEFAULT FALSE,
+    post_count INTEGER NOT NULL DEFAULT 0
+);
+
+SELECT manage_updated_at('collection');
+
+CREATE INDEX collection_owner_id_idx ON collection (owner_id);
+
+-- The posts of a collection are shown by ascending `position`
+CREATE TABLE collection_post (
+    collection_id UUID NOT NULL REFERENCES collection (id) ON DELETE CASCADE,
+    post_id UUID NOT NULL REFERENCES post (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    added_by UUID REFERENCES user_account (id) ON DELETE SET NULL,
+    PRIMARY KEY (collection_id, post_id)
+);
+
+CREATE INDEX collection_post_position_idx ON collection_post (collection_id, position);
+
+CREATE INDEX collection_post_post_id_idx ON collection_post (post_id);
+
+-- Keeps `collection.post_count` in step with `collection_post`
+CREATE OR REPLACE FUNCTION update_collection_post_count() RETURNS trigger AS $$
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        UPDATE collection SET post_count = post_count + 1 WHERE id = NEW.collection_id;
+        RETURN NEW;
+    ELSIF (TG_OP = 'DELETE') THEN
+        UPDATE collection SET post_count = post_count - 1 WHERE id = OLD.collection_id;
+        RETURN OLD;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER update_collection_post_count AFTER INSERT OR DELETE ON collection_post
+    FOR EACH ROW EXECUTE PROCEDURE update_collection_post_count();
+
+-- Users other than the owner who may add, remove and reorder the posts of a collection
+CREATE TABLE collection_collaborator (
+    collection_id UUID NOT NULL REFERENCES collection (id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES user_account (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (collection_id, user_id)
+);
+
+CREATE INDEX collection_collaborator_user_id_idx ON collection_collaborator (user_id);
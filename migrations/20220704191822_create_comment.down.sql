@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS comment_revision;
+
+DROP TABLE IF EXISTS comment;
@@ -0,0 +1,38 @@
+-- Add up migration script here
+CREATE TABLE comment (
+    id UUID PRIMARY KEY NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    post_id UUID NOT NULL REFERENCES post (id) ON DELETE CASCADE,
+    author_id UUID NOT NULL REFERENCES user_account (id) ON DELETE CASCADE,
+    -- NULL for comments that start a thread
+    parent_id UUID REFERENCES comment (id) ON DELETE CASCADE,
+    depth INTEGER NOT NULL DEFAULT 0,
+    -- the markdown as written, and the sanitized HTML it renders to
+    body TEXT NOT NULL,
+    body_html TEXT NOT NULL,
+    edited_at TIMESTAMPTZ,
+    -- deleted comments keep their place in the thread, but not their body
+    deleted_at TIMESTAMPTZ,
+    deleted_by UUID REFERENCES user_account (id) ON DELETE SET NULL,
+    hidden_at TIMESTAMPTZ,
+    hidden_by UUID REFERENCES user_account (id) ON DELETE SET NULL
+);
+
+SELECT manage_updated_at('comment');
+
+CREATE INDEX comment_post_id_created_at_idx ON comment (post_id, created_at) WHERE parent_id IS NULL;
+
+CREATE INDEX comment_parent_id_idx ON comment (parent_id);
+
+CREATE INDEX comment_author_id_idx ON comment (author_id);
+
+-- Every version of every comment, including the first
+CREATE TABLE comment_revision (
+    comment_id UUID NOT NULL REFERENCES comment (id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    editor_id UUID REFERENCES user_account (id) ON DELETE SET NULL,
+    body TEXT NOT NULL,
+    PRIMARY KEY (comment_id, version)
+);
@@ -0,0 +1,21 @@
+-- Add up migration script here
+-- Every version of the community-edited content of every post: its tags, sources, rating and
+-- description. Each row is a full snapshot, so any two versions can be compared directly.
+CREATE TABLE post_revision (
+    post_id UUID NOT NULL REFERENCES post (id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+    editor_id UUID REFERENCES user_account (id) ON DELETE SET NULL,
+    -- the editor's role when they made the edit, so reverts are judged by who they were then
+    editor_role user_role NOT NULL,
+    -- the names of the post's tags, sorted
+    tags JSONB NOT NULL,
+    source_urls JSONB NOT NULL,
+    rating post_rating NOT NULL,
+    description TEXT NOT NULL,
+    -- the version this one restored, if it was a revert
+    reverted_to INTEGER,
+    PRIMARY KEY (post_id, version)
+);
+
+CREATE INDEX post_revision_editor_id_idx ON post_revision (editor_id);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP INDEX post_favorite_count_idx;
+
+ALTER TABLE post ADD COLUMN score INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX post_score_idx ON post (score);
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- Posts are ordered by `favorite_count` instead, which the score was never set apart from
+ALTER TABLE post DROP COLUMN score;
+
+CREATE INDEX post_favorite_count_idx ON post (favorite_count);
@@ -1,25 +1,114 @@
 //! CRUD action-like resources
 use anyhow::Result;
-use entity::{sea_orm_active_enums::UserRole, user_account};
+use chrono::Utc;
+use entity::{
+    comment,
+    sea_orm_active_enums::{PostRating, PostStatus, TagCategory, UserRole},
+    user_account,
+};
 use oso::{Oso, PolarClass};
 use serde::Deserialize;
-use validator::Validate;
+use std::{collections::HashSet, sync::Arc};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
 
-use crate::constants::RE_USERNAME;
+use crate::{
+    auth::Guest, collections, constants::RE_USERNAME, filtering::Filter, permissions::Grants,
+    policy,
+};
 
 /// The "READ" action. Because there is no data pertinent to this action it is a unit struct.
-#[derive(Debug, Clone, Copy, PolarClass)]
+///
+/// Policies can construct it with `new Read()`, to build rules on top of whether something can be
+/// read.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
 pub struct Read;
 
+/// The "CREATE" action. Because the resource doesn't exist yet, it's authorized against the name
+/// of its class instead, e.g. `allow(user: User, _: Create, "Post")`.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Create;
+
+/// The "UPLOAD" action, for creating posts from uploaded files. Like `Create`, it's authorized
+/// against the class name, e.g. `allow(user: User, _: Upload, "Post")`.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Upload;
+
+/// The "FIND SIMILAR" action, for comparing an uploaded file against every post. Like `Upload`,
+/// it's authorized against the class name.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct FindSimilar;
+
+/// The "VIEW MEDIA" action, for fetching the files of a post rather than its metadata.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct ViewMedia;
+
 /// The "DELETE" action. Because there is no data pertinent to this action it is a unit struct.
-#[derive(Debug, Clone, Copy, PolarClass)]
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
 pub struct Delete;
 
+/// The "RELOAD" action, used for administratively reloading resources such as the policy.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Reload;
+
+/// The "EXPLAIN" action, for explaining how the policy reached a decision.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Explain;
+
+/// The "GRANT" action, for granting a user a fine-grained permission.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Grant;
+
+/// The "REVOKE" action, for revoking a permission previously granted to a user.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Revoke;
+
+/// The "EDIT TAGS" action, for replacing the tags of a post.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct EditTags;
+
+/// The "FAVORITE" action, for adding a post to one's favorites.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Favorite;
+
+/// The "EDIT POSTS" action, for adding, removing and reordering the posts of a collection.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct EditCollectionPosts;
+
+/// The "MANAGE COLLABORATORS" action, for adding and removing the collaborators of a collection.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct ManageCollaborators;
+
+/// The "COMMENT ON" action, for commenting on a post.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct CommentOn;
+
+/// The "EDIT COMMENT" action, for changing the body of a comment.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct EditComment;
+
+/// The "HIDE" action, for moderators to take something out of view without deleting it.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Hide;
+
+/// The "RESTORE" action, for bringing back something that was hidden.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Restore;
+
+/// The "REVERT" action, for undoing a revision of a post by restoring an earlier one.
+#[derive(Debug, Clone, Copy, Default, PolarClass)]
+pub struct Revert;
+
 /// The action by which a user is updated. Can be understood as a sort of changeset.
 ///
 /// This struct in particular doubles up for multiple use cases. It's used for PUT `/user/:id` form responses,
 /// in authorization rules, and also for updates to the ORM.
+///
+/// It is authorized field by field with `allow_field` rules, so that a changeset can be rejected
+/// with exactly the fields the requester may not set. Changesets that set nothing fail validation,
+/// as every actor may set every one of no fields.
 #[derive(Debug, Clone, Validate, Deserialize, PolarClass)]
+#[validate(schema(function = "validate_update_user"))]
 pub struct UpdateUser {
     #[validate(
         length(
@@ -41,6 +130,21 @@ pub struct UpdateUser {
     pub role: Option<UserRole>,
 }
 
+impl UpdateUser {
+    /// The names of the fields this changeset sets, as used in `allow_field` rules.
+    pub fn fields(&self) -> HashSet<String> {
+        [
+            ("name", self.name.is_some()),
+            ("email", self.email.is_some()),
+            ("role", self.role.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(field, _)| field.to_owned())
+        .collect()
+    }
+}
+
 impl From<UpdateUser> for user_account::UpdateUserAccount {
     fn from(update: UpdateUser) -> Self {
         Self {
@@ -51,21 +155,223 @@ impl From<UpdateUser> for user_account::UpdateUserAccount {
     }
 }
 
-/// Attempt to create a new oso instance for managing authorization schemes.
-pub fn try_register_oso() -> Result<Oso> {
-    let mut oso = Oso::new();
+/// The action by which a post is updated. Like `UpdateUser`, it's authorized field by field and
+/// must set at least one of them.
+#[derive(Debug, Clone, Validate, Deserialize, PolarClass)]
+#[validate(schema(function = "validate_update_post"))]
+pub struct UpdatePost {
+    #[validate(length(
+        min = 1,
+        max = 256,
+        message = "Minimum length is 1 character, maximum is 256"
+    ))]
+    #[polar(attribute)]
+    pub title: Option<String>,
+    #[validate(length(max = 10000, message = "Maximum length is 10000 characters"))]
+    #[polar(attribute)]
+    pub description: Option<String>,
+    /// Whitespace-separated source URLs, replacing the existing ones.
+    #[validate(custom = "validate_source_urls")]
+    pub source_urls: Option<String>,
+    #[polar(attribute)]
+    pub rating: Option<PostRating>,
+    #[polar(attribute)]
+    pub status: Option<PostStatus>,
+}
+
+impl UpdatePost {
+    /// The names of the fields this changeset sets, as used in `allow_field` rules.
+    pub fn fields(&self) -> HashSet<String> {
+        [
+            ("title", self.title.is_some()),
+            ("description", self.description.is_some()),
+            ("source_urls", self.source_urls.is_some()),
+            ("rating", self.rating.is_some()),
+            ("status", self.status.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(field, _)| field.to_owned())
+        .collect()
+    }
+}
+
+/// The action by which a tag is updated. Authorized as a whole, as any tag editor may change any
+/// of its fields.
+#[derive(Debug, Clone, Validate, Deserialize, PolarClass)]
+pub struct UpdateTag {
+    #[polar(attribute)]
+    pub category: Option<TagCategory>,
+    /// The tag's wiki, describing what it's used for.
+    #[validate(length(max = 20000, message = "Maximum length is 20000 characters"))]
+    #[polar(attribute)]
+    pub wiki: Option<String>,
+}
+
+/// The action by which a collection is updated. Authorized as a whole, like `UpdateTag`.
+#[derive(Debug, Clone, Validate, Deserialize, PolarClass)]
+pub struct UpdateCollection {
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "Minimum length is 1 character, maximum is 128"
+    ))]
+    #[polar(attribute)]
+    pub name: Option<String>,
+    #[validate(length(max = 10000, message = "Maximum length is 10000 characters"))]
+    #[polar(attribute)]
+    pub description: Option<String>,
+    #[polar(attribute)]
+    pub is_public: Option<bool>,
+}
+
+/// The most source URLs a single post may have.
+pub const MAX_SOURCE_URLS: usize = 16;
+
+/// Split whitespace-separated source URLs as submitted in forms.
+pub fn parse_source_urls(source_urls: &str) -> Vec<String> {
+    source_urls.split_whitespace().map(str::to_owned).collect()
+}
 
+/// Validate that a changeset authorized field by field sets at least one of them.
+fn validate_changes(fields: &HashSet<String>) -> Result<(), ValidationError> {
+    if fields.is_empty() {
+        let mut error = ValidationError::new("empty_changeset");
+        error.message = Some("Must change at least one field".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn validate_update_user(update: &UpdateUser) -> Result<(), ValidationError> {
+    validate_changes(&update.fields())
+}
+
+fn validate_update_post(update: &UpdatePost) -> Result<(), ValidationError> {
+    validate_changes(&update.fields())
+}
+
+/// Validate whitespace-separated source URLs as submitted in forms.
+pub fn validate_source_urls(source_urls: &str) -> Result<(), ValidationError> {
+    let urls = parse_source_urls(source_urls);
+    if urls.len() > MAX_SOURCE_URLS {
+        let mut error = ValidationError::new("source_urls");
+        error.message = Some(format!("At most {} sources are allowed", MAX_SOURCE_URLS).into());
+        return Err(error);
+    }
+    if !urls.iter().all(validator::validate_url) {
+        let mut error = ValidationError::new("source_urls");
+        error.message = Some("Must all be valid URLs".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Attempt to create a new oso instance for managing authorization schemes, checking fine-grained
+/// permissions against `grants`.
+pub fn try_register_oso(grants: &Arc<Grants>) -> Result<Oso> {
+    // NOTE: every rule file in `policy::POLAR_DIR` is loaded
+    let (oso, _) = policy::try_load_policy(grants)?;
+    Ok(oso)
+}
+
+/// Register every class policies may refer to, without loading any rules.
+pub fn register_classes(oso: &mut Oso, grants: &Arc<Grants>) -> Result<()> {
     // NOTE: load classes here
-    oso.register_class(entity::user_account::Model::get_polar_class())?;
-    oso.register_class(UserRole::get_polar_class())?;
+    let (global_grants, scoped_grants) = (grants.clone(), grants.clone());
+    oso.register_class(
+        user_account::Model::get_polar_class_builder()
+            .add_method(
+                "has_permission",
+                move |user: &user_account::Model, permission: String| {
+                    global_grants.has_permission(user.id, &permission, None)
+                },
+            )
+            .add_method(
+                "has_permission_on",
+                move |user: &user_account::Model,
+                      permission: String,
+                      resource_type: String,
+                      resource_id: Uuid| {
+                    scoped_grants.has_permission(
+                        user.id,
+                        &permission,
+                        Some((&resource_type, resource_id)),
+                    )
+                },
+            )
+            .build(),
+    )?;
+    oso.register_class(Guest::get_polar_class())?;
+    oso.register_class(
+        UserRole::get_polar_class_builder()
+            .add_method("outranks", |role: &UserRole, other: UserRole| {
+                role.outranks(&other)
+            })
+            .add_method("at_least", |role: &UserRole, other: UserRole| {
+                role.at_least(&other)
+            })
+            .add_method("below", UserRole::below)
+            .build(),
+    )?;
+    oso.register_class(entity::post::Model::get_polar_class())?;
+    oso.register_class(PostRating::get_polar_class())?;
+    oso.register_class(PostStatus::get_polar_class())?;
+    oso.register_class(entity::tag::Model::get_polar_class())?;
+    oso.register_class(TagCategory::get_polar_class())?;
+    oso.register_class(collections::CollectionResource::get_polar_class())?;
+    oso.register_class(
+        comment::Model::get_polar_class_builder()
+            .add_attribute_getter("deleted", |comment: &comment::Model| {
+                comment.deleted_at.is_some()
+            })
+            .add_attribute_getter("hidden", |comment: &comment::Model| {
+                comment.hidden_at.is_some()
+            })
+            .add_method("age_minutes", |comment: &comment::Model| {
+                Utc::now()
+                    .signed_duration_since(comment.created_at)
+                    .num_minutes()
+            })
+            .build(),
+    )?;
+    oso.register_class(entity::post_revision::Model::get_polar_class())?;
+    oso.register_class(collections::Favorites::get_polar_class())?;
+    oso.register_class(policy::Policy::get_polar_class())?;
+    oso.register_class(
+        Filter::get_polar_class_builder()
+            .set_constructor(Filter::new)
+            .build(),
+    )?;
 
     // action classes in this module should be loaded here too
-    oso.register_class(Read::get_polar_class())?;
+    oso.register_class(Create::get_polar_class())?;
+    oso.register_class(
+        Read::get_polar_class_builder()
+            .set_constructor(|| Read)
+            .build(),
+    )?;
+    oso.register_class(Upload::get_polar_class())?;
+    oso.register_class(FindSimilar::get_polar_class())?;
+    oso.register_class(ViewMedia::get_polar_class())?;
     oso.register_class(Delete::get_polar_class())?;
+    oso.register_class(Reload::get_polar_class())?;
+    oso.register_class(Explain::get_polar_class())?;
+    oso.register_class(Grant::get_polar_class())?;
+    oso.register_class(Revoke::get_polar_class())?;
+    oso.register_class(EditTags::get_polar_class())?;
+    oso.register_class(Favorite::get_polar_class())?;
+    oso.register_class(EditCollectionPosts::get_polar_class())?;
+    oso.register_class(ManageCollaborators::get_polar_class())?;
+    oso.register_class(CommentOn::get_polar_class())?;
+    oso.register_class(EditComment::get_polar_class())?;
+    oso.register_class(Hide::get_polar_class())?;
+    oso.register_class(Restore::get_polar_class())?;
+    oso.register_class(Revert::get_polar_class())?;
     oso.register_class(UpdateUser::get_polar_class())?;
+    oso.register_class(UpdatePost::get_polar_class())?;
+    oso.register_class(UpdateTag::get_polar_class())?;
+    oso.register_class(UpdateCollection::get_polar_class())?;
 
-    // NOTE: load oso rule files here
-    oso.load_files(vec!["polar/users.polar"])?;
-
-    Ok(oso)
+    Ok(())
 }
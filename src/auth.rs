@@ -1,12 +1,26 @@
 use axum::{
     async_trait,
-    extract::{Extension, FromRequest, RequestParts, TypedHeader},
+    extract::{ConnectInfo, Extension, FromRequest, Path, RequestParts, TypedHeader},
     headers::Cookie,
 };
+use oso::{Oso, PolarClass, PolarValue, ToPolar};
 use redis::AsyncCommands;
+use sea_orm::{DatabaseConnection, EntityTrait, PrimaryKeyTrait};
+use std::{
+    collections::HashSet,
+    fmt,
+    marker::PhantomData,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+use uuid::Uuid;
 
 use crate::{
-    constants::{SESSION_COOKIE_NAME, SESSION_DURATION_SECS, SESSION_KEY_PREFIX},
+    constants::{
+        AGE_GATE_COOKIE_NAME, CLIENT_HEADER_NAME, FORWARDED_FOR_HEADER_NAME, SESSION_COOKIE_NAME,
+        SESSION_DURATION_SECS, SESSION_KEY_PREFIX,
+    },
     error::MixiniError,
     server::State,
 };
@@ -15,23 +29,193 @@ use crate::{
 ///
 /// The extractor middleware that captures this Auth looks for a `SESSION_COOKIE_NAME` cookie
 /// with the value being the unprefixed key.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Auth {
     KnownUser(entity::user_account::Model),
-    UnknownUser,
+    UnknownUser(Guest),
+}
+
+/// A visitor without a session, as seen by policies.
+#[derive(Debug, Clone, Default, PolarClass)]
+pub struct Guest {
+    /// The address the request came from, as found by `client_ip`.
+    #[polar(attribute)]
+    pub ip: Option<String>,
+    /// The API client, as identified by the `CLIENT_HEADER_NAME` header.
+    #[polar(attribute)]
+    pub client: Option<String>,
+    /// Whether the visitor acknowledged the age gate, stored in the `AGE_GATE_COOKIE_NAME` cookie.
+    #[polar(attribute)]
+    pub age_gate_acknowledged: bool,
+}
+
+impl Auth {
+    /// Check that this principal may perform `action` on `resource`.
+    ///
+    /// Rejects with `Unauthorized` for guests and `Forbidden` for known users.
+    pub async fn authorize<A, R>(
+        &self,
+        oso: &Mutex<Oso>,
+        action: A,
+        resource: R,
+    ) -> Result<(), MixiniError>
+    where
+        A: ToPolar,
+        R: ToPolar,
+    {
+        if oso
+            .lock()
+            .await
+            .is_allowed(self.to_owned(), action, resource)?
+        {
+            Ok(())
+        } else {
+            match self {
+                Auth::KnownUser(_) => Err(MixiniError::Forbidden),
+                Auth::UnknownUser(_) => Err(MixiniError::Unauthorized),
+            }
+        }
+    }
+
+    /// Check that this principal may perform `action` on every one of the `requested` fields of
+    /// `resource`.
+    ///
+    /// Rejects with `Unauthorized` for guests and `ForbiddenFields` listing the offending fields for
+    /// known users.
+    pub async fn authorize_fields<A, R>(
+        &self,
+        oso: &Mutex<Oso>,
+        action: A,
+        resource: R,
+        requested: &HashSet<String>,
+    ) -> Result<(), MixiniError>
+    where
+        A: ToPolar,
+        R: ToPolar,
+    {
+        let authorized = self.authorized_fields(oso, action, resource).await?;
+        let mut forbidden: Vec<String> = requested.difference(&authorized).cloned().collect();
+
+        if forbidden.is_empty() {
+            Ok(())
+        } else {
+            match self {
+                Auth::KnownUser(_) => {
+                    forbidden.sort_unstable();
+                    Err(MixiniError::ForbiddenFields(forbidden))
+                }
+                Auth::UnknownUser(_) => Err(MixiniError::Unauthorized),
+            }
+        }
+    }
+
+    /// The fields of `resource` this principal may perform `action` on.
+    pub async fn authorized_fields<A, R>(
+        &self,
+        oso: &Mutex<Oso>,
+        action: A,
+        resource: R,
+    ) -> Result<HashSet<String>, MixiniError>
+    where
+        A: ToPolar,
+        R: ToPolar,
+    {
+        Ok(oso
+            .lock()
+            .await
+            .authorized_fields(self.to_owned(), action, resource)?)
+    }
+}
+
+/// Known users are passed to policies as themselves, and everyone else as their `Guest`.
+impl ToPolar for Auth {
+    fn to_polar(self) -> PolarValue {
+        match self {
+            Auth::KnownUser(user) => user.to_polar(),
+            Auth::UnknownUser(guest) => guest.to_polar(),
+        }
+    }
+}
+
+/// The address a request came from, given the `peer` that sent it and the value of its
+/// `FORWARDED_FOR_HEADER_NAME` header.
+///
+/// Every proxy appends the address it got the request from to the header, so only the entries
+/// added by `trusted_proxies` can be believed. The header is ignored unless the peer is trusted,
+/// and is otherwise read from the right up to the first address that isn't a trusted proxy.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let mut ip = peer?;
+    let hops = forwarded_for
+        .into_iter()
+        .flat_map(|value| value.rsplit(','));
+    for hop in hops {
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(hop) => ip = hop,
+            // nothing further left can be believed
+            Err(_) => break,
+        }
+    }
+    Some(ip)
 }
 
 #[async_trait]
-impl<B> FromRequest<B> for Auth
+impl<B> FromRequest<B> for Guest
 where
     B: Send,
 {
     type Rejection = MixiniError;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        let Extension(state) = Extension::<State>::from_request(req)
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_owned())
+                .filter(|value| !value.is_empty())
+        };
+        let forwarded_for = header(FORWARDED_FOR_HEADER_NAME);
+        let client = header(CLIENT_HEADER_NAME);
+
+        let Extension(state) = Extension::<Arc<State>>::from_request(req).await?;
+        let peer = Option::<ConnectInfo<SocketAddr>>::from_request(req)
             .await
-            .expect("State extension missing");
+            .unwrap()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let ip = client_ip(peer, forwarded_for.as_deref(), &state.trusted_proxies)
+            .map(|ip| ip.to_string());
+
+        let cookie = Option::<TypedHeader<Cookie>>::from_request(req)
+            .await
+            .unwrap();
+        let age_gate_acknowledged = cookie
+            .as_ref()
+            .and_then(|cookie| cookie.get(AGE_GATE_COOKIE_NAME))
+            .map_or(false, |value| value == "1");
+
+        Ok(Guest {
+            ip,
+            client,
+            age_gate_acknowledged,
+        })
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for Auth
+where
+    B: Send,
+{
+    type Rejection = MixiniError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(state) = Extension::<Arc<State>>::from_request(req).await?;
 
         let cookie = Option::<TypedHeader<Cookie>>::from_request(req)
             .await
@@ -52,10 +236,72 @@ where
                             .await?;
                         Ok(Auth::KnownUser(user))
                     }
-                    None => Ok(Auth::UnknownUser),
+                    None => Ok(Auth::UnknownUser(Guest::from_request(req).await?)),
                 }
             }
-            None => Ok(Auth::UnknownUser),
+            None => Ok(Auth::UnknownUser(Guest::from_request(req).await?)),
         }
     }
 }
+
+/// Load the resource of entity `E` with the given id, rejecting with `NotFound` if it does not exist.
+pub async fn find_resource<E>(db: &DatabaseConnection, id: Uuid) -> Result<E::Model, MixiniError>
+where
+    E: EntityTrait,
+    Uuid: Into<<E::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+{
+    E::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or(MixiniError::NotFound)
+}
+
+/// A resource of entity `E`, loaded by the `:id` in the request path, that the requester is
+/// authorized to perform action `A` on.
+///
+/// This only works for actions without data of their own (such as `Read` or `Delete`). Actions
+/// that carry data, like changesets, should use `find_resource` and `Auth::authorize` instead.
+pub struct Authorized<A, E: EntityTrait> {
+    /// The requester.
+    pub auth: Auth,
+    /// The resource the requester is authorized for.
+    pub resource: E::Model,
+    action: PhantomData<fn() -> A>,
+}
+
+impl<A, E: EntityTrait> fmt::Debug for Authorized<A, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authorized")
+            .field("auth", &self.auth)
+            .field("resource", &self.resource)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<A, E, B> FromRequest<B> for Authorized<A, E>
+where
+    A: ToPolar + Default + Send,
+    E: EntityTrait,
+    E::Model: ToPolar,
+    Uuid: Into<<E::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+    B: Send,
+{
+    type Rejection = MixiniError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Path(id) = Path::<Uuid>::from_request(req).await?;
+        let Extension(state) = Extension::<Arc<State>>::from_request(req).await?;
+        let auth = Auth::from_request(req).await?;
+
+        let resource = find_resource::<E>(&state.db, id).await?;
+        auth.authorize(&state.oso, A::default(), resource.to_owned())
+            .await?;
+
+        Ok(Authorized {
+            auth,
+            resource,
+            action: PhantomData,
+        })
+    }
+}
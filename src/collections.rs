@@ -0,0 +1,470 @@
+//! Favorites and collections: the ways users save posts.
+//!
+//! Every user has one list of favorites, which only they can see unless they make it public, and
+//! any number of collections, which are named, ordered lists of posts that other users can be
+//! invited to collaborate on. Who may see or change either is decided by `polar/collections.polar`
+//! against the `Favorites` and `CollectionResource` classes here.
+use entity::{
+    collection, collection_collaborator, collection_post, post, post_favorite, prelude::*,
+};
+use oso::{Oso, PolarClass};
+use sea_orm::{
+    entity::*,
+    prelude::*,
+    sea_query::{Expr as SqlExpr, Query, SelectStatement},
+    Condition, ConnectionTrait, DbBackend, QueryOrder, Statement, TransactionTrait,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+use validator::ValidationError;
+
+use crate::{
+    auth::Auth,
+    constants::{MAX_COLLABORATORS, MAX_COLLECTION_POSTS, POSTS_PER_PAGE},
+    error::MixiniError,
+    preferences::{listing_filter, ListingFilter},
+};
+
+/// The favorites of a user, as seen by policies.
+#[derive(Debug, Clone, PolarClass)]
+#[polar(class_name = "Favorites")]
+pub struct Favorites {
+    /// The user whose favorites these are.
+    #[polar(attribute)]
+    pub user_id: Uuid,
+    /// Whether the user has made their favorites public.
+    #[polar(attribute)]
+    pub public: bool,
+}
+
+impl Favorites {
+    /// Load the favorites of `user_id`, which are private for users without preferences.
+    pub async fn load<C: ConnectionTrait>(db: &C, user_id: Uuid) -> Result<Self, DbErr> {
+        let public = UserPreference::find_by_id(user_id)
+            .one(db)
+            .await?
+            .map_or(false, |preference| preference.public_favorites);
+        Ok(Self { user_id, public })
+    }
+}
+
+/// A collection as seen by policies, along with who collaborates on it.
+#[derive(Debug, Clone, PolarClass)]
+#[polar(class_name = "Collection")]
+pub struct CollectionResource {
+    #[polar(attribute)]
+    pub id: Uuid,
+    #[polar(attribute)]
+    pub owner_id: Uuid,
+    #[polar(attribute)]
+    pub is_public: bool,
+    /// The users other than the owner who may edit the collection's posts.
+    #[polar(attribute)]
+    pub collaborator_ids: Vec<Uuid>,
+}
+
+impl CollectionResource {
+    pub fn new(collection: &collection::Model, collaborator_ids: Vec<Uuid>) -> Self {
+        Self {
+            id: collection.id,
+            owner_id: collection.owner_id,
+            is_public: collection.is_public,
+            collaborator_ids,
+        }
+    }
+
+    /// Load the collaborators of `collection` to authorize actions on it.
+    pub async fn load<C: ConnectionTrait>(
+        db: &C,
+        collection: &collection::Model,
+    ) -> Result<Self, DbErr> {
+        let collaborator_ids = CollectionCollaborator::find()
+            .filter(collection_collaborator::Column::CollectionId.eq(collection.id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|collaborator| collaborator.user_id)
+            .collect();
+        Ok(Self::new(collection, collaborator_ids))
+    }
+}
+
+/// Load the collection with the given id along with its resource for policies, rejecting with
+/// `NotFound` if it does not exist.
+pub async fn find_collection(
+    db: &DatabaseConnection,
+    id: Uuid,
+) -> Result<(collection::Model, CollectionResource), MixiniError> {
+    let collection = Collection::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or(MixiniError::NotFound)?;
+    let resource = CollectionResource::load(db, &collection).await?;
+    Ok((collection, resource))
+}
+
+/// A subquery selecting the ids of the posts meeting `condition`, such as a `ListingFilter`'s.
+pub fn post_ids_where(condition: Condition) -> SelectStatement {
+    Query::select()
+        .column(post::Column::Id)
+        .from(Post)
+        .cond_where(condition)
+        .to_owned()
+}
+
+/// A post in someone's favorites or in a collection.
+#[derive(Debug, Serialize)]
+pub struct SavedPost {
+    #[serde(flatten)]
+    pub post: post::Model,
+    /// When the post was favorited or added to the collection.
+    pub saved_at: DateTimeWithTimeZone,
+    /// Whether the post is on the requester's blacklist, see `SearchResult::blacklisted`.
+    pub blacklisted: bool,
+}
+
+/// Load the posts of `saved`, keeping its order.
+async fn saved_posts(
+    db: &DatabaseConnection,
+    filter: &ListingFilter,
+    saved: Vec<(Uuid, DateTimeWithTimeZone)>,
+) -> Result<Vec<SavedPost>, MixiniError> {
+    let ids: Vec<Uuid> = saved.iter().map(|(id, _)| *id).collect();
+    let mut posts: HashMap<Uuid, post::Model> = Post::find()
+        .filter(post::Column::Id.is_in(ids.to_owned()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|post| (post.id, post))
+        .collect();
+    let blacklisted = filter.marked_ids(db, &ids).await?;
+
+    Ok(saved
+        .into_iter()
+        .filter_map(|(id, saved_at)| {
+            Some(SavedPost {
+                post: posts.remove(&id)?,
+                saved_at,
+                blacklisted: blacklisted.contains(&id),
+            })
+        })
+        .collect())
+}
+
+/// Fetch the `page`th page of the favorites of `user_id` that `auth` may read and wants to be
+/// shown, most recently favorited first.
+///
+/// Whether `auth` may see the favorites at all is checked separately, against `Favorites`.
+pub async fn favorite_posts(
+    db: &DatabaseConnection,
+    oso: &Mutex<Oso>,
+    auth: &Auth,
+    user_id: Uuid,
+    page: usize,
+) -> Result<Vec<SavedPost>, MixiniError> {
+    let filter = listing_filter(db, oso, auth).await?;
+    let favorites = PostFavorite::find()
+        .filter(post_favorite::Column::UserId.eq(user_id))
+        .filter(
+            post_favorite::Column::PostId.in_subquery(post_ids_where(filter.condition.to_owned())),
+        )
+        .order_by_desc(post_favorite::Column::CreatedAt)
+        .order_by_asc(post_favorite::Column::PostId)
+        .paginate(db, POSTS_PER_PAGE)
+        .fetch_page(page)
+        .await?;
+
+    saved_posts(
+        db,
+        &filter,
+        favorites
+            .into_iter()
+            .map(|favorite| (favorite.post_id, favorite.created_at))
+            .collect(),
+    )
+    .await
+}
+
+/// Fetch the `page`th page of the posts of `collection_id` that `auth` may read and wants to be
+/// shown, in the collection's order.
+///
+/// Whether `auth` may see the collection at all is checked separately, against its
+/// `CollectionResource`.
+pub async fn collection_posts(
+    db: &DatabaseConnection,
+    oso: &Mutex<Oso>,
+    auth: &Auth,
+    collection_id: Uuid,
+    page: usize,
+) -> Result<Vec<SavedPost>, MixiniError> {
+    let filter = listing_filter(db, oso, auth).await?;
+    let entries = CollectionPost::find()
+        .filter(collection_post::Column::CollectionId.eq(collection_id))
+        .filter(
+            collection_post::Column::PostId
+                .in_subquery(post_ids_where(filter.condition.to_owned())),
+        )
+        .order_by_asc(collection_post::Column::Position)
+        .order_by_asc(collection_post::Column::AddedAt)
+        .order_by_asc(collection_post::Column::PostId)
+        .paginate(db, POSTS_PER_PAGE)
+        .fetch_page(page)
+        .await?;
+
+    saved_posts(
+        db,
+        &filter,
+        entries
+            .into_iter()
+            .map(|entry| (entry.post_id, entry.added_at))
+            .collect(),
+    )
+    .await
+}
+
+/// Add `post_id` to the favorites of `user_id`, doing nothing if it's already there.
+pub async fn add_favorite<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    post_id: Uuid,
+) -> Result<(), DbErr> {
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "INSERT INTO post_favorite (user_id, post_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        vec![user_id.into(), post_id.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Remove `post_id` from the favorites of `user_id`, doing nothing if it isn't there.
+pub async fn remove_favorite<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    post_id: Uuid,
+) -> Result<(), DbErr> {
+    PostFavorite::delete_many()
+        .filter(post_favorite::Column::UserId.eq(user_id))
+        .filter(post_favorite::Column::PostId.eq(post_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Whether `user_id` has favorited `post_id`.
+pub async fn is_favorite<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    post_id: Uuid,
+) -> Result<bool, DbErr> {
+    Ok(PostFavorite::find_by_id((user_id, post_id))
+        .one(db)
+        .await?
+        .is_some())
+}
+
+/// Split whitespace-separated post ids as submitted in forms.
+pub fn parse_post_ids(post_ids: &str) -> Result<Vec<Uuid>, uuid::Error> {
+    post_ids.split_whitespace().map(Uuid::parse_str).collect()
+}
+
+/// Validate whitespace-separated post ids as submitted in forms.
+pub fn validate_post_ids(post_ids: &str) -> Result<(), ValidationError> {
+    match parse_post_ids(post_ids) {
+        Ok(ids) if ids.len() > MAX_COLLECTION_POSTS => {
+            let mut error = ValidationError::new("post_ids");
+            error.message = Some(
+                format!(
+                    "A collection can have at most {} posts",
+                    MAX_COLLECTION_POSTS
+                )
+                .into(),
+            );
+            Err(error)
+        }
+        Ok(_) => Ok(()),
+        Err(_) => {
+            let mut error = ValidationError::new("post_ids");
+            error.message = Some("Must all be valid post ids".into());
+            Err(error)
+        }
+    }
+}
+
+/// Check that `order` lists every post of `current` exactly once, and nothing else.
+pub fn check_order(current: &[Uuid], order: &[Uuid]) -> Result<(), MixiniError> {
+    let mut seen = HashSet::with_capacity(order.len());
+    let complete = order.len() == current.len()
+        && order.iter().all(|id| seen.insert(*id))
+        && current.iter().all(|id| seen.contains(id));
+    if complete {
+        Ok(())
+    } else {
+        Err(MixiniError::Conflict(
+            "The order must list every post of the collection exactly once".to_owned(),
+        ))
+    }
+}
+
+/// The posts of `collection_id` in order.
+async fn ordered_posts<C: ConnectionTrait>(
+    db: &C,
+    collection_id: Uuid,
+) -> Result<Vec<collection_post::Model>, DbErr> {
+    CollectionPost::find()
+        .filter(collection_post::Column::CollectionId.eq(collection_id))
+        .order_by_asc(collection_post::Column::Position)
+        .order_by_asc(collection_post::Column::AddedAt)
+        .all(db)
+        .await
+}
+
+/// Lock the row of `collection_id` until the end of the transaction, so changes to the order of
+/// its posts don't interleave.
+async fn lock_collection<C: ConnectionTrait>(db: &C, collection_id: Uuid) -> Result<(), DbErr> {
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT id FROM collection WHERE id = $1 FOR UPDATE",
+        vec![collection_id.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Add `post_id` to `collection_id` at the zero-indexed `index`, or at the end if not given or
+/// past it. Posts from `index` on move down by one.
+pub async fn add_collection_post(
+    db: &DatabaseConnection,
+    collection_id: Uuid,
+    post_id: Uuid,
+    added_by: Uuid,
+    index: Option<usize>,
+) -> Result<collection_post::Model, MixiniError> {
+    let txn = db.begin().await?;
+    lock_collection(&txn, collection_id).await?;
+
+    let posts = ordered_posts(&txn, collection_id).await?;
+    if posts.iter().any(|existing| existing.post_id == post_id) {
+        return Err(MixiniError::Conflict(
+            "This post is already in the collection".to_owned(),
+        ));
+    }
+    if posts.len() >= MAX_COLLECTION_POSTS {
+        return Err(MixiniError::Conflict(format!(
+            "A collection can have at most {} posts",
+            MAX_COLLECTION_POSTS
+        )));
+    }
+
+    // positions may have gaps where posts were deleted, so `index` is counted in posts
+    let position = match index.and_then(|index| posts.get(index)) {
+        Some(at) => {
+            CollectionPost::update_many()
+                .col_expr(
+                    collection_post::Column::Position,
+                    SqlExpr::col(collection_post::Column::Position).add(1),
+                )
+                .filter(collection_post::Column::CollectionId.eq(collection_id))
+                .filter(collection_post::Column::Position.gte(at.position))
+                .exec(&txn)
+                .await?;
+            at.position
+        }
+        None => posts.last().map_or(0, |last| last.position + 1),
+    };
+
+    let added = collection_post::ActiveModel {
+        collection_id: Set(collection_id),
+        post_id: Set(post_id),
+        position: Set(position),
+        added_by: Set(Some(added_by)),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(added)
+}
+
+/// Remove `post_id` from `collection_id`, rejecting with `NotFound` if it isn't in it.
+pub async fn remove_collection_post(
+    db: &DatabaseConnection,
+    collection_id: Uuid,
+    post_id: Uuid,
+) -> Result<(), MixiniError> {
+    let result = CollectionPost::delete_many()
+        .filter(collection_post::Column::CollectionId.eq(collection_id))
+        .filter(collection_post::Column::PostId.eq(post_id))
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(MixiniError::NotFound);
+    }
+    Ok(())
+}
+
+/// Put the posts of `collection_id` in the order of `order`, which must list each of them once.
+pub async fn reorder_collection_posts(
+    db: &DatabaseConnection,
+    collection_id: Uuid,
+    order: &[Uuid],
+) -> Result<(), MixiniError> {
+    let txn = db.begin().await?;
+    lock_collection(&txn, collection_id).await?;
+
+    let current: Vec<Uuid> = ordered_posts(&txn, collection_id)
+        .await?
+        .into_iter()
+        .map(|existing| existing.post_id)
+        .collect();
+    check_order(&current, order)?;
+
+    for (position, post_id) in order.iter().enumerate() {
+        CollectionPost::update_many()
+            .col_expr(
+                collection_post::Column::Position,
+                SqlExpr::value(position as i32),
+            )
+            .filter(collection_post::Column::CollectionId.eq(collection_id))
+            .filter(collection_post::Column::PostId.eq(*post_id))
+            .exec(&txn)
+            .await?;
+    }
+    txn.commit().await?;
+
+    Ok(())
+}
+
+/// Make `user_id` a collaborator on `collection`.
+pub async fn add_collaborator(
+    db: &DatabaseConnection,
+    collection: &CollectionResource,
+    user_id: Uuid,
+) -> Result<collection_collaborator::Model, MixiniError> {
+    if user_id == collection.owner_id {
+        return Err(MixiniError::Conflict(
+            "The owner of a collection can't also be a collaborator".to_owned(),
+        ));
+    }
+    if collection.collaborator_ids.contains(&user_id) {
+        return Err(MixiniError::Conflict(
+            "This user is already a collaborator".to_owned(),
+        ));
+    }
+    if collection.collaborator_ids.len() >= MAX_COLLABORATORS {
+        return Err(MixiniError::Conflict(format!(
+            "A collection can have at most {} collaborators",
+            MAX_COLLABORATORS
+        )));
+    }
+
+    Ok(collection_collaborator::ActiveModel {
+        collection_id: Set(collection.id),
+        user_id: Set(user_id),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?)
+}
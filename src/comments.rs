@@ -0,0 +1,261 @@
+//! Threaded comments on posts.
+//!
+//! Comments reply either to a post, starting a thread, or to another comment on it, up to
+//! `MAX_COMMENT_DEPTH` deep. Every version of a comment's body is kept as a revision, so edits
+//! can be reviewed. Deleted and hidden comments keep their place in their thread, but their bodies
+//! are only shown to those `polar/comments.polar` lets read them.
+use chrono::Utc;
+use entity::{comment, comment_revision, prelude::*};
+use oso::Oso;
+use sea_orm::{
+    entity::*, prelude::*, ConnectionTrait, DbBackend, QueryOrder, Statement, TransactionTrait,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+use ulid::Ulid;
+
+use crate::{
+    actions::Read,
+    auth::Auth,
+    constants::{COMMENT_THREADS_PER_PAGE, MAX_COMMENT_DEPTH},
+    error::MixiniError,
+    utils::markdown::render_markdown,
+};
+
+/// A comment as shown to someone, without its body or author unless they may read it.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommentResponse {
+    pub id: Uuid,
+    pub created_at: DateTimeWithTimeZone,
+    pub post_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub author_id: Option<Uuid>,
+    /// The markdown as written.
+    pub body: Option<String>,
+    /// The sanitized HTML the body renders to.
+    pub body_html: Option<String>,
+    pub edited_at: Option<DateTimeWithTimeZone>,
+    pub deleted: bool,
+    pub hidden: bool,
+}
+
+impl CommentResponse {
+    /// Show `comment`, with its body and author only if `readable`.
+    pub fn new(comment: comment::Model, readable: bool) -> Self {
+        Self {
+            id: comment.id,
+            created_at: comment.created_at,
+            post_id: comment.post_id,
+            parent_id: comment.parent_id,
+            author_id: readable.then(|| comment.author_id),
+            body: readable.then(|| comment.body),
+            body_html: readable.then(|| comment.body_html),
+            edited_at: comment.edited_at,
+            deleted: comment.deleted_at.is_some(),
+            hidden: comment.hidden_at.is_some(),
+        }
+    }
+
+    /// Show `comment` to `auth`.
+    pub async fn for_auth(
+        oso: &Mutex<Oso>,
+        auth: &Auth,
+        comment: comment::Model,
+    ) -> Result<Self, MixiniError> {
+        let readable = oso
+            .lock()
+            .await
+            .is_allowed(auth.to_owned(), Read, comment.to_owned())?;
+        Ok(Self::new(comment, readable))
+    }
+}
+
+/// A comment and the replies to it, oldest first.
+#[derive(Debug, Serialize)]
+pub struct CommentThread {
+    #[serde(flatten)]
+    pub comment: CommentResponse,
+    pub replies: Vec<CommentThread>,
+}
+
+/// Nest `comments` into threads, keeping their order among siblings.
+///
+/// Comments whose parent isn't among `comments` start threads of their own.
+pub fn build_threads(comments: Vec<CommentResponse>) -> Vec<CommentThread> {
+    fn attach(
+        comment: CommentResponse,
+        replies: &mut HashMap<Uuid, Vec<CommentResponse>>,
+    ) -> CommentThread {
+        let children = replies.remove(&comment.id).unwrap_or_default();
+        CommentThread {
+            replies: children
+                .into_iter()
+                .map(|child| attach(child, replies))
+                .collect(),
+            comment,
+        }
+    }
+
+    let ids: HashSet<Uuid> = comments.iter().map(|comment| comment.id).collect();
+    let mut roots = Vec::new();
+    let mut replies: HashMap<Uuid, Vec<CommentResponse>> = HashMap::new();
+    for comment in comments {
+        match comment
+            .parent_id
+            .filter(|parent_id| ids.contains(parent_id))
+        {
+            Some(parent_id) => replies.entry(parent_id).or_default().push(comment),
+            None => roots.push(comment),
+        }
+    }
+
+    roots
+        .into_iter()
+        .map(|root| attach(root, &mut replies))
+        .collect()
+}
+
+/// Fetch the `page`th page of the threads on `post_id` as shown to `auth`, oldest first.
+///
+/// Whether `auth` may read the post is checked separately.
+pub async fn list_threads(
+    db: &DatabaseConnection,
+    oso: &Mutex<Oso>,
+    auth: &Auth,
+    post_id: Uuid,
+    page: usize,
+) -> Result<Vec<CommentThread>, MixiniError> {
+    let mut comments = Comment::find()
+        .filter(comment::Column::PostId.eq(post_id))
+        .filter(comment::Column::ParentId.is_null())
+        .order_by_asc(comment::Column::CreatedAt)
+        .order_by_asc(comment::Column::Id)
+        .paginate(db, COMMENT_THREADS_PER_PAGE)
+        .fetch_page(page)
+        .await?;
+
+    // replies are at most `MAX_COMMENT_DEPTH` deep, so this takes as many queries at most
+    let mut frontier: Vec<Uuid> = comments.iter().map(|comment| comment.id).collect();
+    while !frontier.is_empty() {
+        let replies = Comment::find()
+            .filter(comment::Column::ParentId.is_in(frontier))
+            .order_by_asc(comment::Column::CreatedAt)
+            .order_by_asc(comment::Column::Id)
+            .all(db)
+            .await?;
+        frontier = replies.iter().map(|reply| reply.id).collect();
+        comments.extend(replies);
+    }
+
+    let mut shown = Vec::with_capacity(comments.len());
+    for comment in comments {
+        shown.push(CommentResponse::for_auth(oso, auth, comment).await?);
+    }
+    Ok(build_threads(shown))
+}
+
+/// Comment on `post_id` as `author_id`, replying to `parent` if given.
+pub async fn create_comment(
+    db: &DatabaseConnection,
+    post_id: Uuid,
+    author_id: Uuid,
+    parent: Option<&comment::Model>,
+    body: String,
+) -> Result<comment::Model, MixiniError> {
+    let depth = match parent {
+        Some(parent) if parent.post_id != post_id => {
+            return Err(MixiniError::Conflict(
+                "Replies must be on the same post as the comment they reply to".to_owned(),
+            ))
+        }
+        Some(parent) if parent.depth >= MAX_COMMENT_DEPTH => {
+            return Err(MixiniError::Conflict(format!(
+                "Replies can be nested at most {} deep",
+                MAX_COMMENT_DEPTH
+            )))
+        }
+        Some(parent) => parent.depth + 1,
+        None => 0,
+    };
+
+    let txn = db.begin().await?;
+    let comment = comment::ActiveModel {
+        id: Set(Uuid::from(Ulid::new())),
+        post_id: Set(post_id),
+        author_id: Set(author_id),
+        parent_id: Set(parent.map(|parent| parent.id)),
+        depth: Set(depth),
+        body_html: Set(render_markdown(&body)),
+        body: Set(body.to_owned()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    comment_revision::ActiveModel {
+        comment_id: Set(comment.id),
+        version: Set(1),
+        editor_id: Set(Some(author_id)),
+        body: Set(body),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(comment)
+}
+
+/// Replace the body of `comment` as `editor_id`, keeping the previous one as a revision.
+pub async fn edit_comment(
+    db: &DatabaseConnection,
+    comment: comment::Model,
+    editor_id: Uuid,
+    body: String,
+) -> Result<comment::Model, MixiniError> {
+    let txn = db.begin().await?;
+    // serialize edits of the same comment so they get distinct versions
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT id FROM comment WHERE id = $1 FOR UPDATE",
+        vec![comment.id.into()],
+    ))
+    .await?;
+
+    let version = CommentRevision::find()
+        .filter(comment_revision::Column::CommentId.eq(comment.id))
+        .order_by_desc(comment_revision::Column::Version)
+        .one(&txn)
+        .await?
+        .map_or(1, |latest| latest.version + 1);
+    comment_revision::ActiveModel {
+        comment_id: Set(comment.id),
+        version: Set(version),
+        editor_id: Set(Some(editor_id)),
+        body: Set(body.to_owned()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    let mut comment: comment::ActiveModel = comment.into();
+    comment.body_html = Set(render_markdown(&body));
+    comment.body = Set(body);
+    comment.edited_at = Set(Some(Utc::now().into()));
+    let comment = comment.update(&txn).await?;
+    txn.commit().await?;
+
+    Ok(comment)
+}
+
+/// Every version of the body of `comment_id`, oldest first.
+pub async fn comment_history<C: ConnectionTrait>(
+    db: &C,
+    comment_id: Uuid,
+) -> Result<Vec<comment_revision::Model>, DbErr> {
+    CommentRevision::find()
+        .filter(comment_revision::Column::CommentId.eq(comment_id))
+        .order_by_asc(comment_revision::Column::Version)
+        .all(db)
+        .await
+}
@@ -7,6 +7,8 @@ lazy_static! {
     pub static ref DOMAIN: String = std::env::var("DOMAIN").expect("DOMAIN is not set in env");
     pub static ref RE_USERNAME: Regex = Regex::new(r"^[a-zA-Z0-9\.\-_]+$").unwrap();
     pub static ref RE_PASSWORD: Regex = Regex::new(r"^[a-zA-Z0-9]*[0-9][a-zA-Z0-9]*$").unwrap();
+    pub static ref RE_PERMISSION: Regex = Regex::new(r"^[a-z_]+(\.[a-z_]+)*$").unwrap();
+    pub static ref RE_TAG_NAME: Regex = Regex::new(r"^[a-z0-9_.'!][a-z0-9_\-.'!()]*$").unwrap();
 }
 
 // for authorized sessions
@@ -14,6 +16,66 @@ pub const SESSION_COOKIE_NAME: &str = "msessid";
 pub const SESSION_KEY_PREFIX: &str = "session:";
 pub const SESSION_DURATION_SECS: usize = 1209600;
 
+// for identifying guests
+pub const CLIENT_HEADER_NAME: &str = "x-mixini-client";
+pub const FORWARDED_FOR_HEADER_NAME: &str = "x-forwarded-for";
+pub const AGE_GATE_COOKIE_NAME: &str = "mage";
+
+// for permission grants
+pub const PERMISSION_REFRESH_SECS: u64 = 60;
+
+// for listings
+pub const USERS_PER_PAGE: usize = 50;
+pub const POSTS_PER_PAGE: usize = 40;
+pub const COLLECTIONS_PER_PAGE: usize = 50;
+pub const COMMENT_THREADS_PER_PAGE: usize = 20;
+pub const REVISIONS_PER_PAGE: usize = 50;
+
+// for collections
+pub const MAX_COLLECTION_POSTS: usize = 10000;
+pub const MAX_COLLABORATORS: usize = 50;
+
+// for comments
+pub const MAX_COMMENT_DEPTH: i32 = 8;
+
+// for preferences
+pub const MAX_BLACKLIST_ENTRIES: usize = 100;
+pub const MAX_BLACKLIST_ENTRY_TERMS: usize = 10;
+
+// for searches
+pub const GUEST_MAX_SEARCH_TERMS: usize = 4;
+pub const MAX_SEARCH_TEXT_CHARS: usize = 256;
+pub const MAX_SEARCH_NESTING: usize = 32;
+
+// for source importers
+pub const IMPORTER_USER_AGENT: &str = concat!("mixini-server/", env!("CARGO_PKG_VERSION"));
+pub const IMPORT_TIMEOUT_SECS: u64 = 10;
+pub const IMPORT_MAX_RESPONSE_BYTES: usize = 2 * 1024 * 1024;
+pub const IMPORT_MAX_REDIRECTS: usize = 5;
+
+// for media storage
+pub const MEDIA_STORE_CONNECT_TIMEOUT_SECS: u64 = 10;
+pub const MEDIA_CACHE_MAX_AGE_SECS: u64 = 365 * 24 * 60 * 60;
+
+// for media metadata
+pub const MAX_EMBEDDED_TEXT_CHARS: usize = 256;
+
+// for media previews
+pub const THUMBNAIL_SIZE: u32 = 150;
+pub const SAMPLE_SIZE: u32 = 850;
+pub const PREVIEW_JPEG_QUALITY: u8 = 85;
+pub const PREVIEW_WEBP_QUALITY: u8 = 80;
+
+// for similar images
+pub const SIMILAR_DEFAULT_DISTANCE: u32 = 8;
+pub const SIMILAR_MAX_RESULTS: usize = 40;
+pub const SIMILARITY_REFRESH_SECS: u64 = 300;
+
+// for uploads
+pub const MAX_UPLOAD_BYTES: u64 = 50 * 1024 * 1024;
+pub const MAX_UPLOAD_FIELD_BYTES: usize = 64 * 1024;
+pub const MAX_IGNORED_UPLOAD_FIELDS: usize = 8;
+
 // for user verify requests
 pub const VERIFY_KEY_PREFIX: &str = "verify:";
 pub const VERIFY_EXPIRY_SECONDS: usize = 86400;
@@ -1,9 +1,13 @@
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
+    Json,
 };
+use serde_json::json;
 use thiserror::Error;
 
+use crate::{importers::ImportError, media::StoreError};
+
 // dost thou know of the pepeloni
 const INTERNAL_SERVER_ERROR_MESSAGE: &str = "ahh the pepeloni";
 
@@ -13,6 +17,60 @@ pub enum MixiniError {
     #[error(transparent)]
     AxumFormRejection(#[from] axum::extract::rejection::FormRejection),
 
+    #[error(transparent)]
+    AxumPathRejection(#[from] axum::extract::rejection::PathRejection),
+
+    #[error(transparent)]
+    AxumExtensionRejection(#[from] axum::extract::rejection::ExtensionRejection),
+
+    #[error(transparent)]
+    AxumMultipartRejection(#[from] axum::extract::rejection::MultipartRejection),
+
+    #[error(transparent)]
+    AxumMultipartError(#[from] axum::extract::multipart::MultipartError),
+
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+
+    #[error("Upload is too large")]
+    PayloadTooLarge,
+
+    #[error("Only GIF, JPEG, PNG and WebP images are supported")]
+    UnsupportedMediaType,
+
+    #[error("This file was already uploaded")]
+    DuplicateMedia(uuid::Uuid),
+
+    #[error("Must be logged in to do this")]
+    Unauthorized,
+
+    #[error("Not allowed to do this")]
+    Forbidden,
+
+    #[error("Not allowed to set fields: {}", .0.join(", "))]
+    ForbiddenFields(Vec<String>),
+
+    #[error("Resource not found")]
+    NotFound,
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error(transparent)]
+    SearchError(#[from] crate::search::ParseError),
+
+    #[error(transparent)]
+    ImportError(#[from] ImportError),
+
+    #[error(transparent)]
+    StoreError(#[from] StoreError),
+
+    #[error(transparent)]
+    ImageError(#[from] image::ImageError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
     #[error(transparent)]
     JsonError(#[from] serde_json::Error),
 
@@ -42,6 +100,81 @@ impl IntoResponse for MixiniError {
     fn into_response(self) -> Response {
         match self {
             MixiniError::AxumFormRejection(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            MixiniError::AxumPathRejection(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            MixiniError::AxumExtensionRejection(e) => {
+                tracing::debug!("Extension rejection occurred: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    INTERNAL_SERVER_ERROR_MESSAGE.into(),
+                )
+            }
+            MixiniError::AxumMultipartRejection(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            MixiniError::AxumMultipartError(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            MixiniError::InvalidUpload(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            MixiniError::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            MixiniError::UnsupportedMediaType => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string())
+            }
+            MixiniError::DuplicateMedia(id) => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(json!({ "error": self.to_string(), "duplicate_of": id })),
+                )
+                    .into_response()
+            }
+            MixiniError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
+            MixiniError::Forbidden => (StatusCode::FORBIDDEN, self.to_string()),
+            MixiniError::ForbiddenFields(fields) => {
+                return (
+                    StatusCode::FORBIDDEN,
+                    Json(json!({ "forbidden_fields": fields })),
+                )
+                    .into_response()
+            }
+            MixiniError::NotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            MixiniError::Conflict(_) => (StatusCode::CONFLICT, self.to_string()),
+            MixiniError::SearchError(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": e.message, "position": e.position })),
+                )
+                    .into_response()
+            }
+            MixiniError::ImportError(ref e) => match e {
+                ImportError::UnsupportedUrl | ImportError::ForbiddenHost => {
+                    (StatusCode::BAD_REQUEST, self.to_string())
+                }
+                ImportError::Http(e) => {
+                    tracing::debug!("Import error occurred: {:?}", e);
+                    (StatusCode::BAD_GATEWAY, self.to_string())
+                }
+                _ => (StatusCode::BAD_GATEWAY, self.to_string()),
+            },
+            MixiniError::StoreError(ref e) => match e {
+                StoreError::NotFound => (StatusCode::NOT_FOUND, self.to_string()),
+                StoreError::InvalidRange => (StatusCode::RANGE_NOT_SATISFIABLE, self.to_string()),
+                _ => {
+                    tracing::debug!("Media store error occurred: {:?}", e);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        INTERNAL_SERVER_ERROR_MESSAGE.into(),
+                    )
+                }
+            },
+            MixiniError::ImageError(e) => {
+                tracing::debug!("Image error occurred: {:?}", e);
+                (
+                    StatusCode::BAD_REQUEST,
+                    "The image could not be read".to_owned(),
+                )
+            }
+            MixiniError::IoError(e) => {
+                tracing::debug!("IO error occurred: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    INTERNAL_SERVER_ERROR_MESSAGE.into(),
+                )
+            }
             MixiniError::JsonError(e) => {
                 tracing::debug!("Json error occurred: {:?}", e);
                 (
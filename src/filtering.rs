@@ -0,0 +1,181 @@
+//! Data filtering: turning policy decisions into database queries.
+//!
+//! Listing records can't authorize each row with `is_allowed`, so instead policies describe which
+//! records an actor may see with an `allow_filter` rule:
+//!
+//! ```polar
+//! allow_filter(actor, action, resource_type: String, filters: List) if ...;
+//! ```
+//!
+//! Every `Filter` in `filters` must hold for a record to be visible, and every result of the rule
+//! is an alternative, so the rule as a whole compiles to an `OR` of `AND`s. An empty `filters`
+//! list allows every record, and no results at all allows none.
+use anyhow::format_err;
+use entity::{
+    collection, collection_collaborator, post,
+    prelude::CollectionCollaborator,
+    sea_orm_active_enums::{PostRating, PostStatus, UserRole},
+    user_account,
+};
+use oso::{FromPolar, Oso, PolarClass, PolarValue, ToPolar};
+use sea_orm::{
+    sea_query::{Expr, Query},
+    ColumnTrait, Condition, EntityTrait,
+};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{auth::Auth, error::MixiniError};
+
+/// A single condition on a field, constructed in policies with `new Filter(field, op, value)`.
+///
+/// Supported operators are `=`, `!=`, `in` and `not in`; the latter two expect a list value.
+#[derive(Debug, Clone, PolarClass)]
+pub struct Filter {
+    #[polar(attribute)]
+    pub field: String,
+    #[polar(attribute)]
+    pub op: String,
+    pub value: PolarValue,
+}
+
+impl Filter {
+    pub fn new(field: String, op: String, value: PolarValue) -> Self {
+        Self { field, op, value }
+    }
+
+    /// Compile this filter into a condition on `column`, converting its value(s) to `T`.
+    pub fn condition<C, T>(&self, column: C) -> Result<Condition, MixiniError>
+    where
+        C: ColumnTrait,
+        T: FromPolar + Into<sea_orm::Value>,
+    {
+        let value = self.value.to_owned();
+        let expr = match self.op.as_str() {
+            "=" => column.eq(T::from_polar(value)?),
+            "!=" => column.ne(T::from_polar(value)?),
+            "in" => column.is_in(Vec::<T>::from_polar(value)?),
+            "not in" => column.is_not_in(Vec::<T>::from_polar(value)?),
+            op => {
+                return Err(format_err!("Unsupported filter operator `{}`", op).into());
+            }
+        };
+        Ok(Condition::all().add(expr))
+    }
+}
+
+/// An entity whose records can be filtered by `allow_filter` rules.
+pub trait PolarFilterable: EntityTrait {
+    /// The resource type this entity goes by in `allow_filter` rules.
+    const RESOURCE_TYPE: &'static str;
+
+    /// Compile a single filter into a condition on this entity.
+    fn filter_condition(filter: &Filter) -> Result<Condition, MixiniError>;
+}
+
+/// Build the condition limiting `E` to the records `auth` may perform `action` on.
+///
+/// Apply it with `E::find().filter(condition)`.
+pub async fn authorized_condition<E, A>(
+    oso: &Mutex<Oso>,
+    auth: &Auth,
+    action: A,
+) -> Result<Condition, MixiniError>
+where
+    E: PolarFilterable,
+    A: ToPolar,
+{
+    let oso = oso.lock().await;
+    let query = oso.query_rule(
+        "allow_filter",
+        (
+            auth.to_owned(),
+            action,
+            E::RESOURCE_TYPE,
+            PolarValue::Variable("filters".to_owned()),
+        ),
+    )?;
+
+    let mut alternatives = Vec::new();
+    for result in query {
+        let filters: Vec<Filter> = result?.get_typed("filters")?;
+        if filters.is_empty() {
+            // this alternative is unconditional, so nothing else matters
+            return Ok(Condition::all());
+        }
+
+        let mut condition = Condition::all();
+        for filter in &filters {
+            condition = condition.add(E::filter_condition(filter)?);
+        }
+        alternatives.push(condition);
+    }
+
+    if alternatives.is_empty() {
+        return Ok(Condition::all().add(Expr::cust("FALSE")));
+    }
+
+    Ok(alternatives
+        .into_iter()
+        .fold(Condition::any(), |any, condition| any.add(condition)))
+}
+
+/// Error for filters on fields an entity doesn't expose to filtering.
+pub fn unsupported_field(filter: &Filter) -> MixiniError {
+    format_err!("Filtering on field `{}` is not supported", filter.field).into()
+}
+
+impl PolarFilterable for user_account::Entity {
+    const RESOURCE_TYPE: &'static str = "User";
+
+    fn filter_condition(filter: &Filter) -> Result<Condition, MixiniError> {
+        match filter.field.as_str() {
+            "id" => filter.condition::<_, Uuid>(user_account::Column::Id),
+            "name" => filter.condition::<_, String>(user_account::Column::Name),
+            "email" => filter.condition::<_, String>(user_account::Column::Email),
+            "role" => filter.condition::<_, UserRole>(user_account::Column::Role),
+            "verified" => filter.condition::<_, bool>(user_account::Column::Verified),
+            _ => Err(unsupported_field(filter)),
+        }
+    }
+}
+
+impl PolarFilterable for post::Entity {
+    const RESOURCE_TYPE: &'static str = "Post";
+
+    fn filter_condition(filter: &Filter) -> Result<Condition, MixiniError> {
+        match filter.field.as_str() {
+            "id" => filter.condition::<_, Uuid>(post::Column::Id),
+            "uploader_id" => filter.condition::<_, Uuid>(post::Column::UploaderId),
+            "rating" => filter.condition::<_, PostRating>(post::Column::Rating),
+            "status" => filter.condition::<_, PostStatus>(post::Column::Status),
+            _ => Err(unsupported_field(filter)),
+        }
+    }
+}
+
+impl PolarFilterable for collection::Entity {
+    const RESOURCE_TYPE: &'static str = "Collection";
+
+    fn filter_condition(filter: &Filter) -> Result<Condition, MixiniError> {
+        match filter.field.as_str() {
+            "id" => filter.condition::<_, Uuid>(collection::Column::Id),
+            "owner_id" => filter.condition::<_, Uuid>(collection::Column::OwnerId),
+            "is_public" => filter.condition::<_, bool>(collection::Column::IsPublic),
+            // matches collections the user is one of the collaborators of
+            "collaborator_id" if filter.op == "=" => {
+                let user_id = Uuid::from_polar(filter.value.to_owned())?;
+                Ok(Condition::all().add(
+                    collection::Column::Id.in_subquery(
+                        Query::select()
+                            .column(collection_collaborator::Column::CollectionId)
+                            .from(CollectionCollaborator)
+                            .and_where(collection_collaborator::Column::UserId.eq(user_id))
+                            .to_owned(),
+                    ),
+                ))
+            }
+            _ => Err(unsupported_field(filter)),
+        }
+    }
+}
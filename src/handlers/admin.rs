@@ -0,0 +1,248 @@
+use axum::{
+    body::Body,
+    extract::{Extension, Path, Query},
+    http::{Response, StatusCode},
+};
+use entity::{permission_grant, prelude::*, sea_orm_active_enums::UserRole};
+use oso::ToPolar;
+use sea_orm::{entity::*, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc};
+use ulid::Ulid;
+use validator::{Validate, ValidationError};
+
+use crate::{
+    actions::{Delete, Explain, Grant, Read, Reload, Revoke, UpdateUser},
+    auth::{find_resource, Auth},
+    constants::RE_PERMISSION,
+    error::MixiniError,
+    handlers::ValidatedForm,
+    policy::{explain_rules, try_reload_oso, Policy, RuleExplanation},
+    server::State,
+};
+
+/// The form input for `POST /admin/permission`
+#[derive(Debug, Validate, Deserialize)]
+#[validate(schema(function = "validate_grant_scope"))]
+pub struct GrantForm {
+    /// The user receiving the permission.
+    pub user_id: Uuid,
+    /// The permission, as checked by `has_permission` in policies.
+    #[validate(
+        length(
+            min = 1,
+            max = 64,
+            message = "Minimum length is 1 character, maximum is 64"
+        ),
+        regex(
+            path = "RE_PERMISSION",
+            message = "Can only contain lowercase letters and underscores, separated by periods (.)"
+        )
+    )]
+    pub permission: String,
+    /// The type of resource the permission is limited to, if any.
+    pub resource_type: Option<String>,
+    /// The resource the permission is limited to, if any.
+    pub resource_id: Option<Uuid>,
+    /// When the permission lapses. Never, if not provided.
+    pub expires_at: Option<DateTimeWithTimeZone>,
+}
+
+/// The actions `GET /admin/explain` can explain.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplainAction {
+    Read,
+    Update,
+    Delete,
+}
+
+/// The query parameters for `GET /admin/explain`
+///
+/// For `update`, the changeset is given by `name`, `email` and `role`.
+#[derive(Debug, Deserialize)]
+pub struct ExplainQuery {
+    /// The user performing the action.
+    pub actor: Uuid,
+    pub action: ExplainAction,
+    /// The user the action is performed on.
+    pub resource: Uuid,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub role: Option<UserRole>,
+}
+
+/// The response for `GET /admin/explain`
+#[derive(Debug, Serialize)]
+pub struct ExplainResponse {
+    /// Whether the action would be allowed.
+    pub decision: bool,
+    /// The fields of the resource the actor is authorized for with this action.
+    pub authorized_fields: Vec<String>,
+    /// For `update`, the fields of the changeset the actor may not set.
+    pub forbidden_fields: Vec<String>,
+    /// How each authorization rule evaluated on its own.
+    pub rules: Vec<RuleExplanation>,
+}
+
+fn validate_grant_scope(form: &GrantForm) -> Result<(), ValidationError> {
+    if form.resource_type.is_some() == form.resource_id.is_some() {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("scope");
+        error.message = Some("resource_type and resource_id must be provided together".into());
+        Err(error)
+    }
+}
+
+/// Handler for `POST /admin/policy/reload`
+pub async fn reload_policy(
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Reload, Policy).await?;
+
+    match try_reload_oso(&state.oso, &state.policy_rules, &state.grants).await {
+        Ok(()) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::empty())
+            .unwrap()),
+        Err(e) => {
+            tracing::error!("Failed to reload polar policy, keeping previous: {:?}", e);
+            Ok(Response::builder()
+                .status(StatusCode::UNPROCESSABLE_ENTITY)
+                .body(Body::from(format!("Policy failed to load: {}", e)))
+                .unwrap())
+        }
+    }
+}
+
+/// Handler for `POST /admin/permission`
+pub async fn grant_permission(
+    ValidatedForm(grant): ValidatedForm<GrantForm>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let user = find_resource::<UserAccount>(&state.db, grant.user_id).await?;
+    auth.authorize(&state.oso, Grant, user).await?;
+
+    let granted_by = match &auth {
+        Auth::KnownUser(this_user) => Some(this_user.id),
+        Auth::UnknownUser(_) => None,
+    };
+    let new_grant = permission_grant::ActiveModel {
+        id: Set(Uuid::from(Ulid::new())),
+        user_id: Set(grant.user_id),
+        permission: Set(grant.permission),
+        resource_type: Set(grant.resource_type),
+        resource_id: Set(grant.resource_id),
+        expires_at: Set(grant.expires_at),
+        granted_by: Set(granted_by),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+    state.grants.refresh(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&new_grant)?))
+        .unwrap())
+}
+
+/// Handler for `DELETE /admin/permission/:id`
+pub async fn revoke_permission(
+    Path(id): Path<Uuid>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let grant = find_resource::<PermissionGrant>(&state.db, id).await?;
+    let user = find_resource::<UserAccount>(&state.db, grant.user_id).await?;
+    auth.authorize(&state.oso, Revoke, user).await?;
+
+    grant.delete(&state.db).await?;
+    state.grants.refresh(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `GET /admin/explain`
+pub async fn explain(
+    Query(query): Query<ExplainQuery>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Explain, Policy).await?;
+
+    let actor = find_resource::<UserAccount>(&state.db, query.actor).await?;
+    let resource = find_resource::<UserAccount>(&state.db, query.resource).await?;
+
+    let (action, requested) = match query.action {
+        ExplainAction::Read => (Read.to_polar(), HashSet::new()),
+        ExplainAction::Delete => (Delete.to_polar(), HashSet::new()),
+        ExplainAction::Update => {
+            let update = UpdateUser {
+                name: query.name,
+                email: query.email,
+                role: query.role,
+            };
+            let requested = update.fields();
+            (update.to_polar(), requested)
+        }
+    };
+
+    let (decision, mut authorized_fields, mut forbidden_fields) = {
+        let oso = state.oso.lock().await;
+        let authorized: HashSet<String> =
+            oso.authorized_fields(actor.to_owned(), action.to_owned(), resource.to_owned())?;
+        let forbidden: Vec<String> = requested.difference(&authorized).cloned().collect();
+        let decision = match query.action {
+            ExplainAction::Read => !authorized.is_empty(),
+            // changesets that set nothing are rejected before they're authorized
+            ExplainAction::Update => !requested.is_empty() && forbidden.is_empty(),
+            ExplainAction::Delete => {
+                oso.is_allowed(actor.to_owned(), action.to_owned(), resource.to_owned())?
+            }
+        };
+        (
+            decision,
+            authorized.into_iter().collect::<Vec<_>>(),
+            forbidden,
+        )
+    };
+    authorized_fields.sort_unstable();
+    forbidden_fields.sort_unstable();
+
+    let loaded_rules = state
+        .policy_rules
+        .read()
+        .expect("policy rules lock poisoned")
+        .to_owned();
+    let grants = state.grants.clone();
+    let rules = tokio::task::spawn_blocking(move || {
+        explain_rules(
+            &loaded_rules,
+            &grants,
+            actor.to_polar(),
+            action,
+            resource.to_polar(),
+        )
+    })
+    .await
+    .map_err(anyhow::Error::from)??;
+
+    let res_body = ExplainResponse {
+        decision,
+        authorized_fields,
+        forbidden_fields,
+        rules,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
+}
@@ -0,0 +1,450 @@
+use axum::{
+    body::Body,
+    extract::{Extension, Path, Query},
+    http::{Response, StatusCode},
+};
+use entity::{collection, collection_collaborator, prelude::*, user_account};
+use sea_orm::{entity::*, prelude::*, query::*};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use ulid::Ulid;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    actions::{
+        Create, Delete, EditCollectionPosts, Favorite, ManageCollaborators, Read, UpdateCollection,
+    },
+    auth::{find_resource, Auth, Authorized},
+    collections::{
+        add_collaborator, add_collection_post, add_favorite, collection_posts, favorite_posts,
+        find_collection, is_favorite, parse_post_ids, remove_collection_post, remove_favorite,
+        reorder_collection_posts, validate_post_ids, Favorites,
+    },
+    constants::COLLECTIONS_PER_PAGE,
+    error::MixiniError,
+    filtering::authorized_condition,
+    handlers::ValidatedForm,
+    server::State,
+};
+
+/// The query parameters for listings of favorites and collections
+#[derive(Debug, Deserialize)]
+pub struct ListSavedQuery {
+    /// The zero-indexed page to fetch.
+    #[serde(default)]
+    pub page: usize,
+}
+
+/// The response for `GET`, `PUT` and `DELETE /post/:id/favorite`
+#[derive(Debug, Serialize)]
+pub struct FavoriteResponse {
+    /// Whether the requester has favorited the post.
+    pub favorited: bool,
+    /// How many users have favorited the post.
+    pub favorite_count: i32,
+}
+
+/// The form input for `POST /collection`
+#[derive(Debug, Validate, Deserialize)]
+pub struct CreateCollection {
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "Minimum length is 1 character, maximum is 128"
+    ))]
+    pub name: String,
+    #[validate(length(max = 10000, message = "Maximum length is 10000 characters"))]
+    #[serde(default)]
+    pub description: String,
+    /// Whether anyone can see the collection. Defaults to private.
+    #[serde(default)]
+    pub is_public: bool,
+}
+
+/// The form input for `POST /collection/:id/posts`
+#[derive(Debug, Validate, Deserialize)]
+pub struct AddCollectionPost {
+    pub post_id: Uuid,
+    /// The zero-indexed place to add the post at. Defaults to the end.
+    pub index: Option<usize>,
+}
+
+/// The form input for `PUT /collection/:id/posts`
+#[derive(Debug, Validate, Deserialize)]
+pub struct ReorderCollectionPosts {
+    /// Every post of the collection in the new order, separated by whitespace.
+    #[validate(custom = "validate_post_ids")]
+    pub post_ids: String,
+}
+
+/// The form input for `POST /collection/:id/collaborator`
+#[derive(Debug, Validate, Deserialize)]
+pub struct AddCollaborator {
+    /// The name of the user to invite.
+    pub name: String,
+}
+
+/// A collaborator of a collection.
+#[derive(Debug, Serialize)]
+pub struct CollaboratorResponse {
+    pub id: Uuid,
+    pub name: String,
+}
+
+/// The response for `GET /collection/:id`
+#[derive(Debug, Serialize)]
+pub struct GetCollectionResponse {
+    #[serde(flatten)]
+    pub collection: collection::Model,
+    pub collaborators: Vec<CollaboratorResponse>,
+}
+
+/// The favorite state of `post_id` for `user_id`.
+async fn favorite_response(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    post_id: Uuid,
+) -> Result<Response<Body>, MixiniError> {
+    let post = find_resource::<Post>(db, post_id).await?;
+    let res_body = FavoriteResponse {
+        favorited: is_favorite(db, user_id, post_id).await?,
+        favorite_count: post.favorite_count,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
+}
+
+/// Handler for `GET /post/:id/favorite`
+pub async fn get_favorite(
+    authorized: Authorized<Read, Post>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let user_id = match authorized.auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    favorite_response(&state.db, user_id, authorized.resource.id).await
+}
+
+/// Handler for `PUT /post/:id/favorite`
+///
+/// Favoriting a post again does nothing.
+pub async fn favorite_post(
+    authorized: Authorized<Favorite, Post>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let user_id = match authorized.auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    add_favorite(&state.db, user_id, authorized.resource.id).await?;
+    favorite_response(&state.db, user_id, authorized.resource.id).await
+}
+
+/// Handler for `DELETE /post/:id/favorite`
+///
+/// Users can always unfavorite a post, even one they can no longer read.
+pub async fn unfavorite_post(
+    Path(id): Path<Uuid>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let user_id = match auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    remove_favorite(&state.db, user_id, id).await?;
+    favorite_response(&state.db, user_id, id).await
+}
+
+/// Handler for `GET /user/:id/favorites`
+pub async fn list_favorites(
+    Path(id): Path<Uuid>,
+    Query(query): Query<ListSavedQuery>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let user = find_resource::<UserAccount>(&state.db, id).await?;
+    let favorites = Favorites::load(&state.db, user.id).await?;
+    auth.authorize(&state.oso, Read, favorites).await?;
+
+    let posts = favorite_posts(&state.db, &state.oso, &auth, user.id, query.page).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&posts)?))
+        .unwrap())
+}
+
+/// Handler for `GET /user/:id/collections`
+///
+/// Lists the collections the user owns that the requester may read.
+pub async fn list_user_collections(
+    Path(id): Path<Uuid>,
+    Query(query): Query<ListSavedQuery>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let user = find_resource::<UserAccount>(&state.db, id).await?;
+    let condition = authorized_condition::<Collection, _>(&state.oso, &auth, Read).await?;
+    let collections = Collection::find()
+        .filter(collection::Column::OwnerId.eq(user.id))
+        .filter(condition)
+        .order_by_desc(collection::Column::UpdatedAt)
+        .order_by_asc(collection::Column::Id)
+        .paginate(&state.db, COLLECTIONS_PER_PAGE)
+        .fetch_page(query.page)
+        .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&collections)?))
+        .unwrap())
+}
+
+/// Handler for `POST /collection`
+pub async fn create_collection(
+    ValidatedForm(create_collection): ValidatedForm<CreateCollection>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Create, "Collection").await?;
+    let owner_id = match auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let new_collection = collection::ActiveModel {
+        id: Set(Uuid::from(Ulid::new())),
+        owner_id: Set(owner_id),
+        name: Set(create_collection.name),
+        description: Set(create_collection.description),
+        is_public: Set(create_collection.is_public),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&new_collection)?))
+        .unwrap())
+}
+
+/// Handler for `GET /collection/:id`
+pub async fn get_collection(
+    Path(id): Path<Uuid>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (collection, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, Read, resource.to_owned())
+        .await?;
+
+    let collaborators = UserAccount::find()
+        .filter(user_account::Column::Id.is_in(resource.collaborator_ids))
+        .order_by_asc(user_account::Column::Name)
+        .all(&state.db)
+        .await?
+        .into_iter()
+        .map(|user| CollaboratorResponse {
+            id: user.id,
+            name: user.name,
+        })
+        .collect();
+    let res_body = GetCollectionResponse {
+        collection,
+        collaborators,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
+}
+
+/// Handler for `PUT /collection/:id`
+pub async fn update_collection(
+    Path(id): Path<Uuid>,
+    ValidatedForm(update_collection): ValidatedForm<UpdateCollection>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (collection, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, update_collection.to_owned(), resource)
+        .await?;
+
+    let mut collection: collection::ActiveModel = collection.into();
+    if let Some(name) = update_collection.name {
+        collection.name = Set(name);
+    }
+    if let Some(description) = update_collection.description {
+        collection.description = Set(description);
+    }
+    if let Some(is_public) = update_collection.is_public {
+        collection.is_public = Set(is_public);
+    }
+    collection.update(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `DELETE /collection/:id`
+pub async fn delete_collection(
+    Path(id): Path<Uuid>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (collection, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, Delete, resource).await?;
+
+    collection.delete(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `GET /collection/:id/posts`
+pub async fn list_collection_posts(
+    Path(id): Path<Uuid>,
+    Query(query): Query<ListSavedQuery>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (collection, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, Read, resource).await?;
+
+    let posts = collection_posts(&state.db, &state.oso, &auth, collection.id, query.page).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&posts)?))
+        .unwrap())
+}
+
+/// Handler for `POST /collection/:id/posts`
+///
+/// Only posts the requester can read can be added.
+pub async fn create_collection_post(
+    Path(id): Path<Uuid>,
+    ValidatedForm(add): ValidatedForm<AddCollectionPost>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (collection, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, EditCollectionPosts, resource)
+        .await?;
+    let post = find_resource::<Post>(&state.db, add.post_id).await?;
+    auth.authorize(&state.oso, Read, post.to_owned()).await?;
+    let added_by = match auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let added = add_collection_post(&state.db, collection.id, post.id, added_by, add.index).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&added)?))
+        .unwrap())
+}
+
+/// Handler for `PUT /collection/:id/posts`
+pub async fn reorder_collection(
+    Path(id): Path<Uuid>,
+    ValidatedForm(reorder): ValidatedForm<ReorderCollectionPosts>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (collection, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, EditCollectionPosts, resource)
+        .await?;
+
+    // already validated
+    let order = parse_post_ids(&reorder.post_ids).unwrap_or_default();
+    reorder_collection_posts(&state.db, collection.id, &order).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `DELETE /collection/:id/posts/:post_id`
+pub async fn delete_collection_post(
+    Path((id, post_id)): Path<(Uuid, Uuid)>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (collection, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, EditCollectionPosts, resource)
+        .await?;
+
+    remove_collection_post(&state.db, collection.id, post_id).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `POST /collection/:id/collaborator`
+pub async fn create_collaborator(
+    Path(id): Path<Uuid>,
+    ValidatedForm(add): ValidatedForm<AddCollaborator>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (_, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, ManageCollaborators, resource.to_owned())
+        .await?;
+    let user = UserAccount::find()
+        .filter(user_account::Column::Name.eq(add.name))
+        .one(&state.db)
+        .await?
+        .ok_or(MixiniError::NotFound)?;
+
+    let collaborator = add_collaborator(&state.db, &resource, user.id).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&collaborator)?))
+        .unwrap())
+}
+
+/// Handler for `DELETE /collection/:id/collaborator/:user_id`
+pub async fn delete_collaborator(
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (collection, resource) = find_collection(&state.db, id).await?;
+    auth.authorize(&state.oso, ManageCollaborators, resource)
+        .await?;
+
+    let result = CollectionCollaborator::delete_many()
+        .filter(collection_collaborator::Column::CollectionId.eq(collection.id))
+        .filter(collection_collaborator::Column::UserId.eq(user_id))
+        .exec(&state.db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(MixiniError::NotFound);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
@@ -0,0 +1,235 @@
+use axum::{
+    body::Body,
+    extract::{Extension, Path, Query},
+    http::{Response, StatusCode},
+};
+use chrono::Utc;
+use entity::{comment, prelude::*};
+use sea_orm::{entity::*, prelude::*};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    actions::{CommentOn, Delete, EditComment, Hide, Read, Restore},
+    auth::{find_resource, Auth, Authorized},
+    comments::{comment_history, create_comment, edit_comment, list_threads, CommentResponse},
+    error::MixiniError,
+    handlers::ValidatedForm,
+    server::State,
+};
+
+/// The query parameters for `GET /post/:id/comments`
+#[derive(Debug, Deserialize)]
+pub struct ListCommentsQuery {
+    /// The zero-indexed page of threads to fetch.
+    #[serde(default)]
+    pub page: usize,
+}
+
+/// The form input for `POST /post/:id/comments`
+#[derive(Debug, Validate, Deserialize)]
+pub struct CreateComment {
+    /// The comment, in markdown.
+    #[validate(length(
+        min = 1,
+        max = 10000,
+        message = "Minimum length is 1 character, maximum is 10000"
+    ))]
+    pub body: String,
+    /// The comment this replies to, if any.
+    pub parent_id: Option<Uuid>,
+}
+
+/// The form input for `PUT /comment/:id`
+#[derive(Debug, Validate, Deserialize)]
+pub struct UpdateComment {
+    /// The new body of the comment, in markdown.
+    #[validate(length(
+        min = 1,
+        max = 10000,
+        message = "Minimum length is 1 character, maximum is 10000"
+    ))]
+    pub body: String,
+}
+
+/// Handler for `GET /post/:id/comments`
+pub async fn list_comments(
+    authorized: Authorized<Read, Post>,
+    Query(query): Query<ListCommentsQuery>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let threads = list_threads(
+        &state.db,
+        &state.oso,
+        &authorized.auth,
+        authorized.resource.id,
+        query.page,
+    )
+    .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&threads)?))
+        .unwrap())
+}
+
+/// Handler for `POST /post/:id/comments`
+pub async fn post_comment(
+    Path(id): Path<Uuid>,
+    ValidatedForm(create): ValidatedForm<CreateComment>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let post = find_resource::<Post>(&state.db, id).await?;
+    auth.authorize(&state.oso, CommentOn, post.to_owned())
+        .await?;
+    let author_id = match auth {
+        Auth::KnownUser(ref this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+    let parent = match create.parent_id {
+        Some(parent_id) => {
+            let parent = find_resource::<Comment>(&state.db, parent_id).await?;
+            // only comments that can be read can be replied to
+            auth.authorize(&state.oso, Read, parent.to_owned()).await?;
+            Some(parent)
+        }
+        None => None,
+    };
+
+    let comment =
+        create_comment(&state.db, post.id, author_id, parent.as_ref(), create.body).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&CommentResponse::new(
+            comment, true,
+        ))?))
+        .unwrap())
+}
+
+/// Handler for `GET /comment/:id`
+pub async fn get_comment(
+    Path(id): Path<Uuid>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let comment = find_resource::<Comment>(&state.db, id).await?;
+    let post = find_resource::<Post>(&state.db, comment.post_id).await?;
+    auth.authorize(&state.oso, Read, post).await?;
+
+    let res_body = CommentResponse::for_auth(&state.oso, &auth, comment).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
+}
+
+/// Handler for `PUT /comment/:id`
+pub async fn update_comment(
+    Path(id): Path<Uuid>,
+    ValidatedForm(update): ValidatedForm<UpdateComment>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let comment = find_resource::<Comment>(&state.db, id).await?;
+    auth.authorize(&state.oso, EditComment, comment.to_owned())
+        .await?;
+    let editor_id = match auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let comment = edit_comment(&state.db, comment, editor_id, update.body).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&CommentResponse::new(
+            comment, true,
+        ))?))
+        .unwrap())
+}
+
+/// Handler for `DELETE /comment/:id`
+///
+/// Comments are only marked as deleted and their body cleared, so replies to them stay in place.
+pub async fn delete_comment(
+    authorized: Authorized<Delete, Comment>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let deleted_by = match authorized.auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    // every body is also kept as a revision, where moderators can still find it
+    let mut comment: comment::ActiveModel = authorized.resource.into();
+    comment.body = Set(String::new());
+    comment.body_html = Set(String::new());
+    comment.deleted_at = Set(Some(Utc::now().into()));
+    comment.deleted_by = Set(Some(deleted_by));
+    comment.update(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `POST /comment/:id/hide`
+pub async fn hide_comment(
+    authorized: Authorized<Hide, Comment>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let hidden_by = match authorized.auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let mut comment: comment::ActiveModel = authorized.resource.into();
+    comment.hidden_at = Set(Some(Utc::now().into()));
+    comment.hidden_by = Set(Some(hidden_by));
+    comment.update(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `POST /comment/:id/restore`
+pub async fn restore_comment(
+    authorized: Authorized<Restore, Comment>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let mut comment: comment::ActiveModel = authorized.resource.into();
+    comment.hidden_at = Set(None);
+    comment.hidden_by = Set(None);
+    comment.update(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `GET /comment/:id/history`
+///
+/// Lists every version of the comment, oldest first, to anyone who can read it and its post.
+pub async fn get_comment_history(
+    authorized: Authorized<Read, Comment>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let post = find_resource::<Post>(&state.db, authorized.resource.post_id).await?;
+    authorized.auth.authorize(&state.oso, Read, post).await?;
+
+    let revisions = comment_history(&state.db, authorized.resource.id).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&revisions)?))
+        .unwrap())
+}
@@ -0,0 +1,507 @@
+use axum::{
+    body::Body,
+    extract::{multipart::Field, Extension, Multipart, Path, Query},
+    http::{header, HeaderMap, Response, StatusCode},
+};
+use entity::{post, prelude::*, sea_orm_active_enums::PostRating};
+use sea_orm::{entity::*, prelude::*, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use ulid::Ulid;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    actions::{parse_source_urls, EditTags, FindSimilar, Read, Upload, ViewMedia},
+    auth::{find_resource, Auth, Guest},
+    constants::{
+        MAX_IGNORED_UPLOAD_FIELDS, MAX_UPLOAD_FIELD_BYTES, MEDIA_CACHE_MAX_AGE_SECS,
+        SIMILAR_DEFAULT_DISTANCE,
+    },
+    error::MixiniError,
+    handlers::CreatePost,
+    media::{
+        dhash_file_blocking, etag_matches, generate_previews, media_etag, media_key, parse_range,
+        preview_key, receive_media, regenerate_previews, sanitize_media, similar_posts, ByteRange,
+        PreviewFormat, PreviewKind, SimilarPost, StoreError,
+    },
+    server::State,
+    tags::{parse_tag_names, set_post_tags_in, validate_tag_names},
+};
+
+/// The text fields of `POST /post/upload`, sent alongside the image in the `file` field.
+#[derive(Debug, Validate, Deserialize)]
+pub struct UploadPost {
+    #[serde(flatten)]
+    #[validate]
+    pub post: CreatePost,
+    /// Whitespace-separated tag names. Only applied if the uploader may edit tags.
+    #[validate(custom = "validate_tag_names")]
+    #[serde(default)]
+    pub tags: String,
+}
+
+impl UploadPost {
+    /// The names of every text field.
+    const FIELDS: [&'static str; 5] = ["title", "description", "source_urls", "rating", "tags"];
+}
+
+/// The response for `POST /post/upload`
+#[derive(Debug, Serialize)]
+pub struct UploadPostResponse {
+    #[serde(flatten)]
+    pub post: post::Model,
+    pub tags: Vec<entity::tag::Model>,
+    /// Posts that look like the upload, which may mean it's a repost.
+    pub similar_posts: Vec<SimilarPost>,
+}
+
+/// The query for `GET /post/similar`
+#[derive(Debug, Validate, Deserialize)]
+pub struct SimilarToPostQuery {
+    /// The post to find posts similar to.
+    pub post: Uuid,
+    /// The most bits the hashes of similar images may differ by, out of 64.
+    #[validate(range(max = 32, message = "Maximum distance is 32"))]
+    pub distance: Option<u32>,
+}
+
+/// The query for `POST /post/similar`
+#[derive(Debug, Validate, Deserialize)]
+pub struct SimilarToUploadQuery {
+    /// The most bits the hashes of similar images may differ by, out of 64.
+    #[validate(range(max = 32, message = "Maximum distance is 32"))]
+    pub distance: Option<u32>,
+}
+
+/// Read a text field, refusing fields over `MAX_UPLOAD_FIELD_BYTES`.
+async fn read_text_field(mut field: Field<'_>) -> Result<String, MixiniError> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.chunk().await? {
+        if bytes.len() + chunk.len() > MAX_UPLOAD_FIELD_BYTES {
+            return Err(MixiniError::PayloadTooLarge);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    String::from_utf8(bytes)
+        .map_err(|_| MixiniError::InvalidUpload("Text fields must be valid UTF-8".to_owned()))
+}
+
+/// Read and discard a field, refusing fields over `MAX_UPLOAD_FIELD_BYTES`.
+async fn skip_field(mut field: Field<'_>) -> Result<(), MixiniError> {
+    let mut len = 0;
+    while let Some(chunk) = field.chunk().await? {
+        len += chunk.len();
+        if len > MAX_UPLOAD_FIELD_BYTES {
+            return Err(MixiniError::PayloadTooLarge);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `e` is from giving a post media that another post already has.
+fn is_duplicate_media(e: &DbErr) -> bool {
+    matches!(
+        e,
+        DbErr::Exec(message) | DbErr::Query(message) if message.contains("post_media_hash_key")
+    )
+}
+
+/// Handler for `POST /post/upload`
+///
+/// Expects a multipart body with the image in a `file` field and the fields of `UploadPost`, each
+/// sent at most once and nothing else.
+/// The image is streamed to a temporary file, stripped of its embedded metadata and then put in the
+/// media store, and rejected if it isn't a supported image or was already uploaded to another post.
+pub async fn upload_post(
+    state: Extension<Arc<State>>,
+    auth: Auth,
+    mut multipart: Multipart,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Upload, "Post").await?;
+    let uploader_id = match auth {
+        Auth::KnownUser(ref this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let mut fields = serde_json::Map::new();
+    let mut media = None;
+    while let Some(field) = multipart.next_field().await? {
+        let name = field.name().map(str::to_owned);
+        match name.as_deref() {
+            Some("file") if media.is_some() => {
+                return Err(MixiniError::InvalidUpload(
+                    "Only one file can be uploaded at a time".to_owned(),
+                ))
+            }
+            Some("file") => media = Some(receive_media(field, &state.upload_dir).await?),
+            // only known fields are read, so the fields buffered are bounded too
+            Some(name) if !UploadPost::FIELDS.contains(&name) => {
+                return Err(MixiniError::InvalidUpload(format!(
+                    "Unknown field `{}`",
+                    name
+                )))
+            }
+            Some(name) if fields.contains_key(name) => {
+                return Err(MixiniError::InvalidUpload(format!(
+                    "`{}` can only be sent once",
+                    name
+                )))
+            }
+            Some(name) => {
+                let value = read_text_field(field).await?;
+                fields.insert(name.to_owned(), value.into());
+            }
+            None => {}
+        }
+    }
+    let media = media.ok_or_else(|| MixiniError::InvalidUpload("Missing the file".to_owned()))?;
+
+    let upload: UploadPost = serde_json::from_value(fields.into())
+        .map_err(|e| MixiniError::InvalidUpload(e.to_string()))?;
+    upload.validate()?;
+
+    // only the stripped file is ever stored, so that's what's hashed and deduplicated
+    let (media, metadata) = sanitize_media(media, &state.upload_dir).await?;
+
+    if let Some(existing) = Post::find()
+        .filter(post::Column::MediaHash.eq(media.hash.as_str()))
+        .one(&state.db)
+        .await?
+    {
+        return Err(MixiniError::DuplicateMedia(existing.id));
+    }
+
+    let dhash = dhash_file_blocking(media.file.path(), &media.format).await?;
+    let similar_posts = similar_posts(
+        &state.db,
+        &state.oso,
+        &auth,
+        &state.similarity_index,
+        dhash,
+        SIMILAR_DEFAULT_DISTANCE,
+        None,
+    )
+    .await?;
+
+    state
+        .media
+        .put_file(
+            &media_key(&media.hash, &media.format),
+            media.file.path(),
+            media.format.mime_type(),
+        )
+        .await?;
+
+    // the post is only kept if it can be tagged too
+    let txn = state.db.begin().await?;
+    let new_post = post::ActiveModel {
+        id: Set(Uuid::from(Ulid::new())),
+        uploader_id: Set(uploader_id),
+        title: Set(upload.post.title),
+        description: Set(upload.post.description),
+        source_urls: Set(parse_source_urls(&upload.post.source_urls).into()),
+        rating: Set(upload.post.rating.unwrap_or(PostRating::Safe)),
+        media_hash: Set(Some(media.hash.to_owned())),
+        media_format: Set(Some(media.format.to_owned())),
+        media_size: Set(Some(media.size as i64)),
+        width: Set(Some(media.width as i32)),
+        height: Set(Some(media.height as i32)),
+        media_dhash: Set(Some(dhash as i64)),
+        camera: Set(metadata.camera),
+        software: Set(metadata.software),
+        taken_at: Set(metadata.taken_at),
+        embedded_artist: Set(metadata.artist),
+        embedded_copyright: Set(metadata.copyright),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await;
+    let new_post = match new_post {
+        // the same media was uploaded concurrently, after the check above
+        Err(e) if is_duplicate_media(&e) => {
+            let existing = Post::find()
+                .filter(post::Column::MediaHash.eq(media.hash.as_str()))
+                .one(&state.db)
+                .await?
+                .ok_or(e)?;
+            return Err(MixiniError::DuplicateMedia(existing.id));
+        }
+        new_post => new_post?,
+    };
+    let tags = match auth
+        .authorize(&state.oso, EditTags, new_post.to_owned())
+        .await
+    {
+        Ok(()) => set_post_tags_in(&txn, new_post.id, &parse_tag_names(&upload.tags)).await?,
+        Err(MixiniError::Forbidden) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    txn.commit().await?;
+
+    state
+        .similarity_index
+        .write()
+        .expect("similarity index lock poisoned")
+        .insert(dhash, new_post.id);
+
+    // previews aren't needed to respond, so they're generated in the background
+    let (store, upload_dir, sizes) = (
+        state.media.clone(),
+        state.upload_dir.clone(),
+        state.preview_sizes,
+    );
+    tokio::spawn(async move {
+        if let Err(e) = generate_previews(
+            store.as_ref(),
+            &upload_dir,
+            media.file.path(),
+            &media.hash,
+            &media.format,
+            sizes,
+        )
+        .await
+        {
+            tracing::warn!("Failed to generate previews of {}: {:?}", media.hash, e);
+        }
+    });
+
+    let res_body = UploadPostResponse {
+        post: new_post,
+        tags,
+        similar_posts,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
+}
+
+/// The `Cache-Control` of a post's files, which never change. They may be cached by anyone if
+/// guests may view them, and only by the requester otherwise.
+async fn media_cache_control(state: &State, post: &post::Model) -> Result<String, MixiniError> {
+    let public = state.oso.lock().await.is_allowed(
+        Auth::UnknownUser(Guest::default()),
+        ViewMedia,
+        post.to_owned(),
+    )?;
+    Ok(format!(
+        "{}, max-age={}, immutable",
+        if public { "public" } else { "private" },
+        MEDIA_CACHE_MAX_AGE_SECS
+    ))
+}
+
+/// Handler for `GET /post/:id/media`
+///
+/// Serves the post's original file. Supports `If-None-Match` with the file's hash as its `ETag`,
+/// and `Range` requests for a single range of bytes.
+pub async fn get_post_media(
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let post = find_resource::<Post>(&state.db, id).await?;
+    auth.authorize(&state.oso, ViewMedia, post.to_owned())
+        .await?;
+    let (hash, format, size) = match (
+        post.media_hash.as_deref(),
+        post.media_format.as_ref(),
+        post.media_size,
+    ) {
+        (Some(hash), Some(format), Some(size)) => (hash, format, size as u64),
+        _ => return Err(MixiniError::NotFound),
+    };
+
+    let etag = media_etag(hash);
+    let response = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(
+            header::CACHE_CONTROL,
+            media_cache_control(&state, &post).await?,
+        )
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    let header_str = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok());
+    if header_str(header::IF_NONE_MATCH).map_or(false, |tags| etag_matches(tags, &etag)) {
+        return Ok(response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    // ranges are only honored if any copy the client already has is of the same file
+    let range = match header_str(header::RANGE) {
+        Some(range) if header_str(header::IF_RANGE).map_or(true, |tag| tag == etag) => {
+            parse_range(range, size)
+        }
+        _ => ByteRange::Full,
+    };
+    let key = media_key(hash, format);
+    let response = response
+        .header(header::CONTENT_TYPE, format.mime_type())
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+
+    match range {
+        ByteRange::Full => {
+            let object = state.media.get(&key, None).await?;
+            Ok(response
+                .status(StatusCode::OK)
+                .header(header::CONTENT_LENGTH, object.size)
+                .body(Body::wrap_stream(object.body))
+                .unwrap())
+        }
+        ByteRange::Partial(range) => {
+            let object = state.media.get(&key, Some(range)).await?;
+            Ok(response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!(
+                        "bytes {}-{}/{}",
+                        object.range.start,
+                        object.range.end - 1,
+                        object.size
+                    ),
+                )
+                .header(
+                    header::CONTENT_LENGTH,
+                    object.range.end - object.range.start,
+                )
+                .body(Body::wrap_stream(object.body))
+                .unwrap())
+        }
+        ByteRange::Unsatisfiable => Ok(response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+            .body(Body::empty())
+            .unwrap()),
+    }
+}
+
+/// Handler for `GET /post/:id/preview/:name`
+///
+/// `name` is the kind of preview and its format, e.g. `thumbnail.webp` or `sample.jpg`. Previews
+/// that are missing, say because the configured sizes changed, are generated before responding.
+pub async fn get_post_preview(
+    Path((id, name)): Path<(Uuid, String)>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let (kind, format) = name.split_once('.').ok_or(MixiniError::NotFound)?;
+    let (kind, format): (PreviewKind, PreviewFormat) = (kind.parse()?, format.parse()?);
+
+    let post = find_resource::<Post>(&state.db, id).await?;
+    auth.authorize(&state.oso, ViewMedia, post.to_owned())
+        .await?;
+    let (hash, media_format) = match (post.media_hash.as_deref(), post.media_format.as_ref()) {
+        (Some(hash), Some(media_format)) => (hash, media_format),
+        _ => return Err(MixiniError::NotFound),
+    };
+
+    let key = preview_key(hash, state.preview_sizes.size(kind), format);
+    let object = match state.media.get(&key, None).await {
+        Err(StoreError::NotFound) => {
+            regenerate_previews(
+                state.media.as_ref(),
+                &state.upload_dir,
+                hash,
+                media_format,
+                state.preview_sizes,
+            )
+            .await?;
+            state.media.get(&key, None).await?
+        }
+        object => object?,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.mime_type())
+        .header(header::CONTENT_LENGTH, object.size)
+        .header(
+            header::CACHE_CONTROL,
+            media_cache_control(&state, &post).await?,
+        )
+        .body(Body::wrap_stream(object.body))
+        .unwrap())
+}
+
+/// Handler for `GET /post/similar`
+pub async fn list_similar_posts(
+    Query(query): Query<SimilarToPostQuery>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    query.validate()?;
+    let post = find_resource::<Post>(&state.db, query.post).await?;
+    auth.authorize(&state.oso, Read, post.to_owned()).await?;
+    // posts without media, or whose media hasn't been hashed yet, can't be compared
+    let dhash = post.media_dhash.ok_or(MixiniError::NotFound)?;
+
+    let posts = similar_posts(
+        &state.db,
+        &state.oso,
+        &auth,
+        &state.similarity_index,
+        dhash as u64,
+        query.distance.unwrap_or(SIMILAR_DEFAULT_DISTANCE),
+        Some(post.id),
+    )
+    .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&posts)?))
+        .unwrap())
+}
+
+/// Handler for `POST /post/similar`
+///
+/// Expects a multipart body with an image in a `file` field, which is only compared and never
+/// stored. Up to `MAX_IGNORED_UPLOAD_FIELDS` other small fields are ignored.
+pub async fn find_similar_posts(
+    Query(query): Query<SimilarToUploadQuery>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+    mut multipart: Multipart,
+) -> Result<Response<Body>, MixiniError> {
+    query.validate()?;
+    auth.authorize(&state.oso, FindSimilar, "Post").await?;
+
+    let mut media = None;
+    let mut ignored = 0;
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() == Some("file") && media.is_none() {
+            media = Some(receive_media(field, &state.upload_dir).await?);
+        } else if ignored < MAX_IGNORED_UPLOAD_FIELDS {
+            ignored += 1;
+            skip_field(field).await?;
+        } else {
+            return Err(MixiniError::InvalidUpload(format!(
+                "At most {} fields other than the file are allowed",
+                MAX_IGNORED_UPLOAD_FIELDS
+            )));
+        }
+    }
+    let media = media.ok_or_else(|| MixiniError::InvalidUpload("Missing the file".to_owned()))?;
+    let dhash = dhash_file_blocking(media.file.path(), &media.format).await?;
+
+    let posts = similar_posts(
+        &state.db,
+        &state.oso,
+        &auth,
+        &state.similarity_index,
+        dhash,
+        query.distance.unwrap_or(SIMILAR_DEFAULT_DISTANCE),
+        None,
+    )
+    .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&posts)?))
+        .unwrap())
+}
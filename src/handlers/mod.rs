@@ -9,10 +9,24 @@ use validator::Validate;
 
 use crate::error::MixiniError;
 
+pub mod admin;
+pub mod collection;
+pub mod comment;
 pub mod login;
+pub mod media;
+pub mod post;
+pub mod revision;
+pub mod tag;
 pub mod user;
 
+pub use admin::*;
+pub use collection::*;
+pub use comment::*;
 pub use login::*;
+pub use media::*;
+pub use post::*;
+pub use revision::*;
+pub use tag::*;
 pub use user::*;
 
 /// A validated form with some input.
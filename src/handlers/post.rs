@@ -0,0 +1,265 @@
+use axum::{
+    body::Body,
+    extract::{Extension, Path, Query},
+    http::{Response, StatusCode},
+};
+use entity::{post, prelude::*, sea_orm_active_enums::PostRating, tag};
+use sea_orm::{entity::*, prelude::*, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use ulid::Ulid;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    actions::{
+        parse_source_urls, validate_source_urls, Create, Delete, EditTags, Read, UpdatePost,
+    },
+    auth::{find_resource, Auth, Authorized},
+    error::MixiniError,
+    handlers::ValidatedForm,
+    revisions::{ensure_first_revision, lock_post, record_revision},
+    search::{max_search_terms, parse_query, search_posts},
+    server::State,
+    tags::set_post_tags_in,
+};
+
+/// The form input for `POST /post`
+#[derive(Debug, Validate, Deserialize)]
+pub struct CreatePost {
+    /// The title of the artwork.
+    #[validate(length(
+        min = 1,
+        max = 256,
+        message = "Minimum length is 1 character, maximum is 256"
+    ))]
+    pub title: String,
+    /// A description of the artwork.
+    #[validate(length(max = 10000, message = "Maximum length is 10000 characters"))]
+    #[serde(default)]
+    pub description: String,
+    /// Whitespace-separated URLs the artwork was sourced from.
+    #[validate(custom = "validate_source_urls")]
+    #[serde(default)]
+    pub source_urls: String,
+    /// The content rating. Defaults to safe.
+    pub rating: Option<PostRating>,
+}
+
+/// The form input for `POST /post/import`
+#[derive(Debug, Validate, Deserialize)]
+pub struct ImportPost {
+    /// The page of the artwork on its source.
+    #[validate(url(message = "Must be a valid URL"))]
+    pub url: String,
+}
+
+/// The response for `POST /post/import`
+#[derive(Debug, Serialize)]
+pub struct ImportPostResponse {
+    #[serde(flatten)]
+    pub post: post::Model,
+    pub media_url: Option<String>,
+    /// The tags the post was tagged with. Empty if the importer may not edit tags.
+    pub tags: Vec<tag::Model>,
+    /// The tags the source suggested.
+    pub suggested_tags: Vec<String>,
+}
+
+/// The query parameters for `GET /post`
+#[derive(Debug, Deserialize)]
+pub struct SearchPostsQuery {
+    /// The search query, as described in `search::parse`. Lists every post if not provided.
+    #[serde(default)]
+    pub q: String,
+    /// Free text to match against titles, descriptions, and the wikis of tags.
+    pub text: Option<String>,
+    /// The zero-indexed page to fetch.
+    #[serde(default)]
+    pub page: usize,
+}
+
+/// Handler for `POST /post`
+pub async fn create_post(
+    ValidatedForm(create_post): ValidatedForm<CreatePost>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Create, "Post").await?;
+    let uploader_id = match auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let new_post = post::ActiveModel {
+        id: Set(Uuid::from(Ulid::new())),
+        uploader_id: Set(uploader_id),
+        title: Set(create_post.title),
+        description: Set(create_post.description),
+        source_urls: Set(parse_source_urls(&create_post.source_urls).into()),
+        rating: Set(create_post.rating.unwrap_or(PostRating::Safe)),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&new_post)?))
+        .unwrap())
+}
+
+/// Handler for `POST /post/import`
+///
+/// Creates a post from an artwork on an external site, tagging it with the source's tags if the
+/// requester may edit tags.
+pub async fn import_post(
+    ValidatedForm(import_post): ValidatedForm<ImportPost>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Create, "Post").await?;
+    let uploader_id = match auth {
+        Auth::KnownUser(ref this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let imported = state.importers.import(&import_post.url).await?;
+    // the post is only kept if it can be tagged too
+    let txn = state.db.begin().await?;
+    let new_post = post::ActiveModel {
+        id: Set(Uuid::from(Ulid::new())),
+        uploader_id: Set(uploader_id),
+        title: Set(imported.title),
+        description: Set(imported.description),
+        source_urls: Set(imported.source_urls.into()),
+        rating: Set(imported.rating),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    let tags = match auth
+        .authorize(&state.oso, EditTags, new_post.to_owned())
+        .await
+    {
+        Ok(()) => set_post_tags_in(&txn, new_post.id, &imported.tags).await?,
+        Err(MixiniError::Forbidden) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    txn.commit().await?;
+
+    let res_body = ImportPostResponse {
+        post: new_post,
+        media_url: imported.media_url,
+        tags,
+        suggested_tags: imported.tags,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
+}
+
+/// Handler for `GET /post`
+pub async fn list_posts(
+    Query(query): Query<SearchPostsQuery>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let search_query = parse_query(&query.q, max_search_terms(&auth))?;
+    let posts = search_posts(
+        &state.db,
+        &state.oso,
+        &auth,
+        &search_query,
+        query.text.as_deref(),
+        query.page,
+    )
+    .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&posts)?))
+        .unwrap())
+}
+
+/// Handler for `GET /post/:id`
+pub async fn get_post(authorized: Authorized<Read, Post>) -> Result<Response<Body>, MixiniError> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&authorized.resource)?))
+        .unwrap())
+}
+
+/// Handler for `PUT /post/:id`
+pub async fn update_post(
+    Path(id): Path<Uuid>,
+    ValidatedForm(update_post): ValidatedForm<UpdatePost>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let post = find_resource::<Post>(&state.db, id).await?;
+    auth.authorize_fields(
+        &state.oso,
+        update_post.to_owned(),
+        post.to_owned(),
+        &update_post.fields(),
+    )
+    .await?;
+    let editor = match auth {
+        Auth::KnownUser(this_user) => this_user,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+    // title and status aren't community-edited, so changes to them aren't kept as revisions
+    let revised = update_post.description.is_some()
+        || update_post.source_urls.is_some()
+        || update_post.rating.is_some();
+
+    let txn = state.db.begin().await?;
+    let post = lock_post(&txn, post.id).await?;
+    if revised {
+        ensure_first_revision(&txn, &post).await?;
+    }
+
+    let mut post: post::ActiveModel = post.into();
+    if let Some(title) = update_post.title {
+        post.title = Set(title);
+    }
+    if let Some(description) = update_post.description {
+        post.description = Set(description);
+    }
+    if let Some(source_urls) = update_post.source_urls {
+        post.source_urls = Set(parse_source_urls(&source_urls).into());
+    }
+    if let Some(rating) = update_post.rating {
+        post.rating = Set(rating);
+    }
+    if let Some(status) = update_post.status {
+        post.status = Set(status);
+    }
+    let post = post.update(&txn).await?;
+    if revised {
+        record_revision(&txn, post.id, &editor, None).await?;
+    }
+    txn.commit().await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `DELETE /post/:id`
+pub async fn delete_post(
+    authorized: Authorized<Delete, Post>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    authorized.resource.delete(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
@@ -0,0 +1,127 @@
+use axum::{
+    body::Body,
+    extract::{Extension, Path, Query},
+    http::{Response, StatusCode},
+};
+use entity::prelude::*;
+use sea_orm::TransactionTrait;
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    actions::{Read, Revert},
+    auth::{find_resource, Auth, Authorized},
+    error::MixiniError,
+    revisions::{
+        diff_revisions, find_revision, list_revisions, lock_post, revert_post, revisions_since,
+    },
+    server::State,
+};
+
+/// The query parameters for `GET /post/:id/revisions`
+#[derive(Debug, Validate, Deserialize)]
+pub struct ListRevisionsQuery {
+    /// The zero-indexed page of revisions to fetch, newest first.
+    #[validate(range(max = 100000, message = "Maximum page is 100000"))]
+    #[serde(default)]
+    pub page: usize,
+}
+
+/// The query parameters for `GET /post/:id/revisions/diff`
+#[derive(Debug, Deserialize)]
+pub struct DiffRevisionsQuery {
+    /// The version to compare from.
+    pub from: i32,
+    /// The version to compare to.
+    pub to: i32,
+}
+
+/// Handler for `GET /post/:id/revisions`
+pub async fn list_post_revisions(
+    authorized: Authorized<Read, Post>,
+    Query(query): Query<ListRevisionsQuery>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    query.validate()?;
+    let revisions = list_revisions(&state.db, authorized.resource.id, query.page).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&revisions)?))
+        .unwrap())
+}
+
+/// Handler for `GET /post/:id/revisions/diff`
+pub async fn diff_post_revisions(
+    authorized: Authorized<Read, Post>,
+    Query(query): Query<DiffRevisionsQuery>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let diff = diff_revisions(&state.db, authorized.resource.id, query.from, query.to).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&diff)?))
+        .unwrap())
+}
+
+/// Handler for `GET /post/:id/revisions/:version`
+pub async fn get_post_revision(
+    Path((id, version)): Path<(Uuid, i32)>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let post = find_resource::<Post>(&state.db, id).await?;
+    auth.authorize(&state.oso, Read, post.to_owned()).await?;
+
+    let revision = find_revision(&state.db, post.id, version).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&revision)?))
+        .unwrap())
+}
+
+/// Handler for `POST /post/:id/revisions/:version/revert`
+///
+/// Restores the post to how it was in the version, which undoes every revision since. The
+/// requester must be allowed to revert each of them. Responds with the new revision, or a conflict
+/// if the post already matches the version.
+pub async fn revert_post_revision(
+    Path((id, version)): Path<(Uuid, i32)>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let post = find_resource::<Post>(&state.db, id).await?;
+    auth.authorize(&state.oso, Read, post.to_owned()).await?;
+    let editor = match auth {
+        Auth::KnownUser(ref this_user) => this_user.to_owned(),
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    // the post stays locked until it's reverted, so no revision can slip in unchecked
+    let txn = state.db.begin().await?;
+    let post = lock_post(&txn, post.id).await?;
+    let revision = find_revision(&txn, post.id, version).await?;
+    let undone = revisions_since(&txn, post.id, version).await?;
+    if undone.is_empty() {
+        return Err(MixiniError::Conflict(
+            "This is already the latest version of the post".to_owned(),
+        ));
+    }
+    for undone_revision in undone {
+        auth.authorize(&state.oso, Revert, undone_revision).await?;
+    }
+
+    let reverted = revert_post(&txn, post, &revision, &editor)
+        .await?
+        .ok_or_else(|| MixiniError::Conflict("The post already matches this version".to_owned()))?;
+    txn.commit().await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&reverted)?))
+        .unwrap())
+}
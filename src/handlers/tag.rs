@@ -0,0 +1,231 @@
+use axum::{
+    body::Body,
+    extract::{Extension, Path},
+    http::{Response, StatusCode},
+};
+use entity::{prelude::*, sea_orm_active_enums::TagCategory, tag};
+use sea_orm::{entity::*, prelude::*, QueryOrder, TransactionTrait};
+use serde::Deserialize;
+use std::sync::Arc;
+use ulid::Ulid;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    actions::{Create, EditTags, Read, UpdateTag},
+    auth::{find_resource, Auth, Authorized},
+    error::MixiniError,
+    handlers::ValidatedForm,
+    revisions::{ensure_first_revision, lock_post, record_revision},
+    server::State,
+    tags::{
+        add_alias, add_implication, is_name_taken, lock_tag_names, normalize_tag_name,
+        parse_tag_names, resolve_tags, set_post_tags_in, validate_tag_name, validate_tag_names,
+    },
+};
+
+/// The form input for `POST /tag`
+#[derive(Debug, Validate, Deserialize)]
+pub struct CreateTag {
+    #[validate(custom = "validate_tag_name")]
+    pub name: String,
+    /// The category of the tag. Defaults to general.
+    pub category: Option<TagCategory>,
+    /// The tag's wiki, describing what it's used for.
+    #[validate(length(max = 20000, message = "Maximum length is 20000 characters"))]
+    #[serde(default)]
+    pub wiki: String,
+}
+
+/// The form input for `POST /tag/alias`
+#[derive(Debug, Validate, Deserialize)]
+pub struct CreateTagAlias {
+    /// The alternative name.
+    #[validate(custom = "validate_tag_name")]
+    pub alias: String,
+    /// The tag, or an alias of the tag, that `alias` resolves to.
+    pub tag: String,
+}
+
+/// The form input for `POST /tag/implication`
+#[derive(Debug, Validate, Deserialize)]
+pub struct CreateTagImplication {
+    /// The implying tag.
+    pub tag: String,
+    /// The tag implied by `tag`.
+    pub implies: String,
+}
+
+/// The form input for `PUT /post/:id/tags`
+#[derive(Debug, Validate, Deserialize)]
+pub struct UpdatePostTags {
+    /// Whitespace-separated tag names, replacing the existing ones.
+    #[validate(custom = "validate_tag_names")]
+    pub tags: String,
+}
+
+/// Resolve a single tag name or alias, as given by a user.
+async fn find_tag(db: &DatabaseConnection, name: &str) -> Result<tag::Model, MixiniError> {
+    let name = normalize_tag_name(name);
+    resolve_tags(db, &[name.to_owned()])
+        .await?
+        .remove(&name)
+        .ok_or(MixiniError::NotFound)
+}
+
+/// Handler for `POST /tag`
+pub async fn create_tag(
+    ValidatedForm(create_tag): ValidatedForm<CreateTag>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Create, "Tag").await?;
+
+    let name = normalize_tag_name(&create_tag.name);
+    let txn = state.db.begin().await?;
+    lock_tag_names(&txn, &[name.to_owned()]).await?;
+    if is_name_taken(&txn, &name).await? {
+        return Err(MixiniError::Conflict(format!(
+            "`{}` is already a tag or alias",
+            name
+        )));
+    }
+
+    let new_tag = tag::ActiveModel {
+        id: Set(Uuid::from(Ulid::new())),
+        name: Set(name),
+        category: create_tag.category.into_active_value(),
+        wiki: Set(create_tag.wiki),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&new_tag)?))
+        .unwrap())
+}
+
+/// Handler for `GET /tag/:id`
+pub async fn get_tag(authorized: Authorized<Read, Tag>) -> Result<Response<Body>, MixiniError> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&authorized.resource)?))
+        .unwrap())
+}
+
+/// Handler for `PUT /tag/:id`
+pub async fn update_tag(
+    Path(id): Path<Uuid>,
+    ValidatedForm(update_tag): ValidatedForm<UpdateTag>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let tag = find_resource::<Tag>(&state.db, id).await?;
+    auth.authorize(&state.oso, update_tag.to_owned(), tag.to_owned())
+        .await?;
+
+    let mut tag: tag::ActiveModel = tag.into();
+    if let Some(category) = update_tag.category {
+        tag.category = Set(category);
+    }
+    if let Some(wiki) = update_tag.wiki {
+        tag.wiki = Set(wiki);
+    }
+    tag.update(&state.db).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handler for `POST /tag/alias`
+pub async fn create_tag_alias(
+    ValidatedForm(create_tag_alias): ValidatedForm<CreateTagAlias>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Create, "TagAlias").await?;
+
+    let tag = find_tag(&state.db, &create_tag_alias.tag).await?;
+    let alias = add_alias(
+        &state.db,
+        normalize_tag_name(&create_tag_alias.alias),
+        tag.id,
+    )
+    .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&alias)?))
+        .unwrap())
+}
+
+/// Handler for `POST /tag/implication`
+pub async fn create_tag_implication(
+    ValidatedForm(create_tag_implication): ValidatedForm<CreateTagImplication>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    auth.authorize(&state.oso, Create, "TagImplication").await?;
+
+    let tag = find_tag(&state.db, &create_tag_implication.tag).await?;
+    let implied_tag = find_tag(&state.db, &create_tag_implication.implies).await?;
+    let implication = add_implication(&state.db, tag.id, implied_tag.id).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&implication)?))
+        .unwrap())
+}
+
+/// Handler for `GET /post/:id/tags`
+pub async fn get_post_tags(
+    authorized: Authorized<Read, Post>,
+    state: Extension<Arc<State>>,
+) -> Result<Response<Body>, MixiniError> {
+    let tags = authorized
+        .resource
+        .find_related(Tag)
+        .order_by_asc(tag::Column::Name)
+        .all(&state.db)
+        .await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&tags)?))
+        .unwrap())
+}
+
+/// Handler for `PUT /post/:id/tags`
+///
+/// Aliases are resolved, unknown tags are created as general tags, and implied tags are added.
+pub async fn update_post_tags(
+    Path(id): Path<Uuid>,
+    ValidatedForm(update_post_tags): ValidatedForm<UpdatePostTags>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let post = find_resource::<Post>(&state.db, id).await?;
+    auth.authorize(&state.oso, EditTags, post.to_owned())
+        .await?;
+    let editor = match auth {
+        Auth::KnownUser(this_user) => this_user,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let txn = state.db.begin().await?;
+    let post = lock_post(&txn, post.id).await?;
+    ensure_first_revision(&txn, &post).await?;
+    let tags = set_post_tags_in(&txn, post.id, &parse_tag_names(&update_post_tags.tags)).await?;
+    record_revision(&txn, post.id, &editor, None).await?;
+    txn.commit().await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&tags)?))
+        .unwrap())
+}
@@ -1,29 +1,31 @@
 use anyhow::format_err;
 use axum::{
     body::Body,
-    extract::{Extension, Path, TypedHeader},
+    extract::{Extension, Path, Query, TypedHeader},
     headers::Cookie,
     http::{Response, StatusCode},
 };
-use entity::{prelude::*, sea_orm_active_enums::UserRole, user_account};
+use entity::{prelude::*, sea_orm_active_enums::UserRole, user_account, user_preference};
 use fieldfilter::FieldFilterable;
 use redis::AsyncCommands;
 use sea_orm::{entity::*, prelude::*, query::*};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, sync::Arc};
+use std::sync::Arc;
 use ulid::Ulid;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     actions::{Delete, Read, UpdateUser},
-    auth::Auth,
+    auth::{find_resource, Auth, Authorized},
     constants::{
-        RE_PASSWORD, RE_USERNAME, SESSION_COOKIE_NAME, SESSION_KEY_PREFIX, VERIFY_EXPIRY_SECONDS,
-        VERIFY_KEY_PREFIX,
+        RE_PASSWORD, RE_USERNAME, SESSION_COOKIE_NAME, SESSION_KEY_PREFIX, USERS_PER_PAGE,
+        VERIFY_EXPIRY_SECONDS, VERIFY_KEY_PREFIX,
     },
     error::MixiniError,
+    filtering::authorized_condition,
     handlers::ValidatedForm,
+    preferences::parse_blacklist,
     server::State,
     utils::{mail::send_email_verification_request, pass::HASHER, RKeys},
 };
@@ -72,6 +74,67 @@ pub struct VerifyForm {
     pub key: String,
 }
 
+/// The query parameters for `GET /user`
+#[derive(Debug, Deserialize)]
+pub struct ListUsersQuery {
+    /// The zero-indexed page to fetch.
+    #[serde(default)]
+    pub page: usize,
+}
+
+/// The form input for `PUT /user/preferences`. Preferences that aren't given are left unchanged.
+#[derive(Debug, Validate, Deserialize)]
+pub struct UpdatePreferences {
+    pub show_safe: Option<bool>,
+    pub show_questionable: Option<bool>,
+    pub show_explicit: Option<bool>,
+    /// Search queries matching posts not to show, one per line.
+    #[validate(length(max = 10000, message = "Maximum length is 10000 characters"))]
+    pub tag_blacklist: Option<String>,
+    /// Whether to list blacklisted posts marked as such, rather than leave them out.
+    pub mark_blacklisted: Option<bool>,
+    /// Whether anyone can see the user's favorites.
+    pub public_favorites: Option<bool>,
+}
+
+/// The response for `GET /user/preferences`
+#[derive(Debug, Serialize)]
+pub struct GetPreferencesResponse {
+    pub show_safe: bool,
+    pub show_questionable: bool,
+    pub show_explicit: bool,
+    pub tag_blacklist: String,
+    pub mark_blacklisted: bool,
+    pub public_favorites: bool,
+}
+
+/// The preferences of users who haven't set any, as in `ContentPreferences::user_default`.
+impl Default for GetPreferencesResponse {
+    fn default() -> Self {
+        Self {
+            show_safe: true,
+            show_questionable: true,
+            show_explicit: true,
+            tag_blacklist: String::new(),
+            mark_blacklisted: false,
+            public_favorites: false,
+        }
+    }
+}
+
+impl From<user_preference::Model> for GetPreferencesResponse {
+    fn from(preference: user_preference::Model) -> Self {
+        Self {
+            show_safe: preference.show_safe,
+            show_questionable: preference.show_questionable,
+            show_explicit: preference.show_explicit,
+            tag_blacklist: preference.tag_blacklist,
+            mark_blacklisted: preference.mark_blacklisted,
+            public_favorites: preference.public_favorites,
+        }
+    }
+}
+
 /// The response for `GET /user/:id`
 #[derive(Debug, Serialize, FieldFilterable)]
 #[field_filterable_on(user_account::Model)]
@@ -132,36 +195,45 @@ pub async fn get_user(
     state: Extension<Arc<State>>,
     auth: Auth,
 ) -> Result<Response<Body>, MixiniError> {
-    let maybe_user = UserAccount::find_by_id(id).one(&state.db).await?;
+    let user = find_resource::<UserAccount>(&state.db, id).await?;
+    let authorized_fields = auth
+        .authorized_fields(&state.oso, Read, user.to_owned())
+        .await?;
 
-    match maybe_user {
-        Some(user) => {
-            let authorized_fields: HashSet<String> = if let Auth::KnownUser(this_user) = auth {
-                state
-                    .oso
-                    .lock()
-                    .await
-                    .authorized_fields(this_user, Read, user.to_owned())?
-            } else {
-                state
-                    .oso
-                    .lock()
-                    .await
-                    .authorized_fields("guest", Read, user.to_owned())?
-            };
+    let res_body = GetUserResponse::field_filter(user, authorized_fields);
 
-            let res_body = GetUserResponse::field_filter(user, authorized_fields);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
+}
 
-            Ok(Response::builder()
-                .status(StatusCode::OK)
-                .body(Body::from(serde_json::to_vec(&res_body)?))
-                .unwrap())
-        }
-        None => Ok(Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap()),
+/// Handler for `GET /user`
+pub async fn list_users(
+    Query(query): Query<ListUsersQuery>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let condition = authorized_condition::<UserAccount, _>(&state.oso, &auth, Read).await?;
+    let users = UserAccount::find()
+        .filter(condition)
+        .order_by_asc(user_account::Column::CreatedAt)
+        .paginate(&state.db, USERS_PER_PAGE)
+        .fetch_page(query.page)
+        .await?;
+
+    let mut res_body = Vec::with_capacity(users.len());
+    for user in users {
+        let authorized_fields = auth
+            .authorized_fields(&state.oso, Read, user.to_owned())
+            .await?;
+        res_body.push(GetUserResponse::field_filter(user, authorized_fields));
     }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
 }
 
 /// Handler for `PUT /user/:id`
@@ -171,100 +243,51 @@ pub async fn update_user(
     state: Extension<Arc<State>>,
     auth: Auth,
 ) -> Result<Response<Body>, MixiniError> {
-    match auth {
-        Auth::KnownUser(this_user) => {
-            let user = if let Some(user) = UserAccount::find_by_id(id).one(&state.db).await? {
-                user
-            } else {
-                return Ok(Response::builder()
-                    .status(StatusCode::FORBIDDEN)
-                    .body(Body::empty())
-                    .unwrap());
-            };
+    let user = find_resource::<UserAccount>(&state.db, id).await?;
+    auth.authorize_fields(
+        &state.oso,
+        update_user.to_owned(),
+        user.to_owned(),
+        &update_user.fields(),
+    )
+    .await?;
 
-            if state.oso.lock().await.is_allowed(
-                this_user,
-                update_user.to_owned(),
-                user.to_owned(),
-            )? {
-                // TODO: When UpdateUser -> ActiveModel works, change this
-                // https://github.com/SeaQL/sea-orm/issues/547
-                let mut user: user_account::ActiveModel = user.into();
-                if let Some(name) = update_user.name {
-                    user.name = Set(name);
-                }
-                if let Some(email) = update_user.email {
-                    user.email = Set(email);
-                }
-                if let Some(role) = update_user.role {
-                    user.role = Set(role);
-                }
-                user.update(&state.db).await?;
-                Ok(Response::builder()
-                    .status(StatusCode::OK)
-                    .body(Body::empty())
-                    .unwrap())
-            } else {
-                Ok(Response::builder()
-                    .status(StatusCode::FORBIDDEN)
-                    .body(Body::empty())
-                    .unwrap())
-            }
-        }
-        Auth::UnknownUser => Ok(Response::builder()
-            .status(StatusCode::UNAUTHORIZED)
-            .body(Body::empty())
-            .unwrap()),
+    // TODO: When UpdateUser -> ActiveModel works, change this
+    // https://github.com/SeaQL/sea-orm/issues/547
+    let mut user: user_account::ActiveModel = user.into();
+    if let Some(name) = update_user.name {
+        user.name = Set(name);
+    }
+    if let Some(email) = update_user.email {
+        user.email = Set(email);
+    }
+    if let Some(role) = update_user.role {
+        user.role = Set(role);
     }
+    user.update(&state.db).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
 }
 
 /// Handler for `DELETE /user/:id`
 pub async fn delete_user(
-    Path(id): Path<Uuid>,
+    authorized: Authorized<Delete, UserAccount>,
     TypedHeader(cookie): TypedHeader<Cookie>,
     state: Extension<Arc<State>>,
-    auth: Auth,
 ) -> Result<Response<Body>, MixiniError> {
-    match auth {
-        Auth::KnownUser(this_user) => {
-            let user = if let Some(user) = UserAccount::find_by_id(id).one(&state.db).await? {
-                user
-            } else {
-                return Ok(Response::builder()
-                    .status(StatusCode::FORBIDDEN)
-                    .body(Body::empty())
-                    .unwrap());
-            };
+    authorized.resource.delete(&state.db).await?;
 
-            if state
-                .oso
-                .lock()
-                .await
-                .is_allowed(this_user, Delete, user.to_owned())?
-            {
-                user.delete(&state.db).await?;
+    // also delete cookie in store
+    let base_key = cookie.get(SESSION_COOKIE_NAME).expect("cookie monster!?");
+    let prefixed_key = format!("{}{}", SESSION_KEY_PREFIX, &base_key);
+    state.redis_manager.to_owned().del(&prefixed_key).await?;
 
-                // also delete cookie in store
-                let base_key = cookie.get(SESSION_COOKIE_NAME).expect("cookie monster!?");
-                let prefixed_key = format!("{}{}", SESSION_KEY_PREFIX, &base_key);
-                state.redis_manager.to_owned().del(&prefixed_key).await?;
-
-                Ok(Response::builder()
-                    .status(StatusCode::OK)
-                    .body(Body::empty())
-                    .unwrap())
-            } else {
-                Ok(Response::builder()
-                    .status(StatusCode::FORBIDDEN)
-                    .body(Body::empty())
-                    .unwrap())
-            }
-        }
-        Auth::UnknownUser => Ok(Response::builder()
-            .status(StatusCode::UNAUTHORIZED)
-            .body(Body::empty())
-            .unwrap()),
-    }
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
 }
 
 /// Handler for `POST /user/verify`
@@ -304,7 +327,7 @@ pub async fn create_verify_user(
                     .unwrap())
             }
         }
-        Auth::UnknownUser => Ok(Response::builder()
+        Auth::UnknownUser(_) => Ok(Response::builder()
             .status(StatusCode::UNAUTHORIZED)
             .body(Body::empty())
             .unwrap()),
@@ -349,3 +372,79 @@ pub async fn update_verify_user(
             .unwrap()),
     }
 }
+
+/// Handler for `GET /user/preferences`
+pub async fn get_preferences(
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let user_id = match auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+
+    let res_body = UserPreference::find_by_id(user_id)
+        .one(&state.db)
+        .await?
+        .map(GetPreferencesResponse::from)
+        .unwrap_or_default();
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_vec(&res_body)?))
+        .unwrap())
+}
+
+/// Handler for `PUT /user/preferences`
+pub async fn update_preferences(
+    ValidatedForm(update): ValidatedForm<UpdatePreferences>,
+    state: Extension<Arc<State>>,
+    auth: Auth,
+) -> Result<Response<Body>, MixiniError> {
+    let user_id = match auth {
+        Auth::KnownUser(this_user) => this_user.id,
+        Auth::UnknownUser(_) => return Err(MixiniError::Unauthorized),
+    };
+    if let Some(tag_blacklist) = &update.tag_blacklist {
+        parse_blacklist(tag_blacklist)?;
+    }
+
+    let existing = UserPreference::find_by_id(user_id).one(&state.db).await?;
+    let is_new = existing.is_none();
+    // preferences that aren't set on a new row take the defaults of users without any
+    let mut preference: user_preference::ActiveModel = match existing {
+        Some(preference) => preference.into(),
+        None => user_preference::ActiveModel {
+            user_id: Set(user_id),
+            ..Default::default()
+        },
+    };
+    if let Some(show_safe) = update.show_safe {
+        preference.show_safe = Set(show_safe);
+    }
+    if let Some(show_questionable) = update.show_questionable {
+        preference.show_questionable = Set(show_questionable);
+    }
+    if let Some(show_explicit) = update.show_explicit {
+        preference.show_explicit = Set(show_explicit);
+    }
+    if let Some(tag_blacklist) = update.tag_blacklist {
+        preference.tag_blacklist = Set(tag_blacklist);
+    }
+    if let Some(mark_blacklisted) = update.mark_blacklisted {
+        preference.mark_blacklisted = Set(mark_blacklisted);
+    }
+    if let Some(public_favorites) = update.public_favorites {
+        preference.public_favorites = Set(public_favorites);
+    }
+    if is_new {
+        preference.insert(&state.db).await?;
+    } else {
+        preference.update(&state.db).await?;
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::empty())
+        .unwrap())
+}
@@ -0,0 +1,132 @@
+//! Keeping imports from reaching the server's own network.
+//!
+//! Every address an import connects to is checked, whether it's written literally in a URL,
+//! resolved from a host name or reached through a redirect. Host names are resolved by
+//! `HostGuard` itself, so the addresses it checks are the ones that are connected to.
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, Url,
+};
+use std::{
+    collections::HashSet,
+    error::Error as StdError,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use crate::constants::IMPORT_MAX_REDIRECTS;
+
+/// Why a request was refused, found among the sources of the `reqwest::Error` it causes.
+#[derive(Debug)]
+pub struct ForbiddenAddress;
+
+impl fmt::Display for ForbiddenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Connecting to this address is not allowed")
+    }
+}
+
+impl StdError for ForbiddenAddress {}
+
+/// Decides which addresses imports may connect to.
+#[derive(Debug, Clone, Default)]
+pub struct HostGuard {
+    /// Private addresses that are allowed anyway.
+    allowed: HashSet<IpAddr>,
+}
+
+impl HostGuard {
+    /// A guard that also allows the private addresses `allowed`.
+    pub fn allowing(allowed: impl IntoIterator<Item = IpAddr>) -> Self {
+        Self {
+            allowed: allowed.into_iter().collect(),
+        }
+    }
+
+    /// Whether imports may connect to `ip`.
+    pub fn allows_ip(&self, ip: IpAddr) -> bool {
+        !is_private_ip(ip) || self.allowed.contains(&ip)
+    }
+
+    /// Whether imports may request `url`, judging by its host as written. Host names are
+    /// checked once they're resolved.
+    pub fn allows_url(&self, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+        match url.host_str() {
+            Some(host) => match host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+            {
+                Ok(ip) => self.allows_ip(ip),
+                Err(_) => true,
+            },
+            None => false,
+        }
+    }
+
+    /// A redirect policy checking every hop with this guard.
+    pub fn redirect_policy(self: Arc<Self>) -> redirect::Policy {
+        redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= IMPORT_MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if !self.allows_url(attempt.url()) {
+                attempt.error(ForbiddenAddress)
+            } else {
+                attempt.follow()
+            }
+        })
+    }
+}
+
+impl Resolve for HostGuard {
+    fn resolve(&self, name: Name) -> Resolving {
+        let guard = self.clone();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            // a name resolving to any forbidden address is refused outright, rather than
+            // connecting to whichever of its addresses are allowed
+            if addrs.iter().any(|addr| !guard.allows_ip(addr.ip())) {
+                return Err(Box::new(ForbiddenAddress) as Box<dyn StdError + Send + Sync>);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is on a loopback, private, link-local or otherwise non-public network.
+pub fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_ipv4(ip),
+        IpAddr::V6(ip) => is_private_ipv6(ip),
+    }
+}
+
+fn is_private_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // 0.0.0.0/8, "this network"
+        || a == 0
+        // 100.64.0.0/10, shared address space
+        || (a == 100 && b & 0xc0 == 64)
+}
+
+fn is_private_ipv6(ip: Ipv6Addr) -> bool {
+    let [first, ..] = ip.segments();
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // fc00::/7, unique local
+        || first & 0xfe00 == 0xfc00
+        // fe80::/10, link-local
+        || first & 0xffc0 == 0xfe80
+        // IPv4-mapped (::ffff:0:0/96) and IPv4-compatible (::/96) addresses
+        || ip.to_ipv4().map_or(false, is_private_ipv4)
+}
@@ -0,0 +1,258 @@
+//! Importing art and its metadata from external sites.
+//!
+//! Each site is handled by a `SourceImporter`, and every importer is kept in an
+//! `ImporterRegistry` on the server `State`. Importers are tried in the order they were
+//! registered, so site-specific importers should be registered before generic ones.
+use axum::async_trait;
+use entity::sea_orm_active_enums::PostRating;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use thiserror::Error;
+
+use crate::{
+    constants::{IMPORTER_USER_AGENT, IMPORT_MAX_RESPONSE_BYTES, IMPORT_TIMEOUT_SECS},
+    tags::{normalize_tag_name, validate_tag_name, MAX_TAGS_PER_POST},
+};
+
+pub mod guard;
+pub mod opengraph;
+
+pub use guard::{is_private_ip, HostGuard};
+pub use opengraph::OpenGraphImporter;
+
+/// Any error while importing from a source.
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("No importer supports this URL")]
+    UnsupportedUrl,
+
+    #[error("Importing from this host is not allowed")]
+    ForbiddenHost,
+
+    #[error("The source responded with {0}")]
+    BadStatus(reqwest::StatusCode),
+
+    #[error(
+        "The source response is larger than {} bytes",
+        IMPORT_MAX_RESPONSE_BYTES
+    )]
+    TooLarge,
+
+    #[error("The source is missing {0}")]
+    MissingMetadata(&'static str),
+
+    #[error(transparent)]
+    Http(reqwest::Error),
+}
+
+impl From<reqwest::Error> for ImportError {
+    fn from(e: reqwest::Error) -> Self {
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            if cause.is::<guard::ForbiddenAddress>() {
+                return Self::ForbiddenHost;
+            }
+            source = cause.source();
+        }
+        Self::Http(e)
+    }
+}
+
+/// Metadata about a single artwork, as found on its source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SourceMetadata {
+    /// The canonical URL of the artwork on its source.
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The artist's name on the source.
+    pub author: Option<String>,
+    /// The URL of the artwork's media itself.
+    pub media_url: Option<String>,
+    /// Whether the source marks the artwork as adult content.
+    pub adult: bool,
+    /// Tags or keywords the source lists, as written there.
+    pub keywords: Vec<String>,
+}
+
+/// The fields of a new post and its tags, mapped from `SourceMetadata`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedPost {
+    pub title: String,
+    pub description: String,
+    pub source_urls: Vec<String>,
+    pub rating: PostRating,
+    pub media_url: Option<String>,
+    /// Valid, normalized tag names.
+    pub tags: Vec<String>,
+}
+
+/// An importer for some set of external sites.
+#[async_trait]
+pub trait SourceImporter: Send + Sync {
+    /// A short, unique name for this importer, e.g. `opengraph`.
+    fn name(&self) -> &'static str;
+
+    /// Whether this importer can import from `url`.
+    fn matches(&self, url: &Url) -> bool;
+
+    /// Fetch the metadata of the artwork at `url`.
+    async fn fetch(&self, client: &ImportClient, url: &Url) -> Result<SourceMetadata, ImportError>;
+
+    /// Map fetched metadata to the fields of a new post.
+    ///
+    /// By default keywords become tags, skipping any that aren't valid tag names, and the
+    /// author becomes a tag too.
+    fn map(&self, metadata: SourceMetadata) -> ImportedPost {
+        let mut tags: Vec<String> = Vec::new();
+        for name in metadata.author.iter().chain(metadata.keywords.iter()) {
+            let name = normalize_tag_name(name);
+            if validate_tag_name(&name).is_ok() && !tags.contains(&name) {
+                tags.push(name);
+            }
+        }
+        tags.truncate(MAX_TAGS_PER_POST);
+
+        ImportedPost {
+            title: truncate(
+                metadata.title.as_deref().unwrap_or(metadata.url.as_str()),
+                256,
+            ),
+            description: truncate(metadata.description.as_deref().unwrap_or_default(), 10000),
+            source_urls: vec![metadata.url],
+            rating: if metadata.adult {
+                PostRating::Explicit
+            } else {
+                PostRating::Safe
+            },
+            media_url: metadata.media_url,
+            tags,
+        }
+    }
+}
+
+/// Truncate `text` to at most `max` characters.
+fn truncate(text: &str, max: usize) -> String {
+    text.trim().chars().take(max).collect()
+}
+
+/// The HTTP client importers share, refusing to connect to any address its `HostGuard` doesn't
+/// allow.
+#[derive(Debug, Clone)]
+pub struct ImportClient {
+    client: Client,
+    guard: Arc<HostGuard>,
+}
+
+impl ImportClient {
+    /// Attempt to create a client guarded by `guard`.
+    pub fn try_new(guard: HostGuard) -> Result<Self, ImportError> {
+        let guard = Arc::new(guard);
+        let client = Client::builder()
+            .user_agent(IMPORTER_USER_AGENT)
+            .timeout(Duration::from_secs(IMPORT_TIMEOUT_SECS))
+            .redirect(guard.clone().redirect_policy())
+            .dns_resolver(guard.clone())
+            .build()?;
+        Ok(Self { client, guard })
+    }
+}
+
+/// Every importer the server knows of, along with the HTTP client they share.
+pub struct ImporterRegistry {
+    client: ImportClient,
+    importers: Vec<Box<dyn SourceImporter>>,
+}
+
+impl std::fmt::Debug for ImporterRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImporterRegistry")
+            .field(
+                "importers",
+                &self.importers.iter().map(|i| i.name()).collect::<Vec<_>>(),
+            )
+            .field("guard", &self.client.guard)
+            .finish()
+    }
+}
+
+impl ImporterRegistry {
+    /// Attempt to create an empty registry.
+    pub fn try_new() -> Result<Self, ImportError> {
+        Ok(Self {
+            client: ImportClient::try_new(HostGuard::default())?,
+            importers: Vec::new(),
+        })
+    }
+
+    /// Attempt to create a registry with every built-in importer.
+    pub fn try_default() -> Result<Self, ImportError> {
+        let mut registry = Self::try_new()?;
+        // NOTE: register site-specific importers here, before the generic ones
+        registry.register(OpenGraphImporter);
+        Ok(registry)
+    }
+
+    /// Add an importer, tried after every importer added before it.
+    pub fn register(&mut self, importer: impl SourceImporter + 'static) {
+        self.importers.push(Box::new(importer));
+    }
+
+    /// Allow importing from the loopback or private network addresses `hosts`, which are
+    /// otherwise rejected so imports can't be used to probe the server's network.
+    pub fn allow_hosts(
+        &mut self,
+        hosts: impl IntoIterator<Item = IpAddr>,
+    ) -> Result<(), ImportError> {
+        self.client = ImportClient::try_new(HostGuard::allowing(hosts))?;
+        Ok(())
+    }
+
+    /// The first importer that can import from `url`.
+    pub fn find(&self, url: &Url) -> Option<&dyn SourceImporter> {
+        self.importers
+            .iter()
+            .find(|importer| importer.matches(url))
+            .map(|importer| importer.as_ref())
+    }
+
+    /// Fetch and map the artwork at `url` with the first importer that supports it.
+    pub async fn import(&self, url: &str) -> Result<ImportedPost, ImportError> {
+        let url = Url::parse(url).map_err(|_| ImportError::UnsupportedUrl)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ImportError::UnsupportedUrl);
+        }
+        if !self.client.guard.allows_url(&url) {
+            return Err(ImportError::ForbiddenHost);
+        }
+
+        let importer = self.find(&url).ok_or(ImportError::UnsupportedUrl)?;
+        let metadata = importer.fetch(&self.client, &url).await?;
+        tracing::debug!("Imported {} with the {} importer", url, importer.name());
+        Ok(importer.map(metadata))
+    }
+}
+
+/// Fetch the body of `url` as text, refusing responses over `IMPORT_MAX_RESPONSE_BYTES`.
+pub async fn fetch_text(client: &ImportClient, url: &Url) -> Result<String, ImportError> {
+    // addresses written literally are never resolved, so they're checked here instead
+    if !client.guard.allows_url(url) {
+        return Err(ImportError::ForbiddenHost);
+    }
+
+    let mut response = client.client.get(url.to_owned()).send().await?;
+    if !response.status().is_success() {
+        return Err(ImportError::BadStatus(response.status()));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > IMPORT_MAX_RESPONSE_BYTES {
+            return Err(ImportError::TooLarge);
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
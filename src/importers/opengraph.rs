@@ -0,0 +1,188 @@
+//! A generic importer for any page with OpenGraph metadata or an oEmbed endpoint.
+use axum::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::importers::{fetch_text, ImportClient, ImportError, SourceImporter, SourceMetadata};
+
+lazy_static! {
+    static ref RE_META_TAG: Regex = Regex::new(r"(?is)<(meta|link)\s[^>]*>").unwrap();
+    static ref RE_ATTRIBUTE: Regex =
+        Regex::new(r#"(?s)([a-zA-Z:_-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+}
+
+/// The subset of an oEmbed response this importer uses.
+#[derive(Debug, Deserialize)]
+struct OEmbed {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    title: Option<String>,
+    author_name: Option<String>,
+    /// The media itself, for `photo` responses.
+    url: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+/// Imports from any HTML page, preferring its oEmbed endpoint and falling back to OpenGraph.
+///
+/// It matches every URL, so it should be registered last.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenGraphImporter;
+
+#[async_trait]
+impl SourceImporter for OpenGraphImporter {
+    fn name(&self) -> &'static str {
+        "opengraph"
+    }
+
+    fn matches(&self, _url: &Url) -> bool {
+        true
+    }
+
+    async fn fetch(&self, client: &ImportClient, url: &Url) -> Result<SourceMetadata, ImportError> {
+        let page = fetch_text(client, url).await?;
+        let head = parse_head(&page);
+
+        let mut metadata = SourceMetadata {
+            url: head
+                .property("og:url")
+                .and_then(|og_url| url.join(og_url).ok())
+                .unwrap_or_else(|| url.to_owned())
+                .to_string(),
+            title: head
+                .property("og:title")
+                .or_else(|| head.property("twitter:title"))
+                .map(str::to_owned),
+            description: head
+                .property("og:description")
+                .or_else(|| head.property("description"))
+                .map(str::to_owned),
+            author: head
+                .property("article:author")
+                .or_else(|| head.property("author"))
+                .map(str::to_owned),
+            media_url: head
+                .property("og:image")
+                .and_then(|image| url.join(image).ok())
+                .map(String::from),
+            adult: head
+                .property("rating")
+                .map_or(false, |rating| rating.eq_ignore_ascii_case("adult")),
+            keywords: head
+                .properties("article:tag")
+                .into_iter()
+                .map(str::to_owned)
+                .chain(
+                    head.property("keywords")
+                        .map(|keywords| {
+                            keywords
+                                .split(',')
+                                .map(|keyword| keyword.trim().to_owned())
+                                .filter(|keyword| !keyword.is_empty())
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default(),
+                )
+                .collect(),
+        };
+
+        // only follow oEmbed links on the same host, so a page can't point imports elsewhere
+        if let Some(oembed_url) = head
+            .oembed
+            .as_deref()
+            .and_then(|href| url.join(href).ok())
+            .filter(|oembed_url| oembed_url.host_str() == url.host_str())
+        {
+            let oembed: OEmbed = serde_json::from_str(&fetch_text(client, &oembed_url).await?)
+                .map_err(|_| ImportError::MissingMetadata("a valid oEmbed response"))?;
+            metadata.title = oembed.title.or(metadata.title);
+            metadata.author = oembed.author_name.or(metadata.author);
+            let media_url = match oembed.kind.as_deref() {
+                Some("photo") => oembed.url.or(oembed.thumbnail_url),
+                _ => oembed.thumbnail_url,
+            };
+            if let Some(media_url) = media_url.and_then(|media_url| url.join(&media_url).ok()) {
+                metadata.media_url = Some(media_url.to_string());
+            }
+        }
+
+        if metadata.title.is_none() && metadata.media_url.is_none() {
+            return Err(ImportError::MissingMetadata("a title or image"));
+        }
+        Ok(metadata)
+    }
+}
+
+/// The `<meta>` and oEmbed `<link>` tags of a page.
+#[derive(Debug, Default)]
+struct Head {
+    /// `(property or name, content)` of every `<meta>` tag, in page order. The two attributes
+    /// share a namespace here, as pages mix them up anyway.
+    meta: Vec<(String, String)>,
+    /// The `href` of the JSON oEmbed `<link>`, if any.
+    oembed: Option<String>,
+}
+
+impl Head {
+    fn properties(&self, key: &str) -> Vec<&str> {
+        self.meta
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, content)| content.as_str())
+            .collect()
+    }
+
+    fn property(&self, key: &str) -> Option<&str> {
+        self.properties(key)
+            .into_iter()
+            .find(|content| !content.is_empty())
+    }
+}
+
+/// Pull every `<meta>` tag and the oEmbed `<link>` out of a page, without parsing the whole page.
+fn parse_head(page: &str) -> Head {
+    let mut head = Head::default();
+
+    for tag in RE_META_TAG.captures_iter(page) {
+        let attributes: HashMap<String, String> = RE_ATTRIBUTE
+            .captures_iter(&tag[0])
+            .map(|attribute| {
+                let value = attribute
+                    .get(2)
+                    .or_else(|| attribute.get(3))
+                    .map_or("", |value| value.as_str());
+                (attribute[1].to_lowercase(), decode_entities(value))
+            })
+            .collect();
+
+        if tag[1].eq_ignore_ascii_case("meta") {
+            let key = attributes
+                .get("property")
+                .or_else(|| attributes.get("name"));
+            if let (Some(key), Some(content)) = (key, attributes.get("content")) {
+                head.meta.push((key.to_owned(), content.trim().to_owned()));
+            }
+        } else if head.oembed.is_none()
+            && attributes.get("type").map(String::as_str) == Some("application/json+oembed")
+        {
+            head.oembed = attributes.get("href").cloned();
+        }
+    }
+
+    head
+}
+
+/// Decode the handful of HTML entities that show up in attribute values.
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
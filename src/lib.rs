@@ -0,0 +1,28 @@
+#![warn(
+    missing_debug_implementations,
+    unreachable_pub,
+    future_incompatible,
+    rust_2018_idioms,
+    rust_2021_compatibility
+)]
+
+pub mod actions;
+pub mod auth;
+pub mod collections;
+pub mod comments;
+pub mod constants;
+pub mod error;
+pub mod filtering;
+pub mod handlers;
+pub mod importers;
+pub mod media;
+pub mod permissions;
+pub mod policy;
+pub mod preferences;
+pub mod revisions;
+pub mod search;
+pub mod server;
+pub mod tags;
+pub mod utils;
+
+pub const DEV_BUILD: bool = cfg!(debug_assertions);
@@ -1,20 +1,4 @@
-#![warn(
-    missing_debug_implementations,
-    unreachable_pub,
-    future_incompatible,
-    rust_2018_idioms,
-    rust_2021_compatibility
-)]
-
-pub mod actions;
-pub mod auth;
-pub mod constants;
-pub mod error;
-pub mod handlers;
-pub mod server;
-pub mod utils;
-
-pub const DEV_BUILD: bool = cfg!(debug_assertions);
+use mixini_server::{server, DEV_BUILD};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
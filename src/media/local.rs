@@ -0,0 +1,94 @@
+//! Storing media on the local filesystem.
+use axum::async_trait;
+use std::{
+    io::SeekFrom,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+use ulid::Ulid;
+
+use crate::media::{validate_key, MediaObject, MediaStore, StoreError};
+
+/// Stores media in a directory, sharded by the first two pairs of characters of each key so no
+/// single directory grows too large: `ab12cd...` is stored at `root/ab/12/ab12cd...`.
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Where the file under `key` is stored.
+    pub fn path(&self, key: &str) -> Result<PathBuf, StoreError> {
+        validate_key(key)?;
+        Ok(self.root.join(&key[..2]).join(&key[2..4]).join(key))
+    }
+}
+
+#[async_trait]
+impl MediaStore for LocalStore {
+    async fn put_file(
+        &self,
+        key: &str,
+        source: &Path,
+        _content_type: &str,
+    ) -> Result<(), StoreError> {
+        let path = self.path(key)?;
+        let dir = path.parent().expect("sharded paths have a parent");
+        tokio::fs::create_dir_all(dir).await?;
+
+        // copy next to the destination first, so readers never see a partly written file
+        let partial = dir.join(format!(".{}.{}", key, Ulid::new()));
+        if let Err(e) = tokio::fs::copy(source, &partial).await {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e.into());
+        }
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<MediaObject, StoreError> {
+        let mut file = File::open(self.path(key)?).await?;
+        let size = file.metadata().await?.len();
+
+        let range = match range {
+            Some(range) if range.start >= range.end || range.start >= size => {
+                return Err(StoreError::InvalidRange)
+            }
+            Some(range) => range.start..range.end.min(size),
+            None => 0..size,
+        };
+        if range.start > 0 {
+            file.seek(SeekFrom::Start(range.start)).await?;
+        }
+
+        Ok(MediaObject {
+            size,
+            body: Box::pin(ReaderStream::new(file.take(range.end - range.start))),
+            range,
+        })
+    }
+
+    async fn size(&self, key: &str) -> Result<Option<u64>, StoreError> {
+        match tokio::fs::metadata(self.path(key)?).await {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,596 @@
+//! Reading and removing metadata embedded in uploaded images.
+//!
+//! Photos often carry GPS coordinates, device serial numbers, owner names and editing history in
+//! EXIF, XMP and PNG text chunks, none of which should be served to everyone who views a post. A
+//! few fields are worth showing, so they're read out first, and then every metadata block is
+//! dropped from the file before it's stored. Only what's needed to display the image correctly is
+//! kept: color profiles, and the EXIF orientation, which is written back on its own.
+use chrono::{DateTime, NaiveDateTime};
+use entity::sea_orm_active_enums::MediaFormat;
+use exif::{In, Tag, Value};
+use lazy_static::lazy_static;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, path::Path};
+use thiserror::Error;
+
+use crate::{
+    constants::MAX_EMBEDDED_TEXT_CHARS,
+    error::MixiniError,
+    media::{ReceivedMedia, TempFile},
+};
+
+/// The useful fields found in an image's metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmbeddedMetadata {
+    /// The make and model of the camera.
+    pub camera: Option<String>,
+    /// The program that created or last edited the image.
+    pub software: Option<String>,
+    /// When the photo was taken or the image created, in whatever time zone the camera was set to.
+    pub taken_at: Option<NaiveDateTime>,
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+}
+
+impl EmbeddedMetadata {
+    /// Fill in the fields `self` is missing from `other`.
+    fn merge(&mut self, other: EmbeddedMetadata) {
+        self.camera = self.camera.take().or(other.camera);
+        self.software = self.software.take().or(other.software);
+        self.taken_at = self.taken_at.or(other.taken_at);
+        self.artist = self.artist.take().or(other.artist);
+        self.copyright = self.copyright.take().or(other.copyright);
+    }
+}
+
+/// The image's container couldn't be parsed, so its metadata can't be removed.
+#[derive(Debug, Error)]
+#[error("The image file is malformed")]
+pub struct MalformedMedia;
+
+/// Everything collected while stripping a file.
+#[derive(Debug, Default)]
+struct Found {
+    metadata: EmbeddedMetadata,
+    /// The EXIF orientation, if there was one.
+    orientation: Option<u32>,
+}
+
+impl Found {
+    fn exif(&mut self, tiff: &[u8]) {
+        let exif = match exif::Reader::new().read_raw(tiff.to_vec()) {
+            Ok(exif) => exif,
+            Err(e) => {
+                tracing::debug!("Ignoring unreadable EXIF: {:?}", e);
+                return;
+            }
+        };
+        let text = |tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+            Value::Ascii(values) => clean_text(&String::from_utf8_lossy(values.first()?)),
+            _ => None,
+        };
+
+        let taken_at = text(Tag::DateTimeOriginal)
+            .or_else(|| text(Tag::DateTime))
+            .and_then(|date| NaiveDateTime::parse_from_str(&date, "%Y:%m:%d %H:%M:%S").ok());
+
+        self.metadata.merge(EmbeddedMetadata {
+            camera: camera_name(text(Tag::Make), text(Tag::Model)),
+            software: text(Tag::Software),
+            taken_at,
+            artist: text(Tag::Artist),
+            copyright: text(Tag::Copyright),
+        });
+        self.orientation = self.orientation.or_else(|| {
+            exif.get_field(Tag::Orientation, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        });
+    }
+
+    fn xmp(&mut self, xmp: &[u8]) {
+        self.metadata.merge(read_xmp(&String::from_utf8_lossy(xmp)));
+    }
+
+    /// The orientation to write back, if it isn't the default.
+    fn rotated(&self) -> Option<u16> {
+        match self.orientation {
+            Some(orientation @ 2..=8) => Some(orientation as u16),
+            _ => None,
+        }
+    }
+}
+
+/// Remove text control characters and extra whitespace from a metadata field, and truncate it to
+/// `MAX_EMBEDDED_TEXT_CHARS`.
+fn clean_text(text: &str) -> Option<String> {
+    let text: String = text
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_EMBEDDED_TEXT_CHARS)
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// Combine a camera's make and model.
+fn camera_name(make: Option<String>, model: Option<String>) -> Option<String> {
+    match (make, model) {
+        // models usually repeat the make, as in "Canon" and "Canon EOS 5D"
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            Some(model)
+        }
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    }
+}
+
+lazy_static! {
+    /// A simple XMP property, written either as an attribute or as an element.
+    static ref XMP_PROPERTY: Regex = Regex::new(
+        r#"(?:^|[\s<])(tiff:Make|tiff:Model|xmp:CreatorTool|xmp:CreateDate|photoshop:DateCreated)(?:\s*=\s*"([^"]*)"|>([^<]*)</)"#
+    )
+    .unwrap();
+    /// The first item of an XMP array property.
+    static ref XMP_ARRAY: Regex =
+        Regex::new(r#"(?s)<(dc:creator|dc:rights)\b[^>]*>.*?<rdf:li\b[^>]*>([^<]*)</rdf:li>"#)
+            .unwrap();
+}
+
+/// Read the fields of `EmbeddedMetadata` from an XMP packet.
+///
+/// XMP is RDF, which can be written in many equivalent ways, but image editors write these few
+/// properties in one of the forms matched here.
+fn read_xmp(xmp: &str) -> EmbeddedMetadata {
+    let mut properties = HashMap::new();
+    for captures in XMP_PROPERTY
+        .captures_iter(xmp)
+        .chain(XMP_ARRAY.captures_iter(xmp))
+    {
+        let value = captures.get(2).or_else(|| captures.get(3));
+        if let (Some(name), Some(value)) = (captures.get(1), value) {
+            if let Some(value) = clean_text(&unescape_xml(value.as_str())) {
+                properties.entry(name.as_str()).or_insert(value);
+            }
+        }
+    }
+
+    let camera = camera_name(
+        properties.remove("tiff:Make"),
+        properties.remove("tiff:Model"),
+    );
+    let taken_at = properties
+        .remove("xmp:CreateDate")
+        .or_else(|| properties.remove("photoshop:DateCreated"))
+        .and_then(|date| parse_iso_date(&date));
+
+    EmbeddedMetadata {
+        camera,
+        software: properties.remove("xmp:CreatorTool"),
+        taken_at,
+        artist: properties.remove("dc:creator"),
+        copyright: properties.remove("dc:rights"),
+    }
+}
+
+/// Parse an ISO 8601 date and time, ignoring fractional seconds and any time zone.
+fn parse_iso_date(date: &str) -> Option<NaiveDateTime> {
+    let date = date.get(..19).unwrap_or(date);
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S").ok()
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// A big-endian TIFF structure holding nothing but an orientation.
+fn orientation_tiff(orientation: u16) -> Vec<u8> {
+    let mut tiff = Vec::with_capacity(26);
+    tiff.extend_from_slice(b"MM\0\x2A");
+    // the offset of the only IFD, right after the header
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    // tag, type SHORT, count, and the value padded to 4 bytes
+    tiff.extend_from_slice(&0x0112u16.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // no next IFD
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff
+}
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Read the useful metadata out of an image and return a copy of it with all metadata removed.
+pub fn strip_metadata(
+    bytes: &[u8],
+    format: &MediaFormat,
+) -> Result<(Vec<u8>, EmbeddedMetadata), MalformedMedia> {
+    let mut found = Found::default();
+    let stripped = match format {
+        MediaFormat::Jpeg => strip_jpeg(bytes, &mut found),
+        MediaFormat::Png => strip_png(bytes, &mut found),
+        MediaFormat::Webp => strip_webp(bytes, &mut found),
+        MediaFormat::Gif => strip_gif(bytes, &mut found),
+    }
+    .ok_or(MalformedMedia)?;
+    Ok((stripped, found.metadata))
+}
+
+fn u16_be(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Copy the segments of a JPEG, dropping every application segment but the JFIF header, color
+/// profiles and Adobe's color transform, and comments. Anything after the end of the image, like
+/// the extra images of multi-picture files, is dropped too.
+fn strip_jpeg(bytes: &[u8], found: &mut Found) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..2]);
+    // the EXIF segment goes after the JFIF header, which must come first
+    let mut exif_at = out.len();
+
+    let mut at = 2;
+    loop {
+        if *bytes.get(at)? != 0xFF {
+            return None;
+        }
+        // any number of 0xFF may pad a marker
+        while *bytes.get(at + 1)? == 0xFF {
+            at += 1;
+        }
+        let marker = bytes[at + 1];
+        match marker {
+            // end of image
+            0xD9 => {
+                out.extend_from_slice(&[0xFF, 0xD9]);
+                break;
+            }
+            // markers without a segment
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&[0xFF, marker]);
+                at += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        // the length includes its own two bytes
+        let len = u16_be(bytes, at + 2)? as usize;
+        if len < 2 {
+            return None;
+        }
+        let end = at + 2 + len;
+        let segment = bytes.get(at..end)?;
+        let data = &segment[4..];
+        let keep = match marker {
+            0xE0 => true,
+            0xE1 => {
+                if let Some(tiff) = data.strip_prefix(EXIF_HEADER) {
+                    found.exif(tiff);
+                } else if let Some(xmp) = data.strip_prefix(XMP_HEADER) {
+                    found.xmp(xmp);
+                }
+                false
+            }
+            0xE2 => data.starts_with(b"ICC_PROFILE\0"),
+            0xEE => data.starts_with(b"Adobe"),
+            0xE3..=0xED | 0xEF | 0xFE => false,
+            _ => true,
+        };
+        if keep {
+            let first = out.len() == 2;
+            out.extend_from_slice(segment);
+            if marker == 0xE0 && first {
+                exif_at = out.len();
+            }
+        }
+        at = end;
+
+        // a scan header is followed by entropy-coded data, which ends at the next marker that
+        // isn't a stuffed 0xFF or a restart marker
+        if marker == 0xDA {
+            let start = at;
+            while at < bytes.len() {
+                if bytes[at] == 0xFF {
+                    match bytes.get(at + 1) {
+                        Some(0x00 | 0xD0..=0xD7) => at += 2,
+                        Some(0xFF) => at += 1,
+                        _ => break,
+                    }
+                } else {
+                    at += 1;
+                }
+            }
+            out.extend_from_slice(&bytes[start..at]);
+            // a truncated file still displays, so keep what there is of it
+            if at >= bytes.len() {
+                break;
+            }
+        }
+    }
+
+    if let Some(orientation) = found.rotated() {
+        let tiff = orientation_tiff(orientation);
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend_from_slice(&((2 + EXIF_HEADER.len() + tiff.len()) as u16).to_be_bytes());
+        segment.extend_from_slice(EXIF_HEADER);
+        segment.extend_from_slice(&tiff);
+        out.splice(exif_at..exif_at, segment);
+    }
+    Some(out)
+}
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Append a PNG chunk to `out`.
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+/// Copy the chunks of a PNG, dropping text and EXIF chunks and anything after the end of the
+/// image.
+fn strip_png(bytes: &[u8], found: &mut Found) -> Option<Vec<u8>> {
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(PNG_SIGNATURE);
+    let mut exif_at = None;
+    let mut text = EmbeddedMetadata::default();
+
+    let mut at = PNG_SIGNATURE.len();
+    loop {
+        let end = at + 12 + u32_be(bytes, at)? as usize;
+        let chunk = bytes.get(at..end)?;
+        let (kind, data) = (&chunk[4..8], &chunk[8..chunk.len() - 4]);
+        match kind {
+            b"eXIf" => found.exif(data),
+            b"tEXt" => {
+                if let Some((keyword, value)) = split_at_nul(data) {
+                    // tEXt is Latin-1
+                    let value: String = value.iter().map(|&b| b as char).collect();
+                    text.merge(read_png_text(keyword, &value));
+                }
+            }
+            b"iTXt" => {
+                if let Some((keyword, rest)) = split_at_nul(data) {
+                    // compressed text is dropped without being read
+                    if let [0, _, rest @ ..] = rest {
+                        let value = split_at_nul(rest)
+                            .and_then(|(_language, rest)| split_at_nul(rest))
+                            .map(|(_translated_keyword, value)| value);
+                        if let Some(value) = value {
+                            if keyword == b"XML:com.adobe.xmp" {
+                                found.xmp(value);
+                            } else {
+                                text.merge(read_png_text(keyword, &String::from_utf8_lossy(value)));
+                            }
+                        }
+                    }
+                }
+            }
+            b"zTXt" => {}
+            _ => out.extend_from_slice(chunk),
+        }
+        if kind == b"IHDR" {
+            exif_at = Some(out.len());
+        }
+        at = end;
+        if kind == b"IEND" {
+            break;
+        }
+    }
+    found.metadata.merge(text);
+
+    if let Some(orientation) = found.rotated() {
+        let mut chunk = Vec::new();
+        write_png_chunk(&mut chunk, b"eXIf", &orientation_tiff(orientation));
+        let exif_at = exif_at?;
+        out.splice(exif_at..exif_at, chunk);
+    }
+    Some(out)
+}
+
+fn split_at_nul(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let nul = bytes.iter().position(|&b| b == 0)?;
+    Some((&bytes[..nul], &bytes[nul + 1..]))
+}
+
+/// Read one of the PNG text keywords that match a field of `EmbeddedMetadata`.
+fn read_png_text(keyword: &[u8], value: &str) -> EmbeddedMetadata {
+    let mut metadata = EmbeddedMetadata::default();
+    match keyword {
+        b"Author" => metadata.artist = clean_text(value),
+        b"Copyright" => metadata.copyright = clean_text(value),
+        b"Software" => metadata.software = clean_text(value),
+        // the device used to create the image
+        b"Source" => metadata.camera = clean_text(value),
+        // recommended to be RFC 1123, but often ISO 8601
+        b"Creation Time" => {
+            metadata.taken_at = DateTime::parse_from_rfc2822(value.trim())
+                .map(|date| date.naive_local())
+                .ok()
+                .or_else(|| parse_iso_date(value.trim()))
+        }
+        _ => {}
+    }
+    metadata
+}
+
+/// Copy the chunks of a WebP, dropping its EXIF and XMP and anything after the end of the file.
+fn strip_webp(bytes: &[u8], found: &mut Found) -> Option<Vec<u8>> {
+    if bytes.get(..4)? != b"RIFF" || bytes.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let riff_end = (8 + u32_le(bytes, 4)? as usize).min(bytes.len());
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..12]);
+    let mut vp8x_at = None;
+
+    let mut at = 12;
+    while at + 8 <= riff_end {
+        let size = u32_le(bytes, at + 4)? as usize;
+        // chunks are padded to an even size
+        let end = (at + 8 + size + size % 2).min(riff_end);
+        let chunk = bytes.get(at..end)?;
+        let data = chunk.get(8..8 + size)?;
+        match &chunk[..4] {
+            b"EXIF" => found.exif(data.strip_prefix(EXIF_HEADER).unwrap_or(data)),
+            b"XMP " => found.xmp(data),
+            kind => {
+                if kind == b"VP8X" {
+                    vp8x_at = Some(out.len());
+                }
+                out.extend_from_slice(chunk);
+            }
+        }
+        at = end;
+    }
+
+    if let Some(vp8x_at) = vp8x_at {
+        // clear the flags saying there's EXIF and XMP
+        *out.get_mut(vp8x_at + 8)? &= !0x0C;
+        if let Some(orientation) = found.rotated() {
+            // EXIF goes after the image data
+            let tiff = orientation_tiff(orientation);
+            out.extend_from_slice(b"EXIF");
+            out.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+            out.extend_from_slice(&tiff);
+            out[vp8x_at + 8] |= 0x08;
+        }
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(out)
+}
+
+/// The position after the data sub-blocks starting at `at`.
+fn skip_gif_sub_blocks(bytes: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let size = *bytes.get(at)? as usize;
+        at += 1 + size;
+        if size == 0 {
+            return Some(at);
+        }
+    }
+}
+
+/// Copy the blocks of a GIF, dropping comments and XMP and anything after the end of the image.
+fn strip_gif(bytes: &[u8], found: &mut Found) -> Option<Vec<u8>> {
+    if !bytes.starts_with(b"GIF8") {
+        return None;
+    }
+    // header and logical screen descriptor
+    let flags = *bytes.get(10)?;
+    let mut at = 13;
+    if flags & 0x80 != 0 {
+        at += 3 << (usize::from(flags & 0x07) + 1);
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(bytes.get(..at)?);
+
+    loop {
+        let start = at;
+        match *bytes.get(at)? {
+            // extension
+            0x21 => {
+                let label = *bytes.get(at + 1)?;
+                at = skip_gif_sub_blocks(bytes, at + 2)?;
+                match label {
+                    // comment
+                    0xFE => continue,
+                    // application extension, which starts with an 11 byte identifier
+                    0xFF if bytes.get(start + 2..start + 14)? == b"\x0BXMP DataXMP" => {
+                        // XMP is written raw rather than in sub-blocks, followed by a trailer
+                        // that makes it parse as sub-blocks
+                        found.xmp(bytes.get(start + 14..at)?);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            // image descriptor, then the local color table and image data
+            0x2C => {
+                let flags = *bytes.get(at + 9)?;
+                at += 10;
+                if flags & 0x80 != 0 {
+                    at += 3 << (usize::from(flags & 0x07) + 1);
+                }
+                // the minimum LZW code size comes before the data
+                at = skip_gif_sub_blocks(bytes, at + 1)?;
+            }
+            // trailer
+            0x3B => {
+                out.push(0x3B);
+                break;
+            }
+            _ => return None,
+        }
+        out.extend_from_slice(bytes.get(start..at)?);
+    }
+    Some(out)
+}
+
+/// Strip the metadata from received media, returning the stripped copy and what was found.
+///
+/// The copy is a new file, so its hash and size are recomputed.
+pub async fn sanitize_media(
+    media: ReceivedMedia,
+    temp_dir: &Path,
+) -> Result<(ReceivedMedia, EmbeddedMetadata), MixiniError> {
+    let stripped_file = TempFile::new_in(temp_dir).await?;
+    let (source, destination, format) = (
+        media.file.path().to_owned(),
+        stripped_file.path().to_owned(),
+        media.format.to_owned(),
+    );
+    let (hash, size, metadata) = tokio::task::spawn_blocking(move || {
+        let bytes = std::fs::read(source)?;
+        let (stripped, metadata) = strip_metadata(&bytes, &format)
+            .map_err(|e| MixiniError::InvalidUpload(e.to_string()))?;
+        std::fs::write(destination, &stripped)?;
+        Ok::<_, MixiniError>((
+            format!("{:x}", Sha256::digest(&stripped)),
+            stripped.len() as u64,
+            metadata,
+        ))
+    })
+    .await
+    .map_err(anyhow::Error::from)??;
+
+    Ok((
+        ReceivedMedia {
+            file: stripped_file,
+            hash,
+            size,
+            ..media
+        },
+        metadata,
+    ))
+}
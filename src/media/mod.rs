@@ -0,0 +1,173 @@
+//! Receiving uploaded media and storing it.
+use axum::extract::multipart::Field;
+use entity::sea_orm_active_enums::MediaFormat;
+use futures::StreamExt;
+use image::ImageFormat;
+use sha2::{Digest, Sha256};
+use std::{
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use tokio::{fs::File, io::AsyncWriteExt};
+use ulid::Ulid;
+
+use crate::{constants::MAX_UPLOAD_BYTES, error::MixiniError};
+
+pub mod local;
+pub mod metadata;
+pub mod preview;
+pub mod s3;
+pub mod serve;
+pub mod similar;
+pub mod store;
+
+pub use local::LocalStore;
+pub use metadata::*;
+pub use preview::*;
+pub use s3::{S3Config, S3Store};
+pub use serve::*;
+pub use similar::*;
+pub use store::*;
+
+/// How many leading bytes `sniff_format` needs to recognize every format.
+pub const SNIFF_LEN: usize = 12;
+
+/// Identify the format of an image from its leading bytes, whatever its name or declared type.
+pub fn sniff_format(bytes: &[u8]) -> Option<MediaFormat> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(MediaFormat::Png),
+        [0xFF, 0xD8, 0xFF, ..] => Some(MediaFormat::Jpeg),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(MediaFormat::Gif),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(MediaFormat::Webp),
+        _ => None,
+    }
+}
+
+/// The `image` crate's name for `format`.
+pub fn image_format(format: &MediaFormat) -> ImageFormat {
+    match format {
+        MediaFormat::Gif => ImageFormat::Gif,
+        MediaFormat::Jpeg => ImageFormat::Jpeg,
+        MediaFormat::Png => ImageFormat::Png,
+        MediaFormat::Webp => ImageFormat::WebP,
+    }
+}
+
+/// A file that is deleted when dropped.
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Pick a new, unused path in `dir`, creating `dir` if needed. The file itself isn't created.
+    pub async fn new_in(dir: &Path) -> std::io::Result<Self> {
+        tokio::fs::create_dir_all(dir).await?;
+        Ok(Self {
+            path: dir.join(Ulid::new().to_string()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                tracing::warn!("Failed to remove temporary file {:?}: {:?}", self.path, e)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Download the file stored under `key` into a temporary file in `temp_dir`.
+pub async fn download(
+    store: &dyn MediaStore,
+    key: &str,
+    temp_dir: &Path,
+) -> Result<TempFile, MixiniError> {
+    let mut object = store.get(key, None).await?;
+    let temp_file = TempFile::new_in(temp_dir).await?;
+    let mut file = File::create(temp_file.path()).await?;
+    while let Some(chunk) = object.body.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+    Ok(temp_file)
+}
+
+/// An uploaded image, received into a temporary file.
+#[derive(Debug)]
+pub struct ReceivedMedia {
+    pub file: TempFile,
+    /// The SHA-256 of the file, in hex.
+    pub hash: String,
+    pub format: MediaFormat,
+    /// The size of the file in bytes.
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Stream an uploaded file from `field` into a temporary file in `temp_dir`, hashing it and
+/// sniffing its format along the way.
+///
+/// Rejects files over `MAX_UPLOAD_BYTES` as soon as they get too large, and files that aren't a
+/// supported image as soon as enough of them has arrived to tell.
+pub async fn receive_media(
+    mut field: Field<'_>,
+    temp_dir: &Path,
+) -> Result<ReceivedMedia, MixiniError> {
+    let temp_file = TempFile::new_in(temp_dir).await?;
+    let mut file = File::create(temp_file.path()).await?;
+
+    let mut hasher = Sha256::new();
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    let mut size = 0;
+    while let Some(chunk) = field.chunk().await? {
+        size += chunk.len() as u64;
+        if size > MAX_UPLOAD_BYTES {
+            return Err(MixiniError::PayloadTooLarge);
+        }
+        if head.len() < SNIFF_LEN {
+            let needed = (SNIFF_LEN - head.len()).min(chunk.len());
+            head.extend_from_slice(&chunk[..needed]);
+            if head.len() == SNIFF_LEN && sniff_format(&head).is_none() {
+                return Err(MixiniError::UnsupportedMediaType);
+            }
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    drop(file);
+
+    let format = sniff_format(&head).ok_or(MixiniError::UnsupportedMediaType)?;
+    let path = temp_file.path().to_owned();
+    let decoder_format = image_format(&format);
+    // reading dimensions only decodes the image header, but it's still blocking IO
+    let (width, height) = tokio::task::spawn_blocking(move || {
+        Ok::<_, MixiniError>(
+            image::io::Reader::with_format(
+                BufReader::new(std::fs::File::open(path)?),
+                decoder_format,
+            )
+            .into_dimensions()?,
+        )
+    })
+    .await
+    .map_err(anyhow::Error::from)??;
+
+    Ok(ReceivedMedia {
+        file: temp_file,
+        hash: format!("{:x}", hasher.finalize()),
+        format,
+        size,
+        width,
+        height,
+    })
+}
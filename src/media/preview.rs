@@ -0,0 +1,254 @@
+//! Smaller previews of media: thumbnails for search grids and samples for post pages.
+//!
+//! Previews are stored next to the media they were made from, under keys derived from the media's
+//! hash and the preview's size, so changing the configured sizes never serves stale previews.
+//! Missing previews are generated again when they're first requested.
+use entity::sea_orm_active_enums::MediaFormat;
+use image::{
+    codecs::{
+        jpeg::JpegEncoder,
+        webp::{WebPEncoder, WebPQuality},
+    },
+    DynamicImage,
+};
+use std::{io::BufReader, path::Path, str::FromStr};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    constants::{PREVIEW_JPEG_QUALITY, PREVIEW_WEBP_QUALITY, SAMPLE_SIZE, THUMBNAIL_SIZE},
+    error::MixiniError,
+    media::{download, image_format, media_key, MediaStore, TempFile},
+};
+
+/// Which preview of a post's media.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewKind {
+    Thumbnail,
+    Sample,
+}
+
+impl FromStr for PreviewKind {
+    type Err = MixiniError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "thumbnail" => Ok(PreviewKind::Thumbnail),
+            "sample" => Ok(PreviewKind::Sample),
+            _ => Err(MixiniError::NotFound),
+        }
+    }
+}
+
+/// The formats every preview is encoded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewFormat {
+    Webp,
+    Jpeg,
+}
+
+impl PreviewFormat {
+    pub const ALL: [PreviewFormat; 2] = [PreviewFormat::Webp, PreviewFormat::Jpeg];
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            PreviewFormat::Webp => "image/webp",
+            PreviewFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PreviewFormat::Webp => "webp",
+            PreviewFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+impl FromStr for PreviewFormat {
+    type Err = MixiniError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "webp" => Ok(PreviewFormat::Webp),
+            "jpg" | "jpeg" => Ok(PreviewFormat::Jpeg),
+            _ => Err(MixiniError::NotFound),
+        }
+    }
+}
+
+/// The longest side of each kind of preview, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreviewSizes {
+    pub thumbnail: u32,
+    pub sample: u32,
+}
+
+impl Default for PreviewSizes {
+    fn default() -> Self {
+        Self {
+            thumbnail: THUMBNAIL_SIZE,
+            sample: SAMPLE_SIZE,
+        }
+    }
+}
+
+impl PreviewSizes {
+    /// Attempt to read the sizes from `THUMBNAIL_SIZE` and `SAMPLE_SIZE`, using the defaults for
+    /// any that aren't set.
+    pub fn try_from_env() -> anyhow::Result<Self> {
+        let size = |var: &str, default: u32| match std::env::var(var) {
+            Ok(size) => size.parse::<u32>().map_err(anyhow::Error::from),
+            Err(_) => Ok(default),
+        };
+        let defaults = Self::default();
+        let sizes = Self {
+            thumbnail: size("THUMBNAIL_SIZE", defaults.thumbnail)?,
+            sample: size("SAMPLE_SIZE", defaults.sample)?,
+        };
+        sizes.validate()?;
+        Ok(sizes)
+    }
+
+    /// Check that previews can be made at these sizes, and that thumbnails are no larger than
+    /// samples.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.thumbnail == 0 || self.sample == 0 {
+            return Err(anyhow::anyhow!(
+                "THUMBNAIL_SIZE and SAMPLE_SIZE must be at least 1, got {} and {}",
+                self.thumbnail,
+                self.sample
+            ));
+        }
+        if self.thumbnail > self.sample {
+            return Err(anyhow::anyhow!(
+                "THUMBNAIL_SIZE ({}) must be no larger than SAMPLE_SIZE ({})",
+                self.thumbnail,
+                self.sample
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn size(&self, kind: PreviewKind) -> u32 {
+        match kind {
+            PreviewKind::Thumbnail => self.thumbnail,
+            PreviewKind::Sample => self.sample,
+        }
+    }
+
+    fn all(&self) -> [u32; 2] {
+        [self.thumbnail, self.sample]
+    }
+}
+
+/// The key of the preview of the media with this hash, at most `size` pixels on its longest side.
+pub fn preview_key(hash: &str, size: u32, format: PreviewFormat) -> String {
+    format!("{}_{}.{}", hash, size, format.extension())
+}
+
+/// Scale `image` down to fit in a `size` pixel square, without ever scaling it up.
+pub fn scale_to_fit(image: &DynamicImage, size: u32) -> DynamicImage {
+    if image.width() <= size && image.height() <= size {
+        image.clone()
+    } else {
+        image.thumbnail(size, size)
+    }
+}
+
+/// Encode `image` as a preview.
+pub fn encode_preview(image: &DynamicImage, format: PreviewFormat) -> Result<Vec<u8>, MixiniError> {
+    let mut bytes = Vec::new();
+    match format {
+        PreviewFormat::Webp => {
+            let rgba = image.to_rgba8();
+            WebPEncoder::new_with_quality(&mut bytes, WebPQuality::lossy(PREVIEW_WEBP_QUALITY))
+                .encode(&rgba, rgba.width(), rgba.height(), image::ColorType::Rgba8)?;
+        }
+        PreviewFormat::Jpeg => {
+            // JPEG has no transparency, so flatten the image onto white first
+            let mut rgb = image::RgbImage::from_pixel(
+                image.width(),
+                image.height(),
+                image::Rgb([255, 255, 255]),
+            );
+            for (x, y, pixel) in image.to_rgba8().enumerate_pixels() {
+                let [r, g, b, a] = pixel.0;
+                let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+                rgb.put_pixel(x, y, image::Rgb([blend(r), blend(g), blend(b)]));
+            }
+            JpegEncoder::new_with_quality(&mut bytes, PREVIEW_JPEG_QUALITY).encode_image(&rgb)?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decode the image at `source` and encode every preview of it, as `(key, format, bytes)`.
+///
+/// This is CPU-bound and blocking, so it should be run with `spawn_blocking`.
+pub fn render_previews(
+    source: &Path,
+    hash: &str,
+    format: &MediaFormat,
+    sizes: PreviewSizes,
+) -> Result<Vec<(String, PreviewFormat, Vec<u8>)>, MixiniError> {
+    let image = image::io::Reader::with_format(
+        BufReader::new(std::fs::File::open(source)?),
+        image_format(format),
+    )
+    .decode()?;
+
+    let mut previews = Vec::new();
+    for size in sizes.all() {
+        let scaled = scale_to_fit(&image, size);
+        for preview_format in PreviewFormat::ALL {
+            previews.push((
+                preview_key(hash, size, preview_format),
+                preview_format,
+                encode_preview(&scaled, preview_format)?,
+            ));
+        }
+    }
+    Ok(previews)
+}
+
+/// Generate every preview of the image at `source` and put them in `store`.
+pub async fn generate_previews(
+    store: &dyn MediaStore,
+    temp_dir: &Path,
+    source: &Path,
+    hash: &str,
+    format: &MediaFormat,
+    sizes: PreviewSizes,
+) -> Result<(), MixiniError> {
+    let previews = {
+        let (source, hash, format) = (source.to_owned(), hash.to_owned(), format.to_owned());
+        tokio::task::spawn_blocking(move || render_previews(&source, &hash, &format, sizes))
+            .await
+            .map_err(anyhow::Error::from)??
+    };
+
+    for (key, preview_format, bytes) in previews {
+        let temp_file = TempFile::new_in(temp_dir).await?;
+        let mut file = File::create(temp_file.path()).await?;
+        file.write_all(&bytes).await?;
+        file.flush().await?;
+        drop(file);
+        store
+            .put_file(&key, temp_file.path(), preview_format.mime_type())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Generate every preview of the stored media with this hash again, fetching the original from
+/// `store`.
+pub async fn regenerate_previews(
+    store: &dyn MediaStore,
+    temp_dir: &Path,
+    hash: &str,
+    format: &MediaFormat,
+    sizes: PreviewSizes,
+) -> Result<(), MixiniError> {
+    let original = download(store, &media_key(hash, format), temp_dir).await?;
+    generate_previews(store, temp_dir, original.path(), hash, format, sizes).await
+}
@@ -0,0 +1,279 @@
+//! Storing media in an S3-compatible object store.
+use axum::async_trait;
+use chrono::Utc;
+use futures::TryStreamExt;
+use hmac::{Hmac, Mac};
+use reqwest::{
+    header::{self, HeaderMap},
+    Body, Client, Method, RequestBuilder, Response, StatusCode, Url,
+};
+use sha2::{Digest, Sha256};
+use std::{fmt, io, ops::Range, path::Path, time::Duration};
+use tokio::fs::File;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    constants::MEDIA_STORE_CONNECT_TIMEOUT_SECS,
+    media::{validate_key, MediaObject, MediaStore, StoreError},
+};
+
+/// Where and how to reach a bucket.
+#[derive(Clone)]
+pub struct S3Config {
+    /// The base URL of the service, e.g. `https://s3.us-east-1.amazonaws.com`.
+    pub endpoint: Url,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl fmt::Debug for S3Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint.as_str())
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("access_key", &self.access_key)
+            .finish()
+    }
+}
+
+/// Stores media as objects in a bucket, named by their keys.
+///
+/// Objects are addressed path-style, as `endpoint/bucket/key`, which every S3-compatible service
+/// supports.
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    client: Client,
+    config: S3Config,
+}
+
+impl S3Store {
+    /// Attempt to create a store for the bucket in `config`.
+    pub fn try_new(config: S3Config) -> Result<Self, StoreError> {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(MEDIA_STORE_CONNECT_TIMEOUT_SECS))
+            .build()?;
+        Ok(Self { client, config })
+    }
+
+    fn url(&self, key: &str) -> Result<Url, StoreError> {
+        validate_key(key)?;
+        let mut url = self.config.endpoint.clone();
+        url.path_segments_mut()
+            .map_err(|_| StoreError::InvalidKey(key.to_owned()))?
+            .pop_if_empty()
+            .push(&self.config.bucket)
+            .push(key);
+        Ok(url)
+    }
+
+    /// Start a signed request for `key`, signing `headers` along with the ones S3 requires.
+    fn request(
+        &self,
+        method: Method,
+        key: &str,
+        headers: &[(&str, String)],
+    ) -> Result<RequestBuilder, StoreError> {
+        let url = self.url(key)?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut signed: Vec<(&str, &str)> = vec![
+            ("host", host.as_str()),
+            ("x-amz-content-sha256", "UNSIGNED-PAYLOAD"),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        signed.extend(headers.iter().map(|(name, value)| (*name, value.as_str())));
+        let authorization = authorization(
+            &self.config.access_key,
+            &self.config.secret_key,
+            &self.config.region,
+            method.as_str(),
+            url.path(),
+            &signed,
+        );
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header(header::AUTHORIZATION, authorization);
+        // reqwest sets the host header itself, to the same value
+        for (name, value) in signed.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(*name, *value);
+        }
+        Ok(request)
+    }
+}
+
+#[async_trait]
+impl MediaStore for S3Store {
+    async fn put_file(
+        &self,
+        key: &str,
+        source: &Path,
+        content_type: &str,
+    ) -> Result<(), StoreError> {
+        let file = File::open(source).await?;
+        let size = file.metadata().await?.len();
+
+        // S3 won't accept chunked uploads, so the length must be given up front
+        let response = self
+            .request(Method::PUT, key, &[])?
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, size)
+            .body(Body::wrap_stream(ReaderStream::new(file)))
+            .send()
+            .await?;
+        check_status(&response)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<MediaObject, StoreError> {
+        let headers = match range {
+            Some(ref range) if range.start >= range.end => return Err(StoreError::InvalidRange),
+            Some(ref range) => vec![("range", format!("bytes={}-{}", range.start, range.end - 1))],
+            None => Vec::new(),
+        };
+        let response = self.request(Method::GET, key, &headers)?.send().await?;
+        check_status(&response)?;
+
+        let length = header_u64(response.headers(), header::CONTENT_LENGTH)
+            .ok_or(StoreError::BadStatus(response.status()))?;
+        let (size, range) = match response.status() {
+            StatusCode::PARTIAL_CONTENT => response
+                .headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_content_range)
+                .ok_or(StoreError::BadStatus(response.status()))?,
+            // the whole object, if the range wasn't honored
+            _ => (length, 0..length),
+        };
+
+        Ok(MediaObject {
+            size,
+            range,
+            body: Box::pin(
+                response
+                    .bytes_stream()
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e)),
+            ),
+        })
+    }
+
+    async fn size(&self, key: &str) -> Result<Option<u64>, StoreError> {
+        let response = self.request(Method::HEAD, key, &[])?.send().await?;
+        match check_status(&response) {
+            Ok(()) => Ok(header_u64(response.headers(), header::CONTENT_LENGTH)),
+            Err(StoreError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        let response = self.request(Method::DELETE, key, &[])?.send().await?;
+        match check_status(&response) {
+            Ok(()) | Err(StoreError::NotFound) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn check_status(response: &Response) -> Result<(), StoreError> {
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        StatusCode::NOT_FOUND => Err(StoreError::NotFound),
+        StatusCode::RANGE_NOT_SATISFIABLE => Err(StoreError::InvalidRange),
+        status => Err(StoreError::BadStatus(status)),
+    }
+}
+
+/// Read a numeric header. `Response::content_length` can't be used, as it's the length of the
+/// body actually received, which is 0 for `HEAD` requests.
+fn header_u64(headers: &HeaderMap, name: header::HeaderName) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Parse a `Content-Range` of `bytes start-end/size` into the size and the range it covers.
+fn parse_content_range(value: &str) -> Option<(u64, Range<u64>)> {
+    let (range, size) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let end: u64 = end.parse().ok()?;
+    Some((size.parse().ok()?, start.parse().ok()?..end + 1))
+}
+
+fn hmac_sha256(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The `Authorization` header of a request signed with AWS Signature Version 4.
+///
+/// `path` must already be percent-encoded and the request must have no query string. `headers`
+/// are the lowercase names and values of every header to sign, which must include `host`,
+/// `x-amz-content-sha256` and `x-amz-date`.
+pub fn authorization(
+    access_key: &str,
+    secret_key: &str,
+    region: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> String {
+    let mut headers = headers.to_vec();
+    headers.sort_unstable_by_key(|(name, _)| *name);
+    let header = |wanted: &str| {
+        headers
+            .iter()
+            .find(|(name, _)| *name == wanted)
+            .map_or("", |(_, value)| *value)
+    };
+    let amz_date = header("x-amz-date");
+    let date = amz_date.get(..8).unwrap_or_default();
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method,
+        path,
+        canonical_headers,
+        signed_headers,
+        header("x-amz-content-sha256")
+    );
+
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        amz_date,
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+
+    let key = [region, "s3", "aws4_request"].iter().fold(
+        hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date),
+        |key, part| hmac_sha256(&key, part),
+    );
+    let signature: String = hmac_sha256(&key, &string_to_sign)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}",
+        access_key, scope, signed_headers, signature
+    )
+}
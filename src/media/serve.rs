@@ -0,0 +1,63 @@
+//! Conditional and partial requests for media.
+//!
+//! Stored media never changes, so its hash makes a strong `ETag`, and since clients seek through
+//! large files, single byte ranges are supported. Requests for several ranges at once are served
+//! the whole file, which HTTP allows.
+use std::ops::Range;
+
+/// What part of a file a `Range` header asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ByteRange {
+    /// The whole file, either because no range was asked for or because the header can't be
+    /// honored and is ignored.
+    Full,
+    Partial(Range<u64>),
+    /// No part of the range lies within the file.
+    Unsatisfiable,
+}
+
+/// Resolve a `Range` header against a file of `size` bytes.
+pub fn parse_range(value: &str, size: u64) -> ByteRange {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return ByteRange::Full,
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return ByteRange::Full,
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // `bytes=start-end`, with `end` inclusive
+        (Ok(start), Ok(end)) if start <= end => start..end.saturating_add(1).min(size),
+        // `bytes=start-`
+        (Ok(start), Err(_)) if end.is_empty() => start..size,
+        // `bytes=-suffix`, the last `suffix` bytes
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            size.saturating_sub(suffix)..size
+        }
+        _ => return ByteRange::Full,
+    };
+
+    if range.start >= size {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(range)
+    }
+}
+
+/// The `ETag` of the media with this hash.
+pub fn media_etag(hash: &str) -> String {
+    format!("\"{}\"", hash)
+}
+
+/// Whether an `If-None-Match` header matches `etag`, meaning the client already has the file.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
@@ -0,0 +1,307 @@
+//! Finding visually similar media, so reposts can be caught even after they've been resized or
+//! recompressed.
+//!
+//! Every uploaded image gets a 64-bit difference hash, and similar images have hashes that differ
+//! in few bits. Hashes are kept in memory in a BK-tree, which finds every hash within some Hamming
+//! distance without comparing against all of them. Like permission grants, the tree is rebuilt
+//! every `SIMILARITY_REFRESH_SECS` to pick up uploads to other instances.
+use entity::{post, prelude::*, sea_orm_active_enums::MediaFormat};
+use image::{imageops::FilterType, DynamicImage};
+use oso::Oso;
+use sea_orm::{entity::*, prelude::*, DatabaseConnection, QuerySelect};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::BufReader,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    auth::Auth,
+    constants::{SIMILARITY_REFRESH_SECS, SIMILAR_MAX_RESULTS},
+    error::MixiniError,
+    media::{download, image_format, media_key, MediaStore},
+    preferences::listing_filter,
+};
+
+/// The difference hash of an image: each bit is whether a pixel of the image, shrunk to 9x8 and
+/// greyscaled, is brighter than the pixel to its right.
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Decode the image at `path` and compute its difference hash.
+///
+/// This is CPU-bound and blocking, so it should be run with `spawn_blocking`.
+pub fn dhash_file(path: &Path, format: &MediaFormat) -> Result<u64, MixiniError> {
+    let image = image::io::Reader::with_format(
+        BufReader::new(std::fs::File::open(path)?),
+        image_format(format),
+    )
+    .decode()?;
+    Ok(dhash(&image))
+}
+
+/// `dhash_file`, off the async runtime.
+pub async fn dhash_file_blocking(path: &Path, format: &MediaFormat) -> Result<u64, MixiniError> {
+    let (path, format) = (path.to_owned(), format.to_owned());
+    tokio::task::spawn_blocking(move || dhash_file(&path, &format))
+        .await
+        .map_err(anyhow::Error::from)?
+}
+
+/// The number of bits two hashes differ in.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[derive(Debug)]
+struct BkNode {
+    hash: u64,
+    /// Every post with exactly this hash.
+    post_ids: Vec<Uuid>,
+    /// `(distance to the child's hash, index of the child)`
+    children: Vec<(u32, usize)>,
+}
+
+/// A BK-tree of post ids by the hash of their media.
+#[derive(Debug, Default)]
+pub struct BkTree {
+    nodes: Vec<BkNode>,
+    len: usize,
+}
+
+impl BkTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of posts in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, hash: u64, post_id: Uuid) {
+        let new_node = |nodes: &mut Vec<BkNode>| {
+            nodes.push(BkNode {
+                hash,
+                post_ids: vec![post_id],
+                children: Vec::new(),
+            });
+            nodes.len() - 1
+        };
+        if self.nodes.is_empty() {
+            new_node(&mut self.nodes);
+            self.len += 1;
+            return;
+        }
+
+        let mut index = 0;
+        loop {
+            let distance = hamming_distance(self.nodes[index].hash, hash);
+            if distance == 0 {
+                if !self.nodes[index].post_ids.contains(&post_id) {
+                    self.nodes[index].post_ids.push(post_id);
+                    self.len += 1;
+                }
+                return;
+            }
+            match self.nodes[index]
+                .children
+                .iter()
+                .find(|(child_distance, _)| *child_distance == distance)
+            {
+                Some(&(_, child)) => index = child,
+                None => {
+                    let child = new_node(&mut self.nodes);
+                    self.nodes[index].children.push((distance, child));
+                    self.len += 1;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Every post whose hash is within `max_distance` of `hash`, as `(post id, distance)`, closest
+    /// first.
+    pub fn find(&self, hash: u64, max_distance: u32) -> Vec<(Uuid, u32)> {
+        let mut found = Vec::new();
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let distance = hamming_distance(node.hash, hash);
+            if distance <= max_distance {
+                found.extend(node.post_ids.iter().map(|id| (*id, distance)));
+            }
+            // by the triangle inequality, only these children can hold hashes close enough
+            let range = distance.saturating_sub(max_distance)..=distance + max_distance;
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(child_distance, _)| range.contains(child_distance))
+                    .map(|(_, child)| *child),
+            );
+        }
+
+        found.sort_unstable_by_key(|(id, distance)| (*distance, *id));
+        found
+    }
+}
+
+/// Build a tree of every hashed post.
+pub async fn load_similarity_index(db: &DatabaseConnection) -> Result<BkTree, DbErr> {
+    let hashes: Vec<(Uuid, i64)> = Post::find()
+        .select_only()
+        .column(post::Column::Id)
+        .column(post::Column::MediaDhash)
+        .filter(post::Column::MediaDhash.is_not_null())
+        .into_values::<_, SimilarColumn>()
+        .all(db)
+        .await?;
+
+    let mut tree = BkTree::new();
+    for (id, hash) in hashes {
+        tree.insert(hash as u64, id);
+    }
+    Ok(tree)
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+enum SimilarColumn {
+    Id,
+    MediaDhash,
+}
+
+/// Periodically rebuild `index` in the background.
+pub fn spawn_similarity_refresh(db: DatabaseConnection, index: Arc<RwLock<BkTree>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SIMILARITY_REFRESH_SECS));
+        // the index was just loaded, so skip the first, immediate tick
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match load_similarity_index(&db).await {
+                Ok(tree) => *index.write().expect("similarity index lock poisoned") = tree,
+                Err(e) => tracing::error!("Failed to refresh the similarity index: {:?}", e),
+            }
+        }
+    });
+}
+
+/// Hash the media of every post uploaded before hashes were computed, adding them to `index`.
+pub async fn backfill_hashes(
+    db: &DatabaseConnection,
+    store: &dyn MediaStore,
+    temp_dir: &Path,
+    index: &RwLock<BkTree>,
+) -> Result<usize, MixiniError> {
+    let posts = Post::find()
+        .filter(post::Column::MediaHash.is_not_null())
+        .filter(post::Column::MediaDhash.is_null())
+        .all(db)
+        .await?;
+
+    let mut hashed = 0;
+    for post in posts {
+        let (hash, format) = match (post.media_hash.as_deref(), post.media_format.as_ref()) {
+            (Some(hash), Some(format)) => (hash, format),
+            _ => continue,
+        };
+        let file = download(store, &media_key(hash, format), temp_dir).await?;
+        let dhash = match dhash_file_blocking(file.path(), format).await {
+            Ok(dhash) => dhash,
+            Err(e) => {
+                tracing::warn!("Failed to hash the media of post {}: {:?}", post.id, e);
+                continue;
+            }
+        };
+
+        let post_id = post.id;
+        let mut post: post::ActiveModel = post.into();
+        post.media_dhash = Set(Some(dhash as i64));
+        post.update(db).await?;
+        index
+            .write()
+            .expect("similarity index lock poisoned")
+            .insert(dhash, post_id);
+        hashed += 1;
+    }
+    Ok(hashed)
+}
+
+/// A post whose media looks like some other image.
+#[derive(Debug, Serialize)]
+pub struct SimilarPost {
+    #[serde(flatten)]
+    pub post: post::Model,
+    /// How many bits the hashes of the two images differ in, out of 64.
+    pub distance: u32,
+    /// Whether the post is on the requester's blacklist, see `SearchResult::blacklisted`.
+    pub blacklisted: bool,
+}
+
+/// The posts `auth` may read and wants to be shown whose media is within `max_distance` of `hash`,
+/// closest first.
+pub async fn similar_posts(
+    db: &DatabaseConnection,
+    oso: &Mutex<Oso>,
+    auth: &Auth,
+    index: &RwLock<BkTree>,
+    hash: u64,
+    max_distance: u32,
+    exclude: Option<Uuid>,
+) -> Result<Vec<SimilarPost>, MixiniError> {
+    let distances: HashMap<Uuid, u32> = index
+        .read()
+        .expect("similarity index lock poisoned")
+        .find(hash, max_distance)
+        .into_iter()
+        .filter(|(id, _)| Some(*id) != exclude)
+        .collect();
+    if distances.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let filter = listing_filter(db, oso, auth).await?;
+    let posts = Post::find()
+        .filter(filter.condition.to_owned())
+        .filter(post::Column::Id.is_in(distances.keys().copied()))
+        .all(db)
+        .await?;
+    let ids: Vec<Uuid> = posts.iter().map(|post| post.id).collect();
+    let blacklisted = filter.marked_ids(db, &ids).await?;
+
+    let mut similar: Vec<SimilarPost> = posts
+        .into_iter()
+        .map(|post| SimilarPost {
+            distance: distances[&post.id],
+            blacklisted: blacklisted.contains(&post.id),
+            post,
+        })
+        .collect();
+    similar.sort_unstable_by_key(|similar| (similar.distance, similar.post.id));
+    similar.truncate(SIMILAR_MAX_RESULTS);
+    Ok(similar)
+}
@@ -0,0 +1,133 @@
+//! Where media is kept once it's been received.
+//!
+//! Every file is stored under a key derived from its hash, see `media_key`, in whichever
+//! `MediaStore` the server was configured with.
+use axum::async_trait;
+use bytes::Bytes;
+use entity::sea_orm_active_enums::MediaFormat;
+use futures::Stream;
+use std::{fmt, io, ops::Range, path::Path, pin::Pin, sync::Arc};
+use thiserror::Error;
+
+use crate::media::{LocalStore, S3Config, S3Store};
+
+/// A stream of bytes read from a store.
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// Any error while reading or writing a store.
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("No media is stored under this key")]
+    NotFound,
+
+    #[error("`{0}` is not a valid media key")]
+    InvalidKey(String),
+
+    #[error("The requested range is not satisfiable")]
+    InvalidRange,
+
+    #[error("The store responded with {0}")]
+    BadStatus(reqwest::StatusCode),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => StoreError::NotFound,
+            _ => StoreError::Io(e),
+        }
+    }
+}
+
+/// A stored file, or part of one, being read.
+pub struct MediaObject {
+    /// The size of the whole file in bytes.
+    pub size: u64,
+    /// The bytes of the file `body` holds.
+    pub range: Range<u64>,
+    pub body: ByteStream,
+}
+
+impl fmt::Debug for MediaObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MediaObject")
+            .field("size", &self.size)
+            .field("range", &self.range)
+            .finish()
+    }
+}
+
+/// Somewhere to keep media files, by key.
+#[async_trait]
+pub trait MediaStore: Send + Sync + fmt::Debug {
+    /// Store the local file at `source` under `key`, replacing anything stored there.
+    async fn put_file(
+        &self,
+        key: &str,
+        source: &Path,
+        content_type: &str,
+    ) -> Result<(), StoreError>;
+
+    /// Read the file stored under `key`, or only the bytes in `range` of it.
+    ///
+    /// A range reaching past the end of the file is cut short, but one starting past the end or
+    /// empty is an `InvalidRange`.
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<MediaObject, StoreError>;
+
+    /// The size in bytes of the file stored under `key`, if there is one.
+    async fn size(&self, key: &str) -> Result<Option<u64>, StoreError>;
+
+    /// Remove the file stored under `key`, if there is one.
+    async fn delete(&self, key: &str) -> Result<(), StoreError>;
+}
+
+/// The key of the original media file with this hash.
+pub fn media_key(hash: &str, format: &MediaFormat) -> String {
+    format!("{}.{}", hash, format.extension())
+}
+
+/// Keys are flat names of lowercase letters, digits, `.`, `_` and `-`, at least 4 long so they
+/// can be sharded. They can't start with `.`, so they never name hidden or relative paths.
+pub fn validate_key(key: &str) -> Result<(), StoreError> {
+    let valid = key.len() >= 4
+        && !key.starts_with('.')
+        && key
+            .bytes()
+            .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'.' | b'_' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidKey(key.to_owned()))
+    }
+}
+
+/// Attempt to set up the store named by `MEDIA_STORE`, which is `local` if unset.
+///
+/// `local` stores media in `MEDIA_DIR`, and `s3` in the bucket `S3_BUCKET` of any S3-compatible
+/// service, configured with `S3_ENDPOINT`, `S3_REGION`, `S3_ACCESS_KEY` and `S3_SECRET_KEY`.
+pub fn try_store_from_env() -> anyhow::Result<Arc<dyn MediaStore>> {
+    let kind = std::env::var("MEDIA_STORE").unwrap_or_else(|_| "local".to_owned());
+    match kind.as_str() {
+        "local" => Ok(Arc::new(LocalStore::new(std::env::var("MEDIA_DIR")?))),
+        "s3" => {
+            let config = S3Config {
+                endpoint: std::env::var("S3_ENDPOINT")?.parse()?,
+                bucket: std::env::var("S3_BUCKET")?,
+                region: std::env::var("S3_REGION")?,
+                access_key: std::env::var("S3_ACCESS_KEY")?,
+                secret_key: std::env::var("S3_SECRET_KEY")?,
+            };
+            Ok(Arc::new(S3Store::try_new(config)?))
+        }
+        kind => Err(anyhow::anyhow!(
+            "MEDIA_STORE must be `local` or `s3`, not `{}`",
+            kind
+        )),
+    }
+}
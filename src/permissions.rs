@@ -0,0 +1,95 @@
+//! Fine-grained permissions granted to individual users on top of their role.
+//!
+//! Policies are evaluated synchronously, so grants can't be queried from the database while a rule
+//! runs. Instead every active grant is kept in memory in `Grants` and refreshed whenever grants
+//! change, as well as every `PERMISSION_REFRESH_SECS` to pick up changes made by other instances.
+use chrono::Utc;
+use entity::{permission_grant, prelude::*};
+use sea_orm::{prelude::*, Condition, DatabaseConnection};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use crate::constants::PERMISSION_REFRESH_SECS;
+
+/// The active grants, by the id of the user they were granted to.
+///
+/// Shared between `State` and the oso instances whose policies check them.
+#[derive(Debug, Default)]
+pub struct Grants {
+    by_user: RwLock<HashMap<Uuid, Vec<permission_grant::Model>>>,
+}
+
+impl Grants {
+    /// Hold exactly `grants`.
+    pub fn new(grants: impl IntoIterator<Item = permission_grant::Model>) -> Self {
+        let this = Self::default();
+        this.replace(grants);
+        this
+    }
+
+    /// Replace every held grant with `grants`.
+    pub fn replace(&self, grants: impl IntoIterator<Item = permission_grant::Model>) {
+        let mut by_user: HashMap<Uuid, Vec<permission_grant::Model>> = HashMap::new();
+        for grant in grants {
+            by_user.entry(grant.user_id).or_default().push(grant);
+        }
+        *self.by_user.write().expect("grants lock poisoned") = by_user;
+    }
+
+    /// Reload every unexpired grant from the database.
+    pub async fn refresh(&self, db: &DatabaseConnection) -> Result<(), DbErr> {
+        let active = PermissionGrant::find()
+            .filter(
+                Condition::any()
+                    .add(permission_grant::Column::ExpiresAt.is_null())
+                    .add(permission_grant::Column::ExpiresAt.gt(Utc::now())),
+            )
+            .all(db)
+            .await?;
+        self.replace(active);
+        Ok(())
+    }
+
+    /// Whether `user_id` holds `permission`, either unscoped or, if given, scoped to the
+    /// `(resource_type, resource_id)` resource.
+    pub fn has_permission(
+        &self,
+        user_id: Uuid,
+        permission: &str,
+        scope: Option<(&str, Uuid)>,
+    ) -> bool {
+        let now = Utc::now();
+        self.by_user
+            .read()
+            .expect("grants lock poisoned")
+            .get(&user_id)
+            .map_or(false, |grants| {
+                grants.iter().any(|grant| {
+                    grant.permission == permission
+                        && grant.expires_at.map_or(true, |expires_at| expires_at > now)
+                        && match (&grant.resource_type, grant.resource_id) {
+                            (Some(resource_type), Some(resource_id)) => {
+                                scope == Some((resource_type.as_str(), resource_id))
+                            }
+                            _ => true,
+                        }
+                })
+            })
+    }
+}
+
+/// Periodically refresh `grants` in the background.
+pub fn spawn_refresh(grants: Arc<Grants>, db: DatabaseConnection) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(PERMISSION_REFRESH_SECS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = grants.refresh(&db).await {
+                tracing::error!("Failed to refresh permission grants: {:?}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,288 @@
+//! Loading, hot reloading and explaining Polar policy files.
+use anyhow::Result;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use oso::{Oso, PolarClass, PolarValue};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, RwLock},
+    time::Duration,
+};
+use tokio::sync::Mutex;
+
+use crate::{actions::register_classes, permissions::Grants};
+
+/// The directory every Polar policy file is loaded from.
+pub const POLAR_DIR: &str = "polar";
+
+/// How long filesystem events are debounced before a reload is attempted.
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// The policy itself, as a resource. Used to authorize administrative actions on it.
+#[derive(Debug, Clone, Copy, PolarClass)]
+pub struct Policy;
+
+/// Collect every `.polar` file in `POLAR_DIR`, sorted so load order is stable.
+pub fn policy_files() -> Result<Vec<PathBuf>> {
+    let mut files = std::fs::read_dir(POLAR_DIR)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|path| is_policy_file(path));
+    files.sort();
+    Ok(files)
+}
+
+fn is_policy_file(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "polar")
+}
+
+/// The rules of the policy currently in force, as last loaded successfully.
+///
+/// Kept next to the running oso instance so decisions are explained by the rules that made them,
+/// even if the files have since changed or failed to load.
+pub type LoadedRules = Arc<RwLock<Vec<PolicyRule>>>;
+
+/// Attempt to load every policy file into a fresh oso instance, returning it along with the rules
+/// that were loaded.
+pub fn try_load_policy(grants: &Arc<Grants>) -> Result<(Oso, Vec<PolicyRule>)> {
+    let files = policy_files()?;
+    let mut rules = Vec::new();
+    for path in &files {
+        let text = std::fs::read_to_string(path)?;
+        rules.extend(parse_rules(&path.to_string_lossy(), &text));
+    }
+
+    let mut oso = Oso::new();
+    register_classes(&mut oso, grants)?;
+    oso.load_files(files)?;
+    Ok((oso, rules))
+}
+
+/// Attempt to reload all policy files.
+///
+/// The policy is first loaded into a fresh oso instance; the running instance and its rules are
+/// only swapped out if that succeeds, so a broken policy never replaces a working one.
+pub async fn try_reload_oso(
+    oso: &Mutex<Oso>,
+    loaded_rules: &LoadedRules,
+    grants: &Arc<Grants>,
+) -> Result<()> {
+    let (fresh, rules) = try_load_policy(grants)?;
+    let mut oso = oso.lock().await;
+    *oso = fresh;
+    *loaded_rules.write().expect("policy rules lock poisoned") = rules;
+    tracing::info!("Reloaded polar policy from {}", POLAR_DIR);
+    Ok(())
+}
+
+/// Watch `POLAR_DIR` and reload the policy whenever a policy file in it changes.
+///
+/// Must be called from within a tokio runtime.
+pub fn watch_policy(
+    oso: Arc<Mutex<Oso>>,
+    loaded_rules: LoadedRules,
+    grants: Arc<Grants>,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut policy_watcher = watcher(tx, WATCH_DEBOUNCE)?;
+    policy_watcher.watch(POLAR_DIR, RecursiveMode::NonRecursive)?;
+    let handle = tokio::runtime::Handle::current();
+
+    std::thread::spawn(move || {
+        // the watcher stops emitting events once dropped, so keep it alive alongside the receiver
+        let _policy_watcher = policy_watcher;
+
+        for event in rx {
+            let changed = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path) => is_policy_file(&path),
+                DebouncedEvent::Rename(from, to) => is_policy_file(&from) || is_policy_file(&to),
+                DebouncedEvent::Error(e, path) => {
+                    tracing::error!("Error watching polar policy at {:?}: {:?}", path, e);
+                    false
+                }
+                _ => false,
+            };
+
+            if changed {
+                if let Err(e) = handle.block_on(try_reload_oso(&oso, &loaded_rules, &grants)) {
+                    tracing::error!("Failed to reload polar policy, keeping previous: {:?}", e);
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// A single rule definition in a policy file.
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyRule {
+    /// The file the rule is defined in.
+    pub file: String,
+    /// The line the rule starts on, counting from 1.
+    pub line: usize,
+    /// The rule name, e.g. `allow` or `allow_field`.
+    pub name: String,
+    /// The `##` comments directly above the rule.
+    pub description: Option<String>,
+    /// The full rule definition.
+    pub source: String,
+}
+
+/// Split the policy source `text` of `file` into its rule definitions.
+///
+/// Rules end at the first `;` outside of a string or comment. Only `##` comments on their own lines
+/// between rules are kept, as the description of the rule that follows them.
+pub fn parse_rules(file: &str, text: &str) -> Vec<PolicyRule> {
+    let mut rules = Vec::new();
+    let mut description: Vec<&str> = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    let mut in_string = false;
+
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if current.is_none() {
+            if trimmed.is_empty() {
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix("##") {
+                description.push(comment.trim());
+                continue;
+            }
+            if trimmed.starts_with('#') {
+                continue;
+            }
+        }
+
+        let (_, source) = current.get_or_insert_with(|| (index + 1, String::new()));
+        if !source.is_empty() {
+            source.push('\n');
+        }
+        source.push_str(line);
+
+        if ends_rule(line, &mut in_string) {
+            let (line, source) = current.take().expect("rule in progress");
+            let name = source
+                .split('(')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_owned();
+            rules.push(PolicyRule {
+                file: file.to_owned(),
+                line,
+                name,
+                description: (!description.is_empty()).then(|| description.join(" ")),
+                source,
+            });
+            description.clear();
+            in_string = false;
+        }
+    }
+
+    rules
+}
+
+/// Whether `line` has a `;` outside of strings and comments, tracking whether it ends inside a
+/// string in `in_string`.
+fn ends_rule(line: &str, in_string: &mut bool) -> bool {
+    let mut escaped = false;
+    for c in line.chars() {
+        if *in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => *in_string = false,
+                _ => {}
+            }
+        } else {
+            match c {
+                '"' => *in_string = true,
+                '#' => return false,
+                ';' => return true,
+                _ => {}
+            }
+        }
+    }
+    false
+}
+
+/// How a single rule evaluated on its own.
+#[derive(Debug, Serialize)]
+pub struct RuleExplanation {
+    #[serde(flatten)]
+    pub rule: PolicyRule,
+    /// Whether the rule succeeded. For `allow_field` rules, whether it authorized any field.
+    pub matched: bool,
+    /// The fields an `allow_field` rule authorized.
+    pub fields: Vec<String>,
+    /// The error evaluating the rule raised, if any.
+    pub error: Option<String>,
+}
+
+/// Evaluate every `allow` and `allow_field` rule of `rules` on its own for the given query.
+///
+/// Each rule is loaded into a scratch oso instance along with every rule that isn't an
+/// authorization rule (so helper rules still resolve), which shows exactly which rules would have
+/// granted the request and which did not.
+///
+/// This loads a policy per rule, so it should be run with `spawn_blocking`.
+pub fn explain_rules(
+    rules: &[PolicyRule],
+    grants: &Arc<Grants>,
+    actor: PolarValue,
+    action: PolarValue,
+    resource: PolarValue,
+) -> Result<Vec<RuleExplanation>> {
+    const AUTHORIZATION_RULES: [&str; 2] = ["allow", "allow_field"];
+
+    let helpers: Vec<&str> = rules
+        .iter()
+        .filter(|rule| !AUTHORIZATION_RULES.contains(&rule.name.as_str()))
+        .map(|rule| rule.source.as_str())
+        .collect();
+
+    let mut explanations = Vec::new();
+    for rule in rules
+        .iter()
+        .filter(|rule| AUTHORIZATION_RULES.contains(&rule.name.as_str()))
+    {
+        let mut scratch = Oso::new();
+        register_classes(&mut scratch, grants)?;
+
+        let mut source = helpers.join("\n");
+        source.push('\n');
+        source.push_str(&rule.source);
+
+        let outcome = scratch.load_str(&source).and_then(|_| {
+            if rule.name == "allow" {
+                scratch
+                    .is_allowed(actor.to_owned(), action.to_owned(), resource.to_owned())
+                    .map(|allowed| (allowed, Vec::new()))
+            } else {
+                scratch
+                    .authorized_fields(actor.to_owned(), action.to_owned(), resource.to_owned())
+                    .map(|fields| {
+                        let mut fields: Vec<String> = fields.into_iter().collect();
+                        fields.sort_unstable();
+                        (!fields.is_empty(), fields)
+                    })
+            }
+        });
+
+        let (matched, fields, error) = match outcome {
+            Ok((matched, fields)) => (matched, fields, None),
+            Err(e) => (false, Vec::new(), Some(e.to_string())),
+        };
+        explanations.push(RuleExplanation {
+            rule: rule.to_owned(),
+            matched,
+            fields,
+            error,
+        });
+    }
+
+    Ok(explanations)
+}
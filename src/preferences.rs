@@ -0,0 +1,232 @@
+//! What people want to see, as opposed to what they may see.
+//!
+//! Policies decide which posts someone may read, and preferences narrow that down to the posts
+//! they've chosen to be shown: the ratings they want, minus anything on their tag blacklist.
+//! Every listing and search of posts is filtered by `listing_filter`, which applies both, so no
+//! handler can forget either.
+use entity::{post, prelude::*, sea_orm_active_enums::PostRating, user_preference};
+use oso::Oso;
+use sea_orm::{
+    prelude::*, sea_query::Expr as SqlExpr, Condition, ConnectionTrait, DatabaseConnection,
+};
+use std::collections::HashSet;
+use tokio::sync::Mutex;
+
+use crate::{
+    actions::Read,
+    auth::Auth,
+    constants::{MAX_BLACKLIST_ENTRIES, MAX_BLACKLIST_ENTRY_TERMS},
+    error::MixiniError,
+    filtering::authorized_condition,
+    search::{parse_query, search_condition, tag_names, Expr, ParseError},
+    tags::resolve_tags,
+};
+
+/// Which posts someone wants to be shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentPreferences {
+    /// The ratings of the posts to show.
+    pub ratings: Vec<PostRating>,
+    /// Search expressions matching posts not to show.
+    pub blacklist: Vec<Expr>,
+    /// Whether blacklisted posts are still listed, marked as blacklisted, so clients can show a
+    /// placeholder in their place.
+    pub mark_blacklisted: bool,
+}
+
+impl ContentPreferences {
+    /// The preferences of visitors without an account, who are only shown safe posts.
+    pub fn guest() -> Self {
+        Self {
+            ratings: vec![PostRating::Safe],
+            blacklist: Vec::new(),
+            mark_blacklisted: false,
+        }
+    }
+
+    /// The preferences of users who haven't set any, who are shown everything they may read.
+    pub fn user_default() -> Self {
+        Self {
+            ratings: vec![
+                PostRating::Safe,
+                PostRating::Questionable,
+                PostRating::Explicit,
+            ],
+            blacklist: Vec::new(),
+            mark_blacklisted: false,
+        }
+    }
+
+    /// Load the preferences of `auth`.
+    pub async fn load(db: &DatabaseConnection, auth: &Auth) -> Result<Self, DbErr> {
+        match auth {
+            Auth::KnownUser(user) => Ok(UserPreference::find_by_id(user.id)
+                .one(db)
+                .await?
+                .map_or_else(Self::user_default, |preference| Self::from(&preference))),
+            Auth::UnknownUser(_) => Ok(Self::guest()),
+        }
+    }
+
+    /// The condition limiting posts to the ratings these preferences show.
+    pub fn rating_condition(&self) -> Condition {
+        if self.ratings.is_empty() {
+            return Condition::all().add(SqlExpr::cust("FALSE"));
+        }
+        Condition::all().add(post::Column::Rating.is_in(self.ratings.to_owned()))
+    }
+
+    /// The condition matching posts on the blacklist, or `None` if it's empty.
+    pub async fn blacklist_condition<C: ConnectionTrait>(
+        &self,
+        db: &C,
+    ) -> Result<Option<Condition>, DbErr> {
+        if self.blacklist.is_empty() {
+            return Ok(None);
+        }
+        let mut names = Vec::new();
+        for expr in &self.blacklist {
+            tag_names(expr, &mut names);
+        }
+        let tags = resolve_tags(db, &names).await?;
+
+        Ok(Some(
+            self.blacklist.iter().fold(Condition::any(), |any, expr| {
+                any.add(search_condition(expr, &tags))
+            }),
+        ))
+    }
+}
+
+impl From<&user_preference::Model> for ContentPreferences {
+    fn from(preference: &user_preference::Model) -> Self {
+        let ratings = [
+            (preference.show_safe, PostRating::Safe),
+            (preference.show_questionable, PostRating::Questionable),
+            (preference.show_explicit, PostRating::Explicit),
+        ];
+        // blacklists are validated when they're saved, but what's valid may have changed since,
+        // and a broken entry shouldn't hide the rest
+        let blacklist = preference
+            .tag_blacklist
+            .lines()
+            .filter_map(|line| match parse_blacklist_entry(line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    tracing::warn!(
+                        "Skipping invalid blacklist entry of user {}: {}",
+                        preference.user_id,
+                        e
+                    );
+                    None
+                }
+            })
+            .take(MAX_BLACKLIST_ENTRIES)
+            .collect();
+
+        Self {
+            ratings: ratings
+                .into_iter()
+                .filter_map(|(shown, rating)| shown.then(|| rating))
+                .collect(),
+            blacklist,
+            mark_blacklisted: preference.mark_blacklisted,
+        }
+    }
+}
+
+/// Parse one line of a tag blacklist, which is a search query without `order:`. Blank lines are
+/// `None`.
+fn parse_blacklist_entry(line: &str) -> Result<Option<Expr>, ParseError> {
+    let query = parse_query(line, MAX_BLACKLIST_ENTRY_TERMS)?;
+    if query.order.is_some() {
+        return Err(ParseError {
+            position: 0,
+            message: "`order:` can't be used in a blacklist".to_owned(),
+        });
+    }
+    // an empty query matches every post
+    Ok((query.terms > 0).then(|| query.expr))
+}
+
+/// Parse a tag blacklist, which has one search query per line, like `gore -rating:safe`.
+///
+/// Error positions count from the start of the whole blacklist.
+pub fn parse_blacklist(text: &str) -> Result<Vec<Expr>, ParseError> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    for line in text.split('\n') {
+        let entry = parse_blacklist_entry(line).map_err(|e| ParseError {
+            position: offset + e.position,
+            ..e
+        })?;
+        if let Some(entry) = entry {
+            if entries.len() == MAX_BLACKLIST_ENTRIES {
+                return Err(ParseError {
+                    position: offset,
+                    message: format!(
+                        "A blacklist can have at most {} entries",
+                        MAX_BLACKLIST_ENTRIES
+                    ),
+                });
+            }
+            entries.push(entry);
+        }
+        offset += line.chars().count() + 1;
+    }
+    Ok(entries)
+}
+
+/// How listings of posts are filtered for someone.
+#[derive(Debug, Clone)]
+pub struct ListingFilter {
+    /// The condition every listed post must meet.
+    pub condition: Condition,
+    /// The condition matching listed posts that are blacklisted, if they're to be marked rather
+    /// than left out.
+    pub marked: Option<Condition>,
+}
+
+impl ListingFilter {
+    /// Which of the listed posts `ids` are blacklisted and should be marked as such.
+    pub async fn marked_ids<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        ids: &[Uuid],
+    ) -> Result<HashSet<Uuid>, DbErr> {
+        let marked = match &self.marked {
+            Some(marked) if !ids.is_empty() => marked.to_owned(),
+            _ => return Ok(HashSet::new()),
+        };
+        Ok(Post::find()
+            .filter(post::Column::Id.is_in(ids.to_vec()))
+            .filter(marked)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|post| post.id)
+            .collect())
+    }
+}
+
+/// Build the filter limiting posts to the ones `auth` may read and wants to be shown.
+///
+/// Every query listing posts should be filtered by this.
+pub async fn listing_filter(
+    db: &DatabaseConnection,
+    oso: &Mutex<Oso>,
+    auth: &Auth,
+) -> Result<ListingFilter, MixiniError> {
+    let preferences = ContentPreferences::load(db, auth).await?;
+    let mut condition = Condition::all()
+        .add(authorized_condition::<Post, _>(oso, auth, Read).await?)
+        .add(preferences.rating_condition());
+
+    let mut marked = None;
+    match preferences.blacklist_condition(db).await? {
+        Some(blacklisted) if preferences.mark_blacklisted => marked = Some(blacklisted),
+        Some(blacklisted) => condition = condition.add(blacklisted.not()),
+        None => {}
+    }
+    Ok(ListingFilter { condition, marked })
+}
@@ -0,0 +1,409 @@
+//! Versioned history of the community-edited content of posts.
+//!
+//! Every change to a post's tags, sources, rating or description is recorded as a revision holding
+//! a full snapshot of all four, so any two versions can be compared and any of them restored. The
+//! first version of a post is how it was before its first recorded edit, taken when that edit is
+//! made, so posts created before revisions were kept get a history too.
+//!
+//! Edits are made in a transaction holding the lock from `lock_post`, and recorded in it before
+//! it's committed, so every revision is of exactly one edit and attributed to whoever made it.
+use entity::{
+    post, post_revision,
+    prelude::*,
+    sea_orm_active_enums::{PostRating, UserRole},
+    tag, user_account,
+};
+use sea_orm::{
+    entity::*, prelude::*, ActiveEnum, ConnectionTrait, DatabaseTransaction, DbBackend, QueryOrder,
+    QuerySelect, Statement,
+};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeSet;
+
+use crate::{constants::REVISIONS_PER_PAGE, error::MixiniError, tags::set_post_tags_in};
+
+/// The content of a post that's kept in its revisions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostSnapshot {
+    /// The names of the post's tags, sorted.
+    pub tags: Vec<String>,
+    pub source_urls: Vec<String>,
+    pub rating: PostRating,
+    pub description: String,
+}
+
+impl PostSnapshot {
+    /// The content of `post` as it is now.
+    pub async fn load<C: ConnectionTrait>(db: &C, post: &post::Model) -> Result<Self, MixiniError> {
+        let tags = post
+            .find_related(Tag)
+            .order_by_asc(tag::Column::Name)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+
+        Ok(Self {
+            tags,
+            source_urls: serde_json::from_value(post.source_urls.to_owned())?,
+            rating: post.rating.to_owned(),
+            description: post.description.to_owned(),
+        })
+    }
+
+    /// The content of a post as of `revision`.
+    pub fn of(revision: &post_revision::Model) -> Result<Self, MixiniError> {
+        Ok(Self {
+            tags: serde_json::from_value(revision.tags.to_owned())?,
+            source_urls: serde_json::from_value(revision.source_urls.to_owned())?,
+            rating: revision.rating.to_owned(),
+            description: revision.description.to_owned(),
+        })
+    }
+
+    /// The names of the fields that differ from `other`.
+    pub fn changed_fields(&self, other: &Self) -> Vec<&'static str> {
+        [
+            ("tags", self.tags != other.tags),
+            ("source_urls", self.source_urls != other.source_urls),
+            ("rating", self.rating != other.rating),
+            ("description", self.description != other.description),
+        ]
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(field, _)| field)
+        .collect()
+    }
+
+    /// `post` with the sources, rating and description of this snapshot. Its tags are set
+    /// separately, with `set_post_tags`.
+    pub fn restore(&self, post: post::Model) -> Result<post::ActiveModel, MixiniError> {
+        let mut post: post::ActiveModel = post.into();
+        post.source_urls = Set(serde_json::to_value(&self.source_urls)?);
+        post.rating = Set(self.rating.to_owned());
+        post.description = Set(self.description.to_owned());
+        Ok(post)
+    }
+}
+
+/// A revision and the fields it changed from the one before it.
+#[derive(Debug, Serialize)]
+pub struct RevisionResponse {
+    #[serde(flatten)]
+    pub revision: post_revision::Model,
+    /// Empty for the first version.
+    pub changed: Vec<&'static str>,
+}
+
+/// The entries added to and removed from a list.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ListDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ListDiff {
+    /// The entries of `to` not in `from` and those of `from` not in `to`, each in their order.
+    pub fn new(from: &[String], to: &[String]) -> Self {
+        let (from_set, to_set): (BTreeSet<&String>, BTreeSet<&String>) =
+            (from.iter().collect(), to.iter().collect());
+        Self {
+            added: to
+                .iter()
+                .filter(|entry| !from_set.contains(entry))
+                .cloned()
+                .collect(),
+            removed: from
+                .iter()
+                .filter(|entry| !to_set.contains(entry))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// A value before and after a change.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+/// What a line of text is in the version being compared to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A line of a text diff, including its line ending.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DiffLine {
+    pub op: LineOp,
+    pub text: String,
+}
+
+/// The differences between two snapshots of a post.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SnapshotDiff {
+    pub tags: ListDiff,
+    pub source_urls: ListDiff,
+    /// `None` if the rating is the same.
+    pub rating: Option<Change<PostRating>>,
+    /// Every line of both descriptions, or `None` if they're the same.
+    pub description: Option<Vec<DiffLine>>,
+}
+
+impl SnapshotDiff {
+    /// How `to` differs from `from`.
+    pub fn new(from: &PostSnapshot, to: &PostSnapshot) -> Self {
+        let description = (from.description != to.description).then(|| {
+            TextDiff::from_lines(&from.description, &to.description)
+                .iter_all_changes()
+                .map(|change| DiffLine {
+                    op: match change.tag() {
+                        ChangeTag::Equal => LineOp::Equal,
+                        ChangeTag::Insert => LineOp::Insert,
+                        ChangeTag::Delete => LineOp::Delete,
+                    },
+                    text: change.value().to_owned(),
+                })
+                .collect()
+        });
+
+        Self {
+            tags: ListDiff::new(&from.tags, &to.tags),
+            source_urls: ListDiff::new(&from.source_urls, &to.source_urls),
+            rating: (from.rating != to.rating).then(|| Change {
+                from: from.rating.to_owned(),
+                to: to.rating.to_owned(),
+            }),
+            description,
+        }
+    }
+}
+
+/// The response for comparing two versions of a post.
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub from: i32,
+    pub to: i32,
+    #[serde(flatten)]
+    pub diff: SnapshotDiff,
+}
+
+/// Find version `version` of `post_id`.
+pub async fn find_revision<C: ConnectionTrait>(
+    db: &C,
+    post_id: Uuid,
+    version: i32,
+) -> Result<post_revision::Model, MixiniError> {
+    PostRevision::find_by_id((post_id, version))
+        .one(db)
+        .await?
+        .ok_or(MixiniError::NotFound)
+}
+
+/// Fetch the `page`th page of the revisions of `post_id`, newest first, with what each changed.
+pub async fn list_revisions<C: ConnectionTrait>(
+    db: &C,
+    post_id: Uuid,
+    page: usize,
+) -> Result<Vec<RevisionResponse>, MixiniError> {
+    // one more than a page, to tell what the oldest revision on it changed
+    let revisions = PostRevision::find()
+        .filter(post_revision::Column::PostId.eq(post_id))
+        .order_by_desc(post_revision::Column::Version)
+        // pages this far out are past the end anyway
+        .offset(page.saturating_mul(REVISIONS_PER_PAGE) as u64)
+        .limit(REVISIONS_PER_PAGE as u64 + 1)
+        .all(db)
+        .await?;
+    let snapshots = revisions
+        .iter()
+        .map(PostSnapshot::of)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(revisions
+        .into_iter()
+        .enumerate()
+        .take(REVISIONS_PER_PAGE)
+        .map(|(i, revision)| RevisionResponse {
+            changed: snapshots
+                .get(i + 1)
+                .map_or_else(Vec::new, |previous| previous.changed_fields(&snapshots[i])),
+            revision,
+        })
+        .collect())
+}
+
+/// Compare versions `from` and `to` of `post_id`.
+pub async fn diff_revisions<C: ConnectionTrait>(
+    db: &C,
+    post_id: Uuid,
+    from: i32,
+    to: i32,
+) -> Result<RevisionDiff, MixiniError> {
+    let from_snapshot = PostSnapshot::of(&find_revision(db, post_id, from).await?)?;
+    let to_snapshot = PostSnapshot::of(&find_revision(db, post_id, to).await?)?;
+
+    Ok(RevisionDiff {
+        from,
+        to,
+        diff: SnapshotDiff::new(&from_snapshot, &to_snapshot),
+    })
+}
+
+/// The revisions of `post_id` after `version`, oldest first.
+pub async fn revisions_since<C: ConnectionTrait>(
+    db: &C,
+    post_id: Uuid,
+    version: i32,
+) -> Result<Vec<post_revision::Model>, DbErr> {
+    PostRevision::find()
+        .filter(post_revision::Column::PostId.eq(post_id))
+        .filter(post_revision::Column::Version.gt(version))
+        .order_by_asc(post_revision::Column::Version)
+        .all(db)
+        .await
+}
+
+/// Lock `post_id` until `txn` ends, and fetch it.
+pub async fn lock_post(
+    txn: &DatabaseTransaction,
+    post_id: Uuid,
+) -> Result<post::Model, MixiniError> {
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT id FROM post WHERE id = $1 FOR UPDATE",
+        vec![post_id.into()],
+    ))
+    .await?;
+
+    Post::find_by_id(post_id)
+        .one(txn)
+        .await?
+        .ok_or(MixiniError::NotFound)
+}
+
+/// Record how `post` is now as its first version, if nothing has been recorded for it yet.
+///
+/// Call this with the post locked, before editing it, so its history starts from before the edit.
+pub async fn ensure_first_revision<C: ConnectionTrait>(
+    db: &C,
+    post: &post::Model,
+) -> Result<(), MixiniError> {
+    let recorded = PostRevision::find()
+        .filter(post_revision::Column::PostId.eq(post.id))
+        .one(db)
+        .await?
+        .is_some();
+    if recorded {
+        return Ok(());
+    }
+
+    let uploader_role = UserAccount::find_by_id(post.uploader_id)
+        .one(db)
+        .await?
+        .map_or(UserRole::Member, |uploader| uploader.role);
+    let snapshot = PostSnapshot::load(db, post).await?;
+    // a first version that was already recorded is never replaced
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "INSERT INTO post_revision \
+         (post_id, version, created_at, editor_id, editor_role, tags, source_urls, rating, description) \
+         VALUES ($1, 1, $2, $3, CAST($4 AS user_role), $5, $6, CAST($7 AS post_rating), $8) \
+         ON CONFLICT DO NOTHING",
+        vec![
+            post.id.into(),
+            post.created_at.into(),
+            post.uploader_id.into(),
+            uploader_role.to_value().into(),
+            serde_json::to_value(&snapshot.tags)?.into(),
+            serde_json::to_value(&snapshot.source_urls)?.into(),
+            snapshot.rating.to_value().into(),
+            snapshot.description.into(),
+        ],
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// The revision of `post_id` after `latest` with the content `snapshot`, by `editor` and restoring
+/// `reverted_to` if given, or `None` if the content is the same as in `latest`.
+pub fn next_revision(
+    post_id: Uuid,
+    latest: Option<&post_revision::Model>,
+    snapshot: PostSnapshot,
+    editor: &user_account::Model,
+    reverted_to: Option<i32>,
+) -> Result<Option<post_revision::ActiveModel>, MixiniError> {
+    let version = match latest {
+        Some(latest) if PostSnapshot::of(latest)? == snapshot => return Ok(None),
+        Some(latest) => latest.version + 1,
+        None => 1,
+    };
+
+    Ok(Some(post_revision::ActiveModel {
+        post_id: Set(post_id),
+        version: Set(version),
+        editor_id: Set(Some(editor.id)),
+        editor_role: Set(editor.role.to_owned()),
+        tags: Set(serde_json::to_value(snapshot.tags)?),
+        source_urls: Set(serde_json::to_value(snapshot.source_urls)?),
+        rating: Set(snapshot.rating),
+        description: Set(snapshot.description),
+        reverted_to: Set(reverted_to),
+        ..Default::default()
+    }))
+}
+
+/// Record how `post_id` is now as a revision by `editor`, restoring `reverted_to` if given.
+///
+/// `txn` must hold the lock on the post from `lock_post` and have made the edit being recorded.
+/// Nothing is recorded if the post is the same as in its latest revision.
+pub async fn record_revision(
+    txn: &DatabaseTransaction,
+    post_id: Uuid,
+    editor: &user_account::Model,
+    reverted_to: Option<i32>,
+) -> Result<Option<post_revision::Model>, MixiniError> {
+    let post = Post::find_by_id(post_id)
+        .one(txn)
+        .await?
+        .ok_or(MixiniError::NotFound)?;
+    let snapshot = PostSnapshot::load(txn, &post).await?;
+    let latest = PostRevision::find()
+        .filter(post_revision::Column::PostId.eq(post_id))
+        .order_by_desc(post_revision::Column::Version)
+        .one(txn)
+        .await?;
+
+    match next_revision(post_id, latest.as_ref(), snapshot, editor, reverted_to)? {
+        Some(revision) => Ok(Some(revision.insert(txn).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Restore `post` to how it was in `revision`, as `editor`, recording that as a new revision.
+///
+/// `txn` must hold the lock on the post from `lock_post`. Whether `editor` may undo the revisions
+/// since is checked separately.
+pub async fn revert_post(
+    txn: &DatabaseTransaction,
+    post: post::Model,
+    revision: &post_revision::Model,
+    editor: &user_account::Model,
+) -> Result<Option<post_revision::Model>, MixiniError> {
+    let snapshot = PostSnapshot::of(revision)?;
+
+    set_post_tags_in(txn, post.id, &snapshot.tags).await?;
+    let post = snapshot.restore(post)?.update(txn).await?;
+
+    record_revision(txn, post.id, editor, Some(revision.version)).await
+}
@@ -0,0 +1,192 @@
+//! Running parsed search queries against the database.
+use entity::{post, post_tag, prelude::*, tag};
+use oso::Oso;
+use sea_orm::{
+    prelude::*,
+    sea_query::{Expr as SqlExpr, Order as SqlOrder, Query},
+    Condition, QueryOrder,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+use crate::{
+    auth::Auth,
+    constants::{GUEST_MAX_SEARCH_TERMS, POSTS_PER_PAGE},
+    error::MixiniError,
+    preferences::listing_filter,
+    search::{
+        rank_expr, text_condition, text_matches, validate_text, Expr, Order, SearchQuery, Term,
+        TextMatch,
+    },
+    tags::resolve_tags,
+};
+
+/// The most terms `auth` may use in a single search.
+pub fn max_search_terms(auth: &Auth) -> usize {
+    match auth {
+        Auth::KnownUser(user) => user.role.max_search_terms(),
+        Auth::UnknownUser(_) => GUEST_MAX_SEARCH_TERMS,
+    }
+}
+
+/// Every exact tag name in `expr`, so they can be resolved in one go.
+pub fn tag_names(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::Term(Term::Tag(name)) => names.push(name.to_owned()),
+        Expr::Term(_) => {}
+        Expr::Not(expr) => tag_names(expr, names),
+        Expr::All(exprs) | Expr::Any(exprs) => exprs.iter().for_each(|expr| tag_names(expr, names)),
+    }
+}
+
+/// Turn a wildcard tag pattern into a `LIKE` pattern.
+fn like_pattern(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('*', "%")
+}
+
+/// Compile a search expression into a condition on posts.
+///
+/// `tags` maps every exact tag name in `expr` to its canonical tag; names missing from it match
+/// no posts.
+pub fn search_condition(expr: &Expr, tags: &HashMap<String, tag::Model>) -> Condition {
+    match expr {
+        Expr::Term(term) => {
+            let condition = match term {
+                Term::Tag(name) => match tags.get(name) {
+                    Some(tag) => post::Column::Id.in_subquery(
+                        Query::select()
+                            .column(post_tag::Column::PostId)
+                            .from(PostTag)
+                            .and_where(post_tag::Column::TagId.eq(tag.id))
+                            .to_owned(),
+                    ),
+                    None => SqlExpr::cust("FALSE"),
+                },
+                Term::TagPattern(pattern) => post::Column::Id.in_subquery(
+                    Query::select()
+                        .column((PostTag, post_tag::Column::PostId))
+                        .from(PostTag)
+                        .inner_join(
+                            Tag,
+                            SqlExpr::tbl(Tag, tag::Column::Id)
+                                .equals(PostTag, post_tag::Column::TagId),
+                        )
+                        .and_where(tag::Column::Name.like(&like_pattern(pattern)))
+                        .to_owned(),
+                ),
+                Term::CategoryTag(category, pattern) => post::Column::Id.in_subquery(
+                    Query::select()
+                        .column((PostTag, post_tag::Column::PostId))
+                        .from(PostTag)
+                        .inner_join(
+                            Tag,
+                            SqlExpr::tbl(Tag, tag::Column::Id)
+                                .equals(PostTag, post_tag::Column::TagId),
+                        )
+                        .and_where(tag::Column::Category.eq(category.to_owned()))
+                        .and_where(tag::Column::Name.like(&like_pattern(pattern)))
+                        .to_owned(),
+                ),
+                Term::Rating(rating) => post::Column::Rating.eq(rating.to_owned()),
+            };
+            Condition::all().add(condition)
+        }
+        Expr::Not(expr) => search_condition(expr, tags).not(),
+        Expr::All(exprs) => exprs.iter().fold(Condition::all(), |all, expr| {
+            all.add(search_condition(expr, tags))
+        }),
+        Expr::Any(exprs) => exprs.iter().fold(Condition::any(), |any, expr| {
+            any.add(search_condition(expr, tags))
+        }),
+    }
+}
+
+/// A post found by a search.
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub post: post::Model,
+    /// How the post matched the free text searched for, if any.
+    #[serde(flatten)]
+    pub text_match: Option<TextMatch>,
+    /// Whether the post is on the searcher's blacklist, which they chose to have marked rather
+    /// than left out.
+    pub blacklisted: bool,
+}
+
+/// Fetch the `page`th page of posts matching `query` and the free text `text` that `auth` may
+/// read and wants to be shown.
+///
+/// Results are ordered by `order:` if given, otherwise by how well they match `text` when
+/// searching text, or newest first when not.
+pub async fn search_posts(
+    db: &DatabaseConnection,
+    oso: &Mutex<Oso>,
+    auth: &Auth,
+    query: &SearchQuery,
+    text: Option<&str>,
+    page: usize,
+) -> Result<Vec<SearchResult>, MixiniError> {
+    let text = text.map(str::trim).filter(|text| !text.is_empty());
+    if let Some(text) = text {
+        validate_text(text)?;
+    }
+
+    let mut names = Vec::new();
+    tag_names(&query.expr, &mut names);
+    let tags = resolve_tags(db, &names).await?;
+
+    let filter = listing_filter(db, oso, auth).await?;
+    let mut select = Post::find()
+        .filter(filter.condition.to_owned())
+        .filter(search_condition(&query.expr, &tags));
+    if let Some(text) = text {
+        select = select.filter(text_condition(text));
+    }
+
+    let order = query.order.unwrap_or(if text.is_some() {
+        Order::Rank
+    } else {
+        Order::New
+    });
+    let select = match (order, text) {
+        (Order::Rank, Some(text)) => select
+            .order_by(rank_expr(text), SqlOrder::Desc)
+            .order_by_desc(post::Column::CreatedAt),
+        (Order::New | Order::Rank, _) => select.order_by_desc(post::Column::CreatedAt),
+        (Order::Old, _) => select.order_by_asc(post::Column::CreatedAt),
+        (Order::Score, _) => select
+            .order_by_desc(post::Column::FavoriteCount)
+            .order_by_desc(post::Column::CreatedAt),
+        (Order::ScoreAsc, _) => select
+            .order_by_asc(post::Column::FavoriteCount)
+            .order_by_desc(post::Column::CreatedAt),
+    };
+
+    let posts = select
+        .order_by_asc(post::Column::Id)
+        .paginate(db, POSTS_PER_PAGE)
+        .fetch_page(page)
+        .await?;
+
+    let ids: Vec<Uuid> = posts.iter().map(|post| post.id).collect();
+    let mut text_matches = match text {
+        Some(text) => text_matches(db, text, &ids).await?,
+        None => HashMap::new(),
+    };
+    let blacklisted = filter.marked_ids(db, &ids).await?;
+
+    Ok(posts
+        .into_iter()
+        .map(|post| SearchResult {
+            text_match: text_matches.remove(&post.id),
+            blacklisted: blacklisted.contains(&post.id),
+            post,
+        })
+        .collect())
+}
@@ -0,0 +1,8 @@
+//! The post search query language.
+pub mod execute;
+pub mod parse;
+pub mod text;
+
+pub use execute::*;
+pub use parse::*;
+pub use text::*;
@@ -0,0 +1,408 @@
+//! Parsing search queries.
+//!
+//! A query is a whitespace-separated list of terms that must all match:
+//!
+//! - `cat` matches posts tagged `cat`, or with a tag `cat` is an alias of.
+//! - `*cat*` matches posts with any tag matching the wildcard pattern.
+//! - `artist:foo` and `artist:*foo*` only match tags of that category.
+//! - `rating:safe` (or `rating:s`) matches posts with that rating.
+//! - `-term` negates a term, `a ~ b` matches either term, and `( ... )` groups terms. Groups and
+//!   negations can be nested at most `MAX_SEARCH_NESTING` deep.
+//! - `order:score` sorts the results. It may only appear once, outside of any group.
+//!
+//! Free text is searched separately, see `search::text`.
+use entity::sea_orm_active_enums::{PostRating, TagCategory};
+use serde::Serialize;
+use std::{iter::Peekable, vec::IntoIter};
+use thiserror::Error;
+
+use crate::{
+    constants::{MAX_SEARCH_NESTING, RE_TAG_NAME},
+    tags::normalize_tag_name,
+};
+
+/// A query that failed to parse, with the position in the query where parsing failed.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize)]
+#[error("{message} (at position {position})")]
+pub struct ParseError {
+    /// The character offset into the query, counting from 0.
+    pub position: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+/// A single condition on a post.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    /// A tag by name, which may be an alias.
+    Tag(String),
+    /// Any tag matching a wildcard pattern.
+    TagPattern(String),
+    /// Any tag of a category matching a wildcard pattern.
+    CategoryTag(TagCategory, String),
+    Rating(PostRating),
+}
+
+/// A search expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Term(Term),
+    Not(Box<Expr>),
+    /// Every expression must match. Matches everything if empty.
+    All(Vec<Expr>),
+    /// Any expression must match.
+    Any(Vec<Expr>),
+}
+
+/// The order search results are returned in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Newest first.
+    New,
+    /// Oldest first.
+    Old,
+    /// Most favorited first.
+    Score,
+    /// Least favorited first.
+    ScoreAsc,
+    /// Best free text match first. The default when searching text.
+    Rank,
+}
+
+/// A parsed search query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub expr: Expr,
+    /// The order given with `order:`, if any.
+    pub order: Option<Order>,
+    /// The number of terms in `expr`.
+    pub terms: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Or,
+    Not,
+    Word(String),
+}
+
+/// Split a query into tokens, each with its character offset.
+///
+/// Parentheses may appear in tag names, so `(` only opens a group at the start of a word, and `)`
+/// only closes one at the end of a word when it isn't balanced within the word.
+fn tokenize(input: &str) -> Vec<(usize, Token)> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = input.chars().collect();
+    let mut index = 0;
+
+    while index < chars.len() {
+        if chars[index].is_whitespace() {
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < chars.len() && !chars[index].is_whitespace() {
+            index += 1;
+        }
+        let word = &chars[start..index];
+
+        if word == ['~'] {
+            tokens.push((start, Token::Or));
+            continue;
+        }
+
+        let mut head = 0;
+        while head < word.len() && (word[head] == '(' || word[head] == '-') {
+            let token = if word[head] == '(' {
+                Token::Open
+            } else {
+                Token::Not
+            };
+            tokens.push((start + head, token));
+            head += 1;
+        }
+
+        let mut tail = word.len();
+        let count = |c: char, range: &[char]| range.iter().filter(|&&x| x == c).count();
+        while tail > head
+            && word[tail - 1] == ')'
+            && count(')', &word[head..tail]) > count('(', &word[head..tail])
+        {
+            tail -= 1;
+        }
+
+        if tail > head {
+            tokens.push((start + head, Token::Word(word[head..tail].iter().collect())));
+        }
+        for offset in tail..word.len() {
+            tokens.push((start + offset, Token::Close));
+        }
+    }
+
+    tokens
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<(usize, Token)>>,
+    /// The position just past the end of the query.
+    end: usize,
+    max_terms: usize,
+    terms: usize,
+    /// The number of groups and negations around the current token.
+    nesting: usize,
+    order: Option<Order>,
+}
+
+impl Parser {
+    /// Enter a group or negation at `position`. Parsing and everything done with the parsed
+    /// expression recurses once per level, so the levels are limited.
+    fn nest(&mut self, position: usize) -> Result<(), ParseError> {
+        self.nesting += 1;
+        if self.nesting > MAX_SEARCH_NESTING {
+            return Err(ParseError::new(
+                position,
+                format!(
+                    "Groups and negations can be nested at most {} deep",
+                    MAX_SEARCH_NESTING
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// `and := or*`, until the end of the query or a closing parenthesis.
+    fn parse_and(&mut self, depth: usize) -> Result<Expr, ParseError> {
+        let mut exprs = Vec::new();
+        while let Some((position, token)) = self.tokens.peek() {
+            match token {
+                Token::Close if depth > 0 => break,
+                Token::Close => return Err(ParseError::new(*position, "Unmatched `)`")),
+                _ => {
+                    if let Some(expr) = self.parse_or(depth)? {
+                        exprs.push(expr);
+                    }
+                }
+            }
+        }
+        Ok(Expr::All(exprs))
+    }
+
+    /// `or := not ("~" not)*`
+    fn parse_or(&mut self, depth: usize) -> Result<Option<Expr>, ParseError> {
+        let first_position = self.position();
+        let first = self.parse_not(depth, false)?;
+        let mut alternatives = Vec::new();
+
+        while let Some((position, Token::Or)) = self.tokens.peek() {
+            let position = *position;
+            self.tokens.next();
+            match self.parse_not(depth, true)? {
+                Some(expr) => alternatives.push(expr),
+                None => return Err(ParseError::new(position, "`order:` can't be used with `~`")),
+            }
+        }
+
+        match first {
+            Some(first) if !alternatives.is_empty() => {
+                alternatives.insert(0, first);
+                Ok(Some(Expr::Any(alternatives)))
+            }
+            None if !alternatives.is_empty() => Err(ParseError::new(
+                first_position,
+                "`order:` can't be used with `~`",
+            )),
+            first => Ok(first),
+        }
+    }
+
+    /// `not := "-" not | atom`
+    ///
+    /// Returns `None` for `order:` terms, which only affect the order of results.
+    fn parse_not(&mut self, depth: usize, nested: bool) -> Result<Option<Expr>, ParseError> {
+        match self.tokens.next() {
+            Some((position, Token::Not)) => {
+                self.nest(position)?;
+                let expr = self.parse_not(depth, true)?;
+                self.nesting -= 1;
+                match expr {
+                    Some(expr) => Ok(Some(Expr::Not(Box::new(expr)))),
+                    None => Err(ParseError::new(position, "`order:` can't be negated")),
+                }
+            }
+            Some((position, Token::Open)) => {
+                self.nest(position)?;
+                let group = self.parse_and(depth + 1)?;
+                self.nesting -= 1;
+                match self.tokens.next() {
+                    Some((_, Token::Close)) => {}
+                    _ => return Err(ParseError::new(position, "Unclosed `(`")),
+                }
+                match group {
+                    Expr::All(exprs) if exprs.is_empty() => {
+                        Err(ParseError::new(position, "Empty group"))
+                    }
+                    Expr::All(mut exprs) if exprs.len() == 1 => Ok(exprs.pop()),
+                    group => Ok(Some(group)),
+                }
+            }
+            Some((position, Token::Word(word))) => self.parse_word(position, &word, depth, nested),
+            Some((position, Token::Or)) => {
+                Err(ParseError::new(position, "Expected a term before `~`"))
+            }
+            Some((position, Token::Close)) => {
+                Err(ParseError::new(position, "Expected a term before `)`"))
+            }
+            None => Err(ParseError::new(self.end, "Expected a term")),
+        }
+    }
+
+    fn parse_word(
+        &mut self,
+        position: usize,
+        word: &str,
+        depth: usize,
+        nested: bool,
+    ) -> Result<Option<Expr>, ParseError> {
+        let term = match word.split_once(':') {
+            Some((key, value)) => {
+                // positions in errors about the value point past the key and colon
+                let value_position = position + key.chars().count() + 1;
+                match key.to_lowercase().as_str() {
+                    "order" => {
+                        if depth > 0 || nested {
+                            return Err(ParseError::new(
+                                position,
+                                "`order:` can only be used at the top level",
+                            ));
+                        }
+                        if self.order.is_some() {
+                            return Err(ParseError::new(
+                                position,
+                                "`order:` can only be used once",
+                            ));
+                        }
+                        self.order = Some(parse_order(value_position, value)?);
+                        return Ok(None);
+                    }
+                    "rating" => Term::Rating(parse_rating(value_position, value)?),
+                    key => match parse_category(key) {
+                        Some(category) => {
+                            Term::CategoryTag(category, parse_pattern(value_position, value)?)
+                        }
+                        None => {
+                            return Err(ParseError::new(
+                                position,
+                                format!("Unknown search key `{}`", key),
+                            ))
+                        }
+                    },
+                }
+            }
+            None if word.contains('*') => Term::TagPattern(parse_pattern(position, word)?),
+            None => {
+                let name = normalize_tag_name(word);
+                if !RE_TAG_NAME.is_match(&name) {
+                    return Err(ParseError::new(
+                        position,
+                        format!("`{}` is not a valid tag", word),
+                    ));
+                }
+                Term::Tag(name)
+            }
+        };
+
+        self.terms += 1;
+        if self.terms > self.max_terms {
+            return Err(ParseError::new(
+                position,
+                format!("Searches are limited to {} terms", self.max_terms),
+            ));
+        }
+        Ok(Some(Expr::Term(term)))
+    }
+
+    fn position(&mut self) -> usize {
+        self.tokens
+            .peek()
+            .map_or(self.end, |(position, _)| *position)
+    }
+}
+
+fn parse_order(position: usize, value: &str) -> Result<Order, ParseError> {
+    match value.to_lowercase().as_str() {
+        "new" => Ok(Order::New),
+        "old" => Ok(Order::Old),
+        "score" => Ok(Order::Score),
+        "score_asc" => Ok(Order::ScoreAsc),
+        "rank" => Ok(Order::Rank),
+        _ => Err(ParseError::new(
+            position,
+            "Order must be one of new, old, score, score_asc, or rank",
+        )),
+    }
+}
+
+fn parse_rating(position: usize, value: &str) -> Result<PostRating, ParseError> {
+    match value.to_lowercase().as_str() {
+        "s" | "safe" => Ok(PostRating::Safe),
+        "q" | "questionable" => Ok(PostRating::Questionable),
+        "e" | "explicit" => Ok(PostRating::Explicit),
+        _ => Err(ParseError::new(
+            position,
+            "Rating must be one of safe, questionable, or explicit",
+        )),
+    }
+}
+
+fn parse_category(key: &str) -> Option<TagCategory> {
+    match key {
+        "artist" => Some(TagCategory::Artist),
+        "character" => Some(TagCategory::Character),
+        "copyright" => Some(TagCategory::Copyright),
+        "general" => Some(TagCategory::General),
+        "meta" => Some(TagCategory::Meta),
+        _ => None,
+    }
+}
+
+/// Normalize a tag name that may contain `*` wildcards.
+fn parse_pattern(position: usize, value: &str) -> Result<String, ParseError> {
+    let pattern = normalize_tag_name(value);
+    let literal = pattern.replace('*', "");
+    if pattern.is_empty() || !(literal.is_empty() || RE_TAG_NAME.is_match(&literal)) {
+        return Err(ParseError::new(
+            position,
+            format!("`{}` is not a valid tag pattern", value),
+        ));
+    }
+    Ok(pattern)
+}
+
+/// Parse a search query, allowing at most `max_terms` terms.
+pub fn parse_query(input: &str, max_terms: usize) -> Result<SearchQuery, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(input).into_iter().peekable(),
+        end: input.chars().count(),
+        max_terms,
+        terms: 0,
+        nesting: 0,
+        order: None,
+    };
+    let expr = parser.parse_and(0)?;
+
+    Ok(SearchQuery {
+        expr,
+        order: parser.order,
+        terms: parser.terms,
+    })
+}
@@ -0,0 +1,149 @@
+//! Free text search over post titles and descriptions, and the names and wikis of their tags.
+//!
+//! Both `post` and `tag` keep a `search_vector` column up to date through the
+//! `manage_search_vector` trigger, which is what every query here matches against. Queries use
+//! `websearch_to_tsquery`, so quoted phrases, `or` and `-word` work as they do on search engines.
+use entity::{post, post_tag, prelude::*, tag};
+use sea_orm::{
+    prelude::*,
+    sea_query::{Expr, Query, SimpleExpr},
+    Condition, DbBackend, FromQueryResult, Statement,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{constants::MAX_SEARCH_TEXT_CHARS, search::ParseError};
+
+/// Characters marking the start and end of matches in headlines. They're removed from the text
+/// before it's highlighted, so only `ts_headline` can put them there.
+const MATCH_START: char = '\u{e000}';
+const MATCH_STOP: char = '\u{e001}';
+
+/// Options for `ts_headline`, marking matches with `MATCH_START` and `MATCH_STOP`.
+const HEADLINE_OPTIONS: &str = "StartSel=\u{e000}, StopSel=\u{e001}, MaxFragments=3";
+
+/// Reject search text that is too long to search.
+pub fn validate_text(text: &str) -> Result<(), ParseError> {
+    if text.chars().count() > MAX_SEARCH_TEXT_CHARS {
+        return Err(ParseError {
+            position: MAX_SEARCH_TEXT_CHARS,
+            message: format!(
+                "Search text is limited to {} characters",
+                MAX_SEARCH_TEXT_CHARS
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Condition matching posts whose title or description matches `text`, or that have a tag whose
+/// name or wiki does.
+pub fn text_condition(text: &str) -> Condition {
+    Condition::any()
+        .add(Expr::cust_with_values(
+            r#""post"."search_vector" @@ websearch_to_tsquery('english', ?)"#,
+            vec![text],
+        ))
+        .add(
+            post::Column::Id.in_subquery(
+                Query::select()
+                    .column((PostTag, post_tag::Column::PostId))
+                    .from(PostTag)
+                    .inner_join(
+                        Tag,
+                        Expr::tbl(Tag, tag::Column::Id).equals(PostTag, post_tag::Column::TagId),
+                    )
+                    .and_where(Expr::cust_with_values(
+                        r#""tag"."search_vector" @@ websearch_to_tsquery('english', ?)"#,
+                        vec![text],
+                    ))
+                    .to_owned(),
+            ),
+        )
+}
+
+/// How well a post's title and description match `text`, for ordering by.
+pub fn rank_expr(text: &str) -> SimpleExpr {
+    Expr::cust_with_values(
+        r#"ts_rank("post"."search_vector", websearch_to_tsquery('english', ?))"#,
+        vec![text],
+    )
+}
+
+/// How a single post matched a text search.
+#[derive(Debug, Clone, FromQueryResult, Serialize)]
+pub struct TextMatch {
+    #[serde(skip)]
+    pub id: Uuid,
+    /// How well the title and description matched. Posts only matched through their tags rank 0.
+    pub rank: f32,
+    /// Snippets of the title and description as HTML, with matches wrapped in `<mark>`.
+    pub headline: String,
+}
+
+/// Turn a headline marked with `MATCH_START` and `MATCH_STOP` into HTML, escaping everything
+/// but the `<mark>`s around matches.
+pub fn headline_html(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+    for c in headline.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_STOP => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Rank and highlight the posts `ids` against `text`.
+pub async fn text_matches(
+    db: &DatabaseConnection,
+    text: &str,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, TextMatch>, DbErr> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let placeholders: Vec<String> = (0..ids.len())
+        .map(|index| format!("${}", index + 4))
+        .collect();
+    let sql = format!(
+        r#"SELECT "id",
+                  ts_rank("search_vector", "query") AS "rank",
+                  ts_headline(
+                      'english',
+                      translate("title" || ' ' || "description", $3, ''),
+                      "query",
+                      $2
+                  ) AS "headline"
+           FROM "post", websearch_to_tsquery('english', $1) AS "query"
+           WHERE "id" IN ({})"#,
+        placeholders.join(", ")
+    );
+    let mut values: Vec<Value> = vec![
+        text.into(),
+        HEADLINE_OPTIONS.into(),
+        String::from_iter([MATCH_START, MATCH_STOP]).into(),
+    ];
+    values.extend(ids.iter().map(|id| Value::from(*id)));
+
+    Ok(TextMatch::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        &sql,
+        values,
+    ))
+    .all(db)
+    .await?
+    .into_iter()
+    .map(|mut text_match| {
+        text_match.headline = headline_html(&text_match.headline);
+        (text_match.id, text_match)
+    })
+    .collect())
+}
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Extension, Router,
 };
 use lettre::{
@@ -9,7 +9,12 @@ use lettre::{
 };
 use oso::Oso;
 use sea_orm::{Database, DatabaseConnection};
-use std::{str::FromStr, sync::Arc};
+use std::{
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
 use tokio::sync::Mutex;
 use tower::ServiceBuilder;
 use tower_http::{
@@ -17,22 +22,49 @@ use tower_http::{
     trace::TraceLayer,
 };
 
-use crate::{actions::try_register_oso, handlers};
+use crate::{
+    handlers,
+    importers::ImporterRegistry,
+    media::{
+        backfill_hashes, load_similarity_index, spawn_similarity_refresh, try_store_from_env,
+        BkTree, MediaStore, PreviewSizes,
+    },
+    permissions::{self, Grants},
+    policy::{try_load_policy, watch_policy, LoadedRules},
+};
 
 #[allow(missing_debug_implementations)]
 #[derive(Clone)]
 pub struct State {
     pub oso: Arc<Mutex<Oso>>,
+    /// The rules loaded into `oso`.
+    pub policy_rules: LoadedRules,
+    /// The fine-grained permissions the policies in `oso` check.
+    pub grants: Arc<Grants>,
     pub db: DatabaseConnection,
     pub redis_manager: redis::aio::ConnectionManager,
     pub mailsender: AsyncSmtpTransport<Tokio1Executor>,
+    pub importers: Arc<ImporterRegistry>,
+    /// Where media is stored.
+    pub media: Arc<dyn MediaStore>,
+    /// Where uploads are received before they're stored.
+    pub upload_dir: PathBuf,
+    pub preview_sizes: PreviewSizes,
+    /// The perceptual hashes of every post's media, for finding similar posts.
+    pub similarity_index: Arc<RwLock<BkTree>>,
+    /// The addresses of the reverse proxies whose forwarded-for headers are believed.
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 impl State {
     /// Attempt to create a new State instance
     pub async fn try_new() -> Result<State> {
-        let oso = Arc::new(Mutex::new(try_register_oso()?));
         let db = Database::connect(&std::env::var("DATABASE_URL")?).await?;
+        let grants = Arc::new(Grants::default());
+        grants.refresh(&db).await?;
+        let (oso, rules) = try_load_policy(&grants)?;
+        let oso = Arc::new(Mutex::new(oso));
+        let policy_rules = Arc::new(RwLock::new(rules));
         let redis_manager = redis::Client::open(std::env::var("REDIS_URL")?)?
             .get_tokio_connection_manager()
             .await?;
@@ -46,12 +78,36 @@ impl State {
                 // Configure expected authentication mechanism
                 .authentication(vec![Mechanism::Plain])
                 .build();
+        let importers = Arc::new(ImporterRegistry::try_default()?);
+        let media = try_store_from_env()?;
+        let upload_dir = std::env::var("UPLOAD_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("mixini-uploads"));
+        let preview_sizes = PreviewSizes::try_from_env()?;
+        let similarity_index = Arc::new(RwLock::new(load_similarity_index(&db).await?));
+        let trusted_proxies = match std::env::var("TRUSTED_PROXIES") {
+            Ok(proxies) => proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(_) => Vec::new(),
+        };
 
         Ok(State {
             oso,
+            policy_rules,
+            grants,
             db,
             redis_manager,
             mailsender,
+            importers,
+            media,
+            upload_dir,
+            preview_sizes,
+            similarity_index,
+            trusted_proxies,
         })
     }
 }
@@ -84,32 +140,159 @@ pub async fn run() -> Result<()> {
     tracing::debug!("listening on {}", addr);
 
     axum::Server::bind(&addr)
-        .serve(try_app().await?.into_make_service())
+        .serve(
+            try_app()
+                .await?
+                .into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        )
         .await?;
     Ok(())
 }
 
 async fn try_app() -> Result<Router> {
     let state = State::try_new().await?;
+    watch_policy(
+        state.oso.clone(),
+        state.policy_rules.clone(),
+        state.grants.clone(),
+    )?;
+    permissions::spawn_refresh(state.grants.clone(), state.db.clone());
+    spawn_similarity_refresh(state.db.clone(), state.similarity_index.clone());
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            match backfill_hashes(
+                &state.db,
+                state.media.as_ref(),
+                &state.upload_dir,
+                &state.similarity_index,
+            )
+            .await
+            {
+                Ok(0) => {}
+                Ok(hashed) => tracing::info!("Hashed the media of {} older posts", hashed),
+                Err(e) => tracing::error!("Failed to hash the media of older posts: {:?}", e),
+            }
+        });
+    }
 
     let middleware_stack = ServiceBuilder::new()
         .layer(TraceLayer::new_for_http())
-        .layer(Extension(state))
+        .layer(Extension(Arc::new(state)))
         .layer(try_cors_layer()?);
 
     Ok(Router::new()
         .route("/", get(|| async { "Hello, World!" }))
-        .route("/user", post(handlers::create_user))
+        .route(
+            "/user",
+            get(handlers::list_users).post(handlers::create_user),
+        )
         .route(
             "/user/verify",
             post(handlers::create_verify_user).put(handlers::update_verify_user),
         )
+        .route(
+            "/user/preferences",
+            get(handlers::get_preferences).put(handlers::update_preferences),
+        )
         .route(
             "/user/:id",
             get(handlers::get_user)
                 .put(handlers::update_user)
                 .delete(handlers::delete_user),
         )
+        .route("/user/:id/favorites", get(handlers::list_favorites))
+        .route(
+            "/user/:id/collections",
+            get(handlers::list_user_collections),
+        )
         .route("/login", post(handlers::login).delete(handlers::logout))
+        .route(
+            "/post",
+            get(handlers::list_posts).post(handlers::create_post),
+        )
+        .route("/post/import", post(handlers::import_post))
+        .route("/post/upload", post(handlers::upload_post))
+        .route(
+            "/post/similar",
+            get(handlers::list_similar_posts).post(handlers::find_similar_posts),
+        )
+        .route(
+            "/post/:id",
+            get(handlers::get_post)
+                .put(handlers::update_post)
+                .delete(handlers::delete_post),
+        )
+        .route("/post/:id/media", get(handlers::get_post_media))
+        .route("/post/:id/preview/:name", get(handlers::get_post_preview))
+        .route(
+            "/post/:id/tags",
+            get(handlers::get_post_tags).put(handlers::update_post_tags),
+        )
+        .route("/post/:id/revisions", get(handlers::list_post_revisions))
+        .route(
+            "/post/:id/revisions/diff",
+            get(handlers::diff_post_revisions),
+        )
+        .route(
+            "/post/:id/revisions/:version",
+            get(handlers::get_post_revision),
+        )
+        .route(
+            "/post/:id/revisions/:version/revert",
+            post(handlers::revert_post_revision),
+        )
+        .route(
+            "/post/:id/favorite",
+            get(handlers::get_favorite)
+                .put(handlers::favorite_post)
+                .delete(handlers::unfavorite_post),
+        )
+        .route(
+            "/post/:id/comments",
+            get(handlers::list_comments).post(handlers::post_comment),
+        )
+        .route(
+            "/comment/:id",
+            get(handlers::get_comment)
+                .put(handlers::update_comment)
+                .delete(handlers::delete_comment),
+        )
+        .route("/comment/:id/history", get(handlers::get_comment_history))
+        .route("/comment/:id/hide", post(handlers::hide_comment))
+        .route("/comment/:id/restore", post(handlers::restore_comment))
+        .route("/collection", post(handlers::create_collection))
+        .route(
+            "/collection/:id",
+            get(handlers::get_collection)
+                .put(handlers::update_collection)
+                .delete(handlers::delete_collection),
+        )
+        .route(
+            "/collection/:id/posts",
+            get(handlers::list_collection_posts)
+                .post(handlers::create_collection_post)
+                .put(handlers::reorder_collection),
+        )
+        .route(
+            "/collection/:id/posts/:post_id",
+            delete(handlers::delete_collection_post),
+        )
+        .route(
+            "/collection/:id/collaborator",
+            post(handlers::create_collaborator),
+        )
+        .route(
+            "/collection/:id/collaborator/:user_id",
+            delete(handlers::delete_collaborator),
+        )
+        .route("/tag", post(handlers::create_tag))
+        .route("/tag/alias", post(handlers::create_tag_alias))
+        .route("/tag/implication", post(handlers::create_tag_implication))
+        .route("/tag/:id", get(handlers::get_tag).put(handlers::update_tag))
+        .route("/admin/policy/reload", post(handlers::reload_policy))
+        .route("/admin/explain", get(handlers::explain))
+        .route("/admin/permission", post(handlers::grant_permission))
+        .route("/admin/permission/:id", delete(handlers::revoke_permission))
         .layer(middleware_stack))
 }
@@ -0,0 +1,295 @@
+//! Tag resolution: canonical names, aliases and implications.
+use entity::{post_tag, prelude::*, tag, tag_alias, tag_implication};
+use sea_orm::{
+    entity::*, prelude::*, ConnectionTrait, DatabaseTransaction, DbBackend, QueryOrder, Statement,
+    TransactionTrait,
+};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use ulid::Ulid;
+use validator::ValidationError;
+
+use crate::{constants::RE_TAG_NAME, error::MixiniError};
+
+/// The first key of the advisory locks taken on tag names, the second being a hash of the name.
+const TAG_NAME_LOCK: i32 = 1;
+
+/// The most tags a single post may be tagged with directly, before implications.
+pub const MAX_TAGS_PER_POST: usize = 256;
+
+/// Normalize a tag name as typed by a user: trimmed, lowercase, with spaces as underscores.
+pub fn normalize_tag_name(name: &str) -> String {
+    name.trim().to_lowercase().replace(' ', "_")
+}
+
+/// Split whitespace-separated tag names as submitted in forms, normalized and deduplicated.
+pub fn parse_tag_names(names: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    names
+        .split_whitespace()
+        .map(normalize_tag_name)
+        .filter(|name| seen.insert(name.to_owned()))
+        .collect()
+}
+
+/// Validate a single tag name or alias.
+pub fn validate_tag_name(name: &str) -> Result<(), ValidationError> {
+    if name.len() <= 64 && RE_TAG_NAME.is_match(&normalize_tag_name(name)) {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("tag");
+        error.message = Some(
+            format!(
+                "`{}` must be at most 64 characters of letters, numbers, and _ - . ' ! ( ), and can't start with - or (",
+                name
+            )
+            .into(),
+        );
+        Err(error)
+    }
+}
+
+/// Validate whitespace-separated tag names as submitted in forms.
+pub fn validate_tag_names(names: &str) -> Result<(), ValidationError> {
+    let names = parse_tag_names(names);
+    if names.len() > MAX_TAGS_PER_POST {
+        let mut error = ValidationError::new("tags");
+        error.message = Some(format!("At most {} tags are allowed", MAX_TAGS_PER_POST).into());
+        return Err(error);
+    }
+    names.iter().try_for_each(|name| validate_tag_name(name))
+}
+
+/// Resolve tag names to their canonical tags, following aliases.
+///
+/// The result is keyed by the name as given; names that are neither a tag nor an alias are left out.
+pub async fn resolve_tags<C: ConnectionTrait>(
+    db: &C,
+    names: &[String],
+) -> Result<HashMap<String, tag::Model>, DbErr> {
+    let mut resolved: HashMap<String, tag::Model> = Tag::find()
+        .filter(tag::Column::Name.is_in(names.iter().cloned()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|tag| (tag.name.to_owned(), tag))
+        .collect();
+
+    let unresolved: Vec<String> = names
+        .iter()
+        .filter(|name| !resolved.contains_key(*name))
+        .cloned()
+        .collect();
+    if !unresolved.is_empty() {
+        let aliased = TagAlias::find()
+            .filter(tag_alias::Column::Name.is_in(unresolved))
+            .find_also_related(Tag)
+            .all(db)
+            .await?;
+        for (alias, tag) in aliased {
+            if let Some(tag) = tag {
+                resolved.insert(alias.name, tag);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Every tag implied by `tag_ids`, transitively, including `tag_ids` themselves.
+pub async fn implied_closure<C: ConnectionTrait>(
+    db: &C,
+    tag_ids: impl IntoIterator<Item = Uuid>,
+) -> Result<HashSet<Uuid>, DbErr> {
+    let mut closure: HashSet<Uuid> = tag_ids.into_iter().collect();
+    let mut frontier: Vec<Uuid> = closure.iter().copied().collect();
+
+    // tags already in the closure are never revisited, so this terminates even if cycles exist
+    while !frontier.is_empty() {
+        frontier = TagImplication::find()
+            .filter(tag_implication::Column::TagId.is_in(frontier))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|implication| implication.implied_tag_id)
+            .filter(|id| closure.insert(*id))
+            .collect();
+    }
+
+    Ok(closure)
+}
+
+/// Keep `names` from being taken by a tag or alias in any other transaction until `txn` ends.
+///
+/// Tags and aliases share names but live in separate tables, so no constraint keeps them apart.
+/// Anything taking a name locks it first and then checks it's free.
+pub async fn lock_tag_names(txn: &DatabaseTransaction, names: &[String]) -> Result<(), DbErr> {
+    // always locked in the same order, so two transactions can't each wait on the other
+    let names: BTreeSet<&String> = names.iter().collect();
+    for name in names {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pg_advisory_xact_lock($1, hashtext($2))",
+            vec![TAG_NAME_LOCK.into(), name.into()],
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+/// Whether `name` is already taken by a tag or an alias.
+pub async fn is_name_taken<C: ConnectionTrait>(db: &C, name: &str) -> Result<bool, DbErr> {
+    Ok(!resolve_tags(db, &[name.to_owned()]).await?.is_empty())
+}
+
+/// Alias `alias` to the tag `tag_id`.
+pub async fn add_alias(
+    db: &DatabaseConnection,
+    alias: String,
+    tag_id: Uuid,
+) -> Result<tag_alias::Model, MixiniError> {
+    let txn = db.begin().await?;
+    lock_tag_names(&txn, &[alias.to_owned()]).await?;
+    if is_name_taken(&txn, &alias).await? {
+        return Err(MixiniError::Conflict(format!(
+            "`{}` is already a tag or alias",
+            alias
+        )));
+    }
+
+    let alias = tag_alias::ActiveModel {
+        name: Set(alias),
+        tag_id: Set(tag_id),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(alias)
+}
+
+/// Make `tag_id` imply `implied_tag_id`, rejecting implications that would form a cycle.
+///
+/// Posts already tagged with `tag_id` are not retagged; implications apply the next time a post's
+/// tags are set.
+pub async fn add_implication(
+    db: &DatabaseConnection,
+    tag_id: Uuid,
+    implied_tag_id: Uuid,
+) -> Result<tag_implication::Model, MixiniError> {
+    if tag_id == implied_tag_id {
+        return Err(MixiniError::Conflict("A tag can't imply itself".to_owned()));
+    }
+
+    let txn = db.begin().await?;
+    // serialize implication changes so two concurrent ones can't form a cycle between them
+    txn.execute(Statement::from_string(
+        DbBackend::Postgres,
+        "LOCK TABLE tag_implication IN SHARE ROW EXCLUSIVE MODE".to_owned(),
+    ))
+    .await?;
+
+    if implied_closure(&txn, [implied_tag_id])
+        .await?
+        .contains(&tag_id)
+    {
+        return Err(MixiniError::Conflict(
+            "This implication would create a cycle".to_owned(),
+        ));
+    }
+
+    let implication = tag_implication::ActiveModel {
+        tag_id: Set(tag_id),
+        implied_tag_id: Set(implied_tag_id),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(implication)
+}
+
+/// Replace the tags of `post_id` with `names` and everything they imply.
+///
+/// Aliases are resolved to their canonical tags and names that don't exist yet are created as
+/// general tags. Returns the post's tags afterwards.
+pub async fn set_post_tags(
+    db: &DatabaseConnection,
+    post_id: Uuid,
+    names: &[String],
+) -> Result<Vec<tag::Model>, MixiniError> {
+    let txn = db.begin().await?;
+    let tags = set_post_tags_in(&txn, post_id, names).await?;
+    txn.commit().await?;
+
+    Ok(tags)
+}
+
+/// Like `set_post_tags`, as part of `txn`.
+pub async fn set_post_tags_in(
+    txn: &DatabaseTransaction,
+    post_id: Uuid,
+    names: &[String],
+) -> Result<Vec<tag::Model>, MixiniError> {
+    let mut resolved = resolve_tags(txn, names).await?;
+    let missing: Vec<String> = names
+        .iter()
+        .filter(|name| !resolved.contains_key(*name))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        // another edit may have created the same tags since they were resolved
+        lock_tag_names(txn, &missing).await?;
+        resolved.extend(resolve_tags(txn, &missing).await?);
+    }
+    for name in missing {
+        if !resolved.contains_key(&name) {
+            let tag = tag::ActiveModel {
+                id: Set(Uuid::from(Ulid::new())),
+                name: Set(name.to_owned()),
+                ..Default::default()
+            }
+            .insert(txn)
+            .await?;
+            resolved.insert(name, tag);
+        }
+    }
+
+    let wanted = implied_closure(txn, resolved.values().map(|tag| tag.id)).await?;
+    let existing: HashSet<Uuid> = PostTag::find()
+        .filter(post_tag::Column::PostId.eq(post_id))
+        .all(txn)
+        .await?
+        .into_iter()
+        .map(|post_tag| post_tag.tag_id)
+        .collect();
+
+    let removed: Vec<Uuid> = existing.difference(&wanted).copied().collect();
+    if !removed.is_empty() {
+        PostTag::delete_many()
+            .filter(post_tag::Column::PostId.eq(post_id))
+            .filter(post_tag::Column::TagId.is_in(removed))
+            .exec(txn)
+            .await?;
+    }
+
+    let added: Vec<post_tag::ActiveModel> = wanted
+        .difference(&existing)
+        .map(|tag_id| post_tag::ActiveModel {
+            post_id: Set(post_id),
+            tag_id: Set(*tag_id),
+        })
+        .collect();
+    if !added.is_empty() {
+        PostTag::insert_many(added).exec(txn).await?;
+    }
+
+    let tags = Tag::find()
+        .filter(tag::Column::Id.is_in(wanted))
+        .order_by_asc(tag::Column::Name)
+        .all(txn)
+        .await?;
+
+    Ok(tags)
+}
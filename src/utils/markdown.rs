@@ -0,0 +1,29 @@
+//! Rendering user-written markdown to HTML that's safe to show to others.
+
+use ammonia::Builder;
+use lazy_static::lazy_static;
+use pulldown_cmark::{html, Options, Parser};
+
+lazy_static! {
+    static ref SANITIZER: Builder<'static> = {
+        let mut builder = Builder::default();
+        builder
+            // images would load from anywhere as soon as a comment is shown
+            .rm_tags(&["img"])
+            .link_rel(Some("noopener noreferrer nofollow ugc"))
+            .url_schemes(["http", "https", "mailto"].into_iter().collect());
+        builder
+    };
+}
+
+/// Render `markdown` to HTML, stripped of scripts, styles, images and anything else that could
+/// run or load on the page it's shown on.
+pub fn render_markdown(markdown: &str) -> String {
+    let parser = Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES,
+    );
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, parser);
+    SANITIZER.clean(&unsafe_html).to_string()
+}
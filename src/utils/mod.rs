@@ -3,6 +3,7 @@ use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
 pub mod mail;
+pub mod markdown;
 pub mod pass;
 
 const KEY_LENGTH: usize = 32;
@@ -0,0 +1,29 @@
+//! Tests for checking the posts submitted when reordering a collection.
+
+use mixini_server::collections::{check_order, parse_post_ids, validate_post_ids};
+use uuid::Uuid;
+
+#[test]
+fn orders_must_list_every_post_once() {
+    let posts: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+    let [a, b, c] = [posts[0], posts[1], posts[2]];
+
+    assert!(check_order(&posts, &[c, a, b]).is_ok());
+    assert!(check_order(&[], &[]).is_ok());
+    // missing, repeated and foreign posts
+    assert!(check_order(&posts, &[c, a]).is_err());
+    assert!(check_order(&posts, &[c, a, a]).is_err());
+    assert!(check_order(&posts, &[c, a, b, Uuid::new_v4()]).is_err());
+    assert!(check_order(&posts, &[c, a, Uuid::new_v4()]).is_err());
+}
+
+#[test]
+fn parses_whitespace_separated_post_ids() {
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+    assert_eq!(
+        parse_post_ids(&format!(" {}\n{} ", a, b)).unwrap(),
+        vec![a, b]
+    );
+    assert!(validate_post_ids(&format!("{} not-an-id", a)).is_err());
+    assert!(validate_post_ids("").is_ok());
+}
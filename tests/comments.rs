@@ -0,0 +1,85 @@
+//! Tests for nesting comments into threads and rendering their bodies.
+
+use chrono::{Duration, Utc};
+use mixini_server::{
+    comments::{build_threads, CommentResponse, CommentThread},
+    utils::markdown::render_markdown,
+};
+use uuid::Uuid;
+
+fn comment(post_id: Uuid, parent_id: Option<Uuid>, minutes_ago: i64) -> CommentResponse {
+    CommentResponse {
+        id: Uuid::new_v4(),
+        created_at: (Utc::now() - Duration::minutes(minutes_ago)).into(),
+        post_id,
+        parent_id,
+        author_id: Some(Uuid::new_v4()),
+        body: Some("a comment".to_owned()),
+        body_html: Some("<p>a comment</p>\n".to_owned()),
+        edited_at: None,
+        deleted: false,
+        hidden: false,
+    }
+}
+
+fn ids(threads: &[CommentThread]) -> Vec<Uuid> {
+    threads.iter().map(|thread| thread.comment.id).collect()
+}
+
+#[test]
+fn nests_replies_under_their_parents_in_order() {
+    let post_id = Uuid::new_v4();
+    let first = comment(post_id, None, 10);
+    let second = comment(post_id, None, 9);
+    let reply = comment(post_id, Some(first.id), 8);
+    let later_reply = comment(post_id, Some(first.id), 7);
+    let nested = comment(post_id, Some(reply.id), 6);
+    // a reply whose parent isn't on this page
+    let orphan = comment(post_id, Some(Uuid::new_v4()), 5);
+
+    let threads = build_threads(vec![
+        first.to_owned(),
+        second.to_owned(),
+        reply.to_owned(),
+        later_reply.to_owned(),
+        nested.to_owned(),
+        orphan.to_owned(),
+    ]);
+
+    assert_eq!(ids(&threads), vec![first.id, second.id, orphan.id]);
+    assert_eq!(ids(&threads[0].replies), vec![reply.id, later_reply.id]);
+    assert_eq!(ids(&threads[0].replies[0].replies), vec![nested.id]);
+    assert!(threads[1].replies.is_empty());
+}
+
+#[test]
+fn redacts_unreadable_comments() {
+    let post_id = Uuid::new_v4();
+    let shown = comment(post_id, None, 0);
+    let value = serde_json::to_value(build_threads(vec![CommentResponse {
+        author_id: None,
+        body: None,
+        body_html: None,
+        deleted: true,
+        ..shown
+    }]))
+    .unwrap();
+
+    assert_eq!(value[0]["body"], serde_json::Value::Null);
+    assert_eq!(value[0]["deleted"], true);
+    assert_eq!(value[0]["replies"], serde_json::json!([]));
+}
+
+#[test]
+fn renders_markdown_without_active_content() {
+    let html = render_markdown(
+        "**bold** <script>alert(1)</script>\n\n[link](https://mixini.test) \
+         [bad](javascript:alert(1)) ![image](https://mixini.test/a.png)",
+    );
+
+    assert!(html.contains("<strong>bold</strong>"));
+    assert!(!html.contains("<script"));
+    assert!(!html.contains("javascript:"));
+    assert!(!html.contains("<img"));
+    assert!(html.contains("rel=\"noopener noreferrer nofollow ugc\""));
+}
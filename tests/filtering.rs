@@ -0,0 +1,235 @@
+//! Tests for compiling `allow_filter` rules and content preferences into SQL.
+
+use chrono::Utc;
+use entity::{
+    prelude::*,
+    sea_orm_active_enums::{PostRating, UserRole},
+    user_account, user_preference,
+};
+use mixini_server::{
+    actions::{try_register_oso, Read},
+    auth::{Auth, Guest},
+    constants::MAX_SEARCH_NESTING,
+    filtering::authorized_condition,
+    preferences::{parse_blacklist, ContentPreferences},
+    search::{Expr, Term},
+};
+use sea_orm::{DbBackend, EntityTrait, QueryFilter, QueryTrait};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+fn user(role: UserRole) -> user_account::Model {
+    let now = Utc::now().into();
+    user_account::Model {
+        id: Uuid::new_v4(),
+        created_at: now,
+        updated_at: now,
+        name: "someone".to_owned(),
+        email: "someone@mixini.test".to_owned(),
+        role,
+        password: String::new(),
+        verified: true,
+    }
+}
+
+async fn list_users_sql(auth: Auth) -> String {
+    let oso = Mutex::new(try_register_oso(&Default::default()).expect("policy failed to load"));
+    let condition = authorized_condition::<UserAccount, _>(&oso, &auth, Read)
+        .await
+        .expect("failed to build condition");
+    UserAccount::find()
+        .filter(condition)
+        .build(DbBackend::Postgres)
+        .to_string()
+}
+
+#[tokio::test]
+async fn admins_list_everyone() {
+    let sql = list_users_sql(Auth::KnownUser(user(UserRole::Admin))).await;
+    assert!(!sql.contains("WHERE"), "unexpected filter in: {}", sql);
+}
+
+#[tokio::test]
+async fn moderators_list_roles_below_them() {
+    let sql = list_users_sql(Auth::KnownUser(user(UserRole::Moderator))).await;
+    assert!(
+        sql.contains(r#""role" IN"#),
+        "missing role filter in: {}",
+        sql
+    );
+    assert!(
+        sql.contains("'maintainer'"),
+        "missing lower role in: {}",
+        sql
+    );
+    assert!(!sql.contains("'admin'"), "higher role leaked into: {}", sql);
+}
+
+#[tokio::test]
+async fn members_list_themselves() {
+    let member = user(UserRole::Member);
+    let id = member.id;
+    let sql = list_users_sql(Auth::KnownUser(member)).await;
+    assert!(
+        sql.contains(&id.to_string()),
+        "missing id filter in: {}",
+        sql
+    );
+}
+
+#[tokio::test]
+async fn guests_list_nobody() {
+    let sql = list_users_sql(Auth::UnknownUser(Guest::default())).await;
+    assert!(sql.contains("FALSE"), "guest not denied in: {}", sql);
+}
+
+#[tokio::test]
+async fn members_list_public_own_and_shared_collections() {
+    let oso = Mutex::new(try_register_oso(&Default::default()).expect("policy failed to load"));
+    let member = user(UserRole::Member);
+    let id = member.id;
+    let condition = authorized_condition::<Collection, _>(&oso, &Auth::KnownUser(member), Read)
+        .await
+        .expect("failed to build condition");
+    let sql = Collection::find()
+        .filter(condition)
+        .build(DbBackend::Postgres)
+        .to_string();
+
+    assert!(
+        sql.contains(r#""is_public" = TRUE"#),
+        "missing visibility filter in: {}",
+        sql
+    );
+    assert!(
+        sql.contains(&format!(r#""owner_id" = '{}'"#, id)),
+        "missing owner filter in: {}",
+        sql
+    );
+    assert!(
+        sql.contains(r#"FROM "collection_collaborator""#),
+        "missing collaborator filter in: {}",
+        sql
+    );
+}
+
+fn list_posts_sql(preferences: &ContentPreferences) -> String {
+    Post::find()
+        .filter(preferences.rating_condition())
+        .build(DbBackend::Postgres)
+        .to_string()
+}
+
+#[test]
+fn guests_are_shown_safe_posts() {
+    let sql = list_posts_sql(&ContentPreferences::guest());
+    assert!(
+        sql.contains(r#""rating" IN ('safe')"#),
+        "missing rating filter in: {}",
+        sql
+    );
+}
+
+#[test]
+fn users_are_shown_the_ratings_they_chose() {
+    let preference = user_preference::Model {
+        user_id: Uuid::new_v4(),
+        updated_at: Utc::now().into(),
+        show_safe: false,
+        show_questionable: true,
+        show_explicit: true,
+        tag_blacklist: String::new(),
+        mark_blacklisted: false,
+        public_favorites: false,
+    };
+    let preferences = ContentPreferences::from(&preference);
+    assert_eq!(
+        preferences.ratings,
+        vec![PostRating::Questionable, PostRating::Explicit]
+    );
+    let sql = list_posts_sql(&preferences);
+    assert!(
+        sql.contains(r#""rating" IN ('questionable', 'explicit')"#),
+        "missing rating filter in: {}",
+        sql
+    );
+
+    let nothing = ContentPreferences {
+        ratings: vec![],
+        ..ContentPreferences::guest()
+    };
+    let sql = list_posts_sql(&nothing);
+    assert!(
+        sql.contains("FALSE"),
+        "empty preferences not denied in: {}",
+        sql
+    );
+}
+
+#[test]
+fn parses_blacklists_by_line() {
+    let gore = Expr::All(vec![
+        Expr::Term(Term::Tag("gore".to_owned())),
+        Expr::Not(Box::new(Expr::Term(Term::Rating(PostRating::Safe)))),
+    ]);
+    // blank lines would match every post, so they're skipped
+    assert_eq!(
+        parse_blacklist("gore -rating:safe\n\n  \nspiders\n").unwrap(),
+        vec![
+            gore,
+            Expr::All(vec![Expr::Term(Term::Tag("spiders".to_owned()))])
+        ]
+    );
+
+    // positions count from the start of the blacklist
+    let error = parse_blacklist("spiders\ncat (dog").unwrap_err();
+    assert_eq!(error.position, 12);
+    let error = parse_blacklist("spiders\ncat order:score").unwrap_err();
+    assert_eq!(error.position, 8);
+}
+
+#[test]
+fn rejects_deeply_nested_blacklist_entries() {
+    // as deep as the longest blacklist allows, which would overflow the stack without a limit
+    let blacklist = format!("spiders\ncat {}dog", "(".repeat(9_980));
+
+    let error = parse_blacklist(&blacklist).unwrap_err();
+    assert_eq!(
+        error.message,
+        format!(
+            "Groups and negations can be nested at most {} deep",
+            MAX_SEARCH_NESTING
+        )
+    );
+    // the first group past the limit, counting from the start of the blacklist
+    assert_eq!(error.position, 12 + MAX_SEARCH_NESTING);
+
+    let preference = user_preference::Model {
+        user_id: Uuid::new_v4(),
+        updated_at: Utc::now().into(),
+        show_safe: true,
+        show_questionable: true,
+        show_explicit: true,
+        tag_blacklist: blacklist,
+        mark_blacklisted: false,
+        public_favorites: false,
+    };
+    assert_eq!(ContentPreferences::from(&preference).blacklist.len(), 1);
+}
+
+#[test]
+fn loads_blacklists_skipping_broken_entries() {
+    let preference = user_preference::Model {
+        user_id: Uuid::new_v4(),
+        updated_at: Utc::now().into(),
+        show_safe: true,
+        show_questionable: true,
+        show_explicit: true,
+        tag_blacklist: "spiders\ncat (dog\n\nrating:explicit".to_owned(),
+        mark_blacklisted: true,
+        public_favorites: false,
+    };
+    let preferences = ContentPreferences::from(&preference);
+    assert_eq!(preferences.blacklist.len(), 2);
+    assert!(preferences.mark_blacklisted);
+}
@@ -0,0 +1,53 @@
+//! Tests for finding the address guests make requests from.
+
+use mixini_server::auth::client_ip;
+use std::net::IpAddr;
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+#[test]
+fn forwarded_for_is_only_believed_from_trusted_proxies() {
+    let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+    let cases = [
+        // no proxy in front
+        (Some("203.0.113.7"), None, Some("203.0.113.7")),
+        // an untrusted peer can't pick its own address
+        (
+            Some("203.0.113.7"),
+            Some("198.51.100.1"),
+            Some("203.0.113.7"),
+        ),
+        (Some("10.0.0.1"), Some("198.51.100.1"), Some("198.51.100.1")),
+        // entries left of the first untrusted hop were written by the client
+        (
+            Some("10.0.0.1"),
+            Some("1.2.3.4, 198.51.100.1, 10.0.0.2"),
+            Some("198.51.100.1"),
+        ),
+        (Some("10.0.0.1"), Some("10.0.0.2"), Some("10.0.0.2")),
+        (
+            Some("10.0.0.1"),
+            Some("garbage, 198.51.100.1"),
+            Some("198.51.100.1"),
+        ),
+        (
+            Some("10.0.0.1"),
+            Some("198.51.100.1, garbage"),
+            Some("10.0.0.1"),
+        ),
+        (Some("::1"), Some("198.51.100.1"), Some("::1")),
+        (None, Some("198.51.100.1"), None),
+    ];
+
+    for (peer, forwarded_for, expected) in cases {
+        assert_eq!(
+            client_ip(peer.map(ip), forwarded_for, &proxies),
+            expected.map(ip),
+            "peer {:?} forwarded for {:?}",
+            peer,
+            forwarded_for
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Tests for source importers, against a local stand-in for external sites.
+
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use entity::sea_orm_active_enums::PostRating;
+use mixini_server::importers::{is_private_ip, ImportError, ImporterRegistry};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+
+fn html(body: &'static str) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/html")], body)
+}
+
+fn redirect(to: String) -> impl IntoResponse {
+    (StatusCode::FOUND, [(header::LOCATION, to)])
+}
+
+/// Serve the fixture pages on an unused local port.
+fn serve_fixtures() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind fixture server");
+    let addr = listener.local_addr().unwrap();
+
+    let app = Router::new()
+        .route(
+            "/art/sunset",
+            get(|| async { html(include_str!("fixtures/importers/opengraph.html")) }),
+        )
+        .route(
+            "/art/night",
+            get(|| async { html(include_str!("fixtures/importers/oembed.html")) }),
+        )
+        .route(
+            "/oembed.json",
+            get(|| async {
+                (
+                    [(header::CONTENT_TYPE, "application/json")],
+                    include_str!("fixtures/importers/oembed.json"),
+                )
+            }),
+        )
+        .route(
+            "/redirect/sunset",
+            get(move || async move { redirect(format!("http://{}/art/sunset", addr)) }),
+        )
+        .route(
+            "/redirect/metadata",
+            get(|| async { redirect("http://169.254.169.254/latest/meta-data/".to_owned()) }),
+        )
+        .route(
+            "/redirect/mapped",
+            get(move || async move {
+                redirect(format!(
+                    "http://[::ffff:127.0.0.1]:{}/art/sunset",
+                    addr.port()
+                ))
+            }),
+        );
+
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .expect("failed to start fixture server")
+            .serve(app.into_make_service()),
+    );
+    addr
+}
+
+fn registry() -> ImporterRegistry {
+    let mut registry = ImporterRegistry::try_default().expect("failed to build registry");
+    registry
+        .allow_hosts([IpAddr::V4(Ipv4Addr::LOCALHOST)])
+        .expect("failed to build registry");
+    registry
+}
+
+#[tokio::test]
+async fn imports_opengraph_metadata() {
+    let addr = serve_fixtures();
+    let imported = registry()
+        .import(&format!("http://{}/art/sunset", addr))
+        .await
+        .expect("import failed");
+
+    assert_eq!(imported.title, "Sunset over the harbour");
+    assert_eq!(
+        imported.description,
+        "Painted on location & finished at home."
+    );
+    assert_eq!(
+        imported.source_urls,
+        vec![format!("http://{}/art/sunset", addr)]
+    );
+    assert_eq!(
+        imported.media_url,
+        Some(format!("http://{}/media/sunset.png", addr))
+    );
+    assert_eq!(imported.rating, PostRating::Safe);
+    assert_eq!(
+        imported.tags,
+        vec!["some_artist", "landscape", "sunset", "harbour"]
+    );
+}
+
+#[tokio::test]
+async fn prefers_oembed_when_linked() {
+    let addr = serve_fixtures();
+    let imported = registry()
+        .import(&format!("http://{}/art/night", addr))
+        .await
+        .expect("import failed");
+
+    assert_eq!(imported.title, "Night market");
+    assert_eq!(
+        imported.media_url,
+        Some(format!("http://{}/media/night.jpg", addr))
+    );
+    assert_eq!(imported.rating, PostRating::Explicit);
+    assert_eq!(imported.tags, vec!["another_artist"]);
+}
+
+#[tokio::test]
+async fn rejects_bad_urls() {
+    let addr = serve_fixtures();
+    let registry = registry();
+
+    assert!(matches!(
+        registry.import(&format!("http://{}/missing", addr)).await,
+        Err(ImportError::BadStatus(_))
+    ));
+    assert!(matches!(
+        registry.import("ftp://example.com/art").await,
+        Err(ImportError::UnsupportedUrl)
+    ));
+    assert!(matches!(
+        ImporterRegistry::try_default()
+            .unwrap()
+            .import(&format!("http://{}/art/sunset", addr))
+            .await,
+        Err(ImportError::ForbiddenHost)
+    ));
+}
+
+#[tokio::test]
+async fn rechecks_every_redirect() {
+    let addr = serve_fixtures();
+    let registry = registry();
+
+    let imported = registry
+        .import(&format!("http://{}/redirect/sunset", addr))
+        .await
+        .expect("import failed");
+    assert_eq!(imported.title, "Sunset over the harbour");
+
+    for path in ["/redirect/metadata", "/redirect/mapped"] {
+        assert!(
+            matches!(
+                registry.import(&format!("http://{}{}", addr, path)).await,
+                Err(ImportError::ForbiddenHost)
+            ),
+            "followed {}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn rejects_private_addresses() {
+    let addr = serve_fixtures();
+    let registry = ImporterRegistry::try_default().unwrap();
+
+    for url in [
+        format!("http://localhost:{}/art/sunset", addr.port()),
+        format!("http://[::ffff:127.0.0.1]:{}/art/sunset", addr.port()),
+        "http://[::ffff:10.0.0.1]/art".to_owned(),
+        "http://[fd00::1]/art".to_owned(),
+        "http://[fe80::1]/art".to_owned(),
+        "http://169.254.169.254/latest/meta-data/".to_owned(),
+    ] {
+        assert!(
+            matches!(registry.import(&url).await, Err(ImportError::ForbiddenHost)),
+            "imported from {}",
+            url
+        );
+    }
+}
+
+#[test]
+fn private_ips() {
+    let cases = [
+        ("127.0.0.1", true),
+        ("10.1.2.3", true),
+        ("172.16.0.1", true),
+        ("192.168.1.1", true),
+        ("169.254.169.254", true),
+        ("100.64.0.1", true),
+        ("0.0.0.0", true),
+        ("::1", true),
+        ("::", true),
+        ("fc00::1", true),
+        ("fdff:ffff::1", true),
+        ("fe80::1", true),
+        ("febf::1", true),
+        ("::ffff:10.0.0.1", true),
+        ("::ffff:127.0.0.1", true),
+        ("::ffff:169.254.169.254", true),
+        ("93.184.216.34", false),
+        ("100.128.0.1", false),
+        ("2606:2800:220:1::", false),
+        ("fec0::1", false),
+        ("::ffff:93.184.216.34", false),
+    ];
+
+    for (ip, private) in cases {
+        assert_eq!(
+            is_private_ip(ip.parse().unwrap()),
+            private,
+            "wrong answer for {}",
+            ip
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Tests for sniffing the format of uploaded media, generating previews of it, and serving it.
+
+use entity::sea_orm_active_enums::MediaFormat;
+use image::DynamicImage;
+use mixini_server::media::{
+    encode_preview, etag_matches, generate_previews, media_etag, parse_range, preview_key,
+    scale_to_fit, sniff_format, ByteRange, LocalStore, MediaStore, PreviewFormat, PreviewSizes,
+    SNIFF_LEN,
+};
+use ulid::Ulid;
+
+#[test]
+fn sniffs_formats_from_magic_bytes() {
+    let cases: [(&[u8], Option<MediaFormat>); 7] = [
+        (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", Some(MediaFormat::Png)),
+        (b"\xff\xd8\xff\xe0\0\x10JFIF\0\x01", Some(MediaFormat::Jpeg)),
+        (b"GIF89a\x01\0\x01\0\x80\0", Some(MediaFormat::Gif)),
+        (b"GIF87a\x01\0\x01\0\x80\0", Some(MediaFormat::Gif)),
+        (b"RIFF\x24\0\0\0WEBPVP8 ", Some(MediaFormat::Webp)),
+        // a WAV file is RIFF too, but not an image
+        (b"RIFF\x24\0\0\0WAVEfmt ", None),
+        (b"<svg xmlns=\"http", None),
+    ];
+
+    for (bytes, expected) in cases {
+        assert!(bytes.len() >= SNIFF_LEN);
+        assert_eq!(
+            sniff_format(&bytes[..SNIFF_LEN]),
+            expected,
+            "for {:?}",
+            bytes
+        );
+    }
+}
+
+#[test]
+fn short_files_are_not_images() {
+    assert_eq!(sniff_format(b""), None);
+    assert_eq!(sniff_format(b"RIFF"), None);
+}
+
+#[test]
+fn previews_fit_without_upscaling() {
+    let wide = DynamicImage::new_rgba8(1000, 500);
+    let scaled = scale_to_fit(&wide, 150);
+    assert_eq!((scaled.width(), scaled.height()), (150, 75));
+
+    let small = DynamicImage::new_rgba8(100, 40);
+    let scaled = scale_to_fit(&small, 150);
+    assert_eq!((scaled.width(), scaled.height()), (100, 40));
+}
+
+#[test]
+fn previews_are_encoded_in_their_format() {
+    let image = DynamicImage::new_rgba8(20, 10);
+    assert_eq!(
+        sniff_format(&encode_preview(&image, PreviewFormat::Webp).unwrap()),
+        Some(MediaFormat::Webp)
+    );
+    assert_eq!(
+        sniff_format(&encode_preview(&image, PreviewFormat::Jpeg).unwrap()),
+        Some(MediaFormat::Jpeg)
+    );
+}
+
+#[tokio::test]
+async fn generates_every_preview() {
+    let dir = std::env::temp_dir().join(format!("mixini-test-{}", Ulid::new()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("source.png");
+    DynamicImage::new_rgb8(1000, 2000).save(&source).unwrap();
+
+    let store = LocalStore::new(dir.join("media"));
+    let sizes = PreviewSizes {
+        thumbnail: 150,
+        sample: 850,
+    };
+    generate_previews(
+        &store,
+        &dir.join("tmp"),
+        &source,
+        "ab12cd34",
+        &MediaFormat::Png,
+        sizes,
+    )
+    .await
+    .unwrap();
+
+    for size in [150, 850] {
+        for format in PreviewFormat::ALL {
+            let key = preview_key("ab12cd34", size, format);
+            assert_eq!(key, format!("ab12cd34_{}.{}", size, format.extension()));
+            assert!(store.size(&key).await.unwrap().is_some(), "for {}", key);
+        }
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn validates_preview_sizes() {
+    let sizes = |thumbnail, sample| PreviewSizes { thumbnail, sample };
+
+    assert!(PreviewSizes::default().validate().is_ok());
+    assert!(sizes(150, 150).validate().is_ok());
+    assert!(sizes(0, 850).validate().is_err());
+    assert!(sizes(150, 0).validate().is_err());
+    assert!(sizes(900, 850).validate().is_err());
+}
+
+#[test]
+fn parses_ranges() {
+    let cases = [
+        ("bytes=0-99", ByteRange::Partial(0..100)),
+        ("bytes=100-", ByteRange::Partial(100..1000)),
+        ("bytes=-100", ByteRange::Partial(900..1000)),
+        ("bytes=-5000", ByteRange::Partial(0..1000)),
+        ("bytes=900-5000", ByteRange::Partial(900..1000)),
+        ("bytes=1000-", ByteRange::Unsatisfiable),
+        ("bytes=-0", ByteRange::Unsatisfiable),
+        // unsupported or invalid headers are ignored
+        ("bytes=0-1,5-9", ByteRange::Full),
+        ("bytes=9-5", ByteRange::Full),
+        ("items=0-5", ByteRange::Full),
+        ("bytes=abc", ByteRange::Full),
+    ];
+
+    for (header, expected) in cases {
+        assert_eq!(parse_range(header, 1000), expected, "for {:?}", header);
+    }
+}
+
+#[test]
+fn matches_etags() {
+    let etag = media_etag("ab12cd34");
+    assert_eq!(etag, "\"ab12cd34\"");
+    assert!(etag_matches("\"ab12cd34\"", &etag));
+    assert!(etag_matches("\"other\", W/\"ab12cd34\"", &etag));
+    assert!(etag_matches("*", &etag));
+    assert!(!etag_matches("\"other\"", &etag));
+}
@@ -0,0 +1,277 @@
+//! Tests for reading and stripping the metadata embedded in uploaded media.
+
+use chrono::NaiveDate;
+use entity::sea_orm_active_enums::MediaFormat;
+use exif::{In, Tag};
+use image::{
+    codecs::{
+        jpeg::JpegEncoder,
+        webp::{WebPEncoder, WebPQuality},
+    },
+    ColorType, DynamicImage, ImageOutputFormat, RgbImage,
+};
+use mixini_server::media::{strip_metadata, EmbeddedMetadata};
+use std::io::Cursor;
+
+const ORIENTATION: u16 = 0x0112;
+const IMAGE_DESCRIPTION: u16 = 0x010E;
+
+/// A big-endian TIFF structure with an orientation and some text fields.
+fn tiff(orientation: u16, text: &[(u16, &str)]) -> Vec<u8> {
+    let mut entries: Vec<(u16, u16, Vec<u8>)> = text
+        .iter()
+        .map(|(tag, value)| (*tag, 2, format!("{}\0", value).into_bytes()))
+        .collect();
+    entries.push((ORIENTATION, 3, orientation.to_be_bytes().to_vec()));
+    entries.sort_by_key(|(tag, _, _)| *tag);
+
+    let mut data_at = 8 + 2 + entries.len() * 12 + 4;
+    let (mut ifd, mut data) = (Vec::new(), Vec::new());
+    ifd.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+    for (tag, kind, value) in entries {
+        let count = if kind == 3 { 1 } else { value.len() as u32 };
+        ifd.extend_from_slice(&tag.to_be_bytes());
+        ifd.extend_from_slice(&kind.to_be_bytes());
+        ifd.extend_from_slice(&count.to_be_bytes());
+        if value.len() <= 4 {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            ifd.extend_from_slice(&inline);
+        } else {
+            ifd.extend_from_slice(&(data_at as u32).to_be_bytes());
+            data_at += value.len();
+            data.extend_from_slice(&value);
+        }
+    }
+    ifd.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut tiff = b"MM\0\x2A\0\0\0\x08".to_vec();
+    tiff.extend(ifd);
+    tiff.extend(data);
+    tiff
+}
+
+fn xmp(rights: &str) -> String {
+    format!(
+        r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description xmp:CreatorTool="Krita 5.0"><dc:rights><rdf:Alt><rdf:li xml:lang="x-default">{}</rdf:li></rdf:Alt></dc:rights></rdf:Description></rdf:RDF></x:xmpmeta>"#,
+        rights
+    )
+}
+
+fn image() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(16, 8, |x, y| {
+        image::Rgb([x as u8 * 16, y as u8 * 32, 128])
+    }))
+}
+
+fn jpeg_segment(marker: u8, data: &[u8]) -> Vec<u8> {
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+    segment.extend_from_slice(data);
+    segment
+}
+
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    chunk.extend_from_slice(&crc.finalize().to_be_bytes());
+    chunk
+}
+
+fn riff_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+fn orientation(bytes: &[u8]) -> Option<u32> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()?;
+    assert!(exif.get_field(Tag::ImageDescription, In::PRIMARY).is_none());
+    exif.get_field(Tag::Orientation, In::PRIMARY)?
+        .value
+        .get_uint(0)
+}
+
+#[test]
+fn strips_jpeg_metadata() {
+    let mut encoded = Vec::new();
+    JpegEncoder::new(&mut encoded)
+        .encode_image(&image())
+        .unwrap();
+    // keep the JFIF header first
+    let app0_end = 4 + u16::from_be_bytes([encoded[4], encoded[5]]) as usize;
+
+    let mut exif = b"Exif\0\0".to_vec();
+    exif.extend(tiff(
+        6,
+        &[
+            (IMAGE_DESCRIPTION, "secret: 51.5007 N, 0.1246 W"),
+            (0x010F, "Canon"),
+            (0x0110, "Canon EOS 5D"),
+            (0x0132, "2022:05:01 12:30:00"),
+            (0x013B, "Jane Doe"),
+        ],
+    ));
+    let mut xmp_data = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+    xmp_data.extend_from_slice(xmp("All rights reserved").as_bytes());
+    let mut jpeg = encoded[..app0_end].to_vec();
+    jpeg.extend(jpeg_segment(0xE1, &exif));
+    jpeg.extend(jpeg_segment(0xE1, &xmp_data));
+    jpeg.extend(jpeg_segment(0xFE, b"secret comment"));
+    jpeg.extend_from_slice(&encoded[app0_end..]);
+    // a second image, as in multi-picture files
+    jpeg.extend_from_slice(&encoded);
+
+    let (stripped, metadata) = strip_metadata(&jpeg, &MediaFormat::Jpeg).unwrap();
+    assert_eq!(
+        metadata,
+        EmbeddedMetadata {
+            camera: Some("Canon EOS 5D".to_owned()),
+            software: Some("Krita 5.0".to_owned()),
+            taken_at: Some(NaiveDate::from_ymd(2022, 5, 1).and_hms(12, 30, 0)),
+            artist: Some("Jane Doe".to_owned()),
+            copyright: Some("All rights reserved".to_owned()),
+        }
+    );
+    assert!(!contains(&stripped, b"secret"));
+    assert!(!contains(&stripped, b"Jane"));
+    assert_eq!(stripped.len(), encoded.len() + 2 + 2 + 6 + 26);
+    assert_eq!(orientation(&stripped), Some(6));
+    let decoded = image::load_from_memory(&stripped).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (16, 8));
+
+    // stripping is idempotent
+    let (again, metadata) = strip_metadata(&stripped, &MediaFormat::Jpeg).unwrap();
+    assert_eq!(again, stripped);
+    assert_eq!(metadata, EmbeddedMetadata::default());
+}
+
+#[test]
+fn strips_png_text_chunks() {
+    let mut encoded = Vec::new();
+    image()
+        .write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::Png)
+        .unwrap();
+    let iend_at = encoded.len() - 12;
+
+    let mut itxt = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+    itxt.extend_from_slice(xmp("CC BY 4.0").as_bytes());
+    let mut png = encoded[..iend_at].to_vec();
+    png.extend(png_chunk(b"tEXt", b"Author\0Jane Doe"));
+    png.extend(png_chunk(b"tEXt", b"Comment\0secret: 51.5007 N, 0.1246 W"));
+    png.extend(png_chunk(
+        b"tEXt",
+        b"Creation Time\0Sun, 01 May 2022 12:30:00 +0100",
+    ));
+    png.extend(png_chunk(b"iTXt", &itxt));
+    png.extend(png_chunk(
+        b"eXIf",
+        &tiff(1, &[(IMAGE_DESCRIPTION, "secret")]),
+    ));
+    png.extend_from_slice(&encoded[iend_at..]);
+    png.extend_from_slice(b"secret trailing data");
+
+    let (stripped, metadata) = strip_metadata(&png, &MediaFormat::Png).unwrap();
+    assert_eq!(
+        metadata,
+        EmbeddedMetadata {
+            camera: None,
+            software: Some("Krita 5.0".to_owned()),
+            taken_at: Some(NaiveDate::from_ymd(2022, 5, 1).and_hms(12, 30, 0)),
+            artist: Some("Jane Doe".to_owned()),
+            copyright: Some("CC BY 4.0".to_owned()),
+        }
+    );
+    // the default orientation isn't written back
+    assert_eq!(stripped, encoded);
+}
+
+#[test]
+fn strips_webp_exif_and_xmp() {
+    let image = image().to_rgb8();
+    let mut encoded = Vec::new();
+    WebPEncoder::new_with_quality(&mut encoded, WebPQuality::lossless())
+        .encode(image.as_raw(), 16, 8, ColorType::Rgb8)
+        .unwrap();
+
+    // an extended file with EXIF and XMP, around the simple file's image data
+    let mut vp8x = vec![0x08 | 0x04, 0, 0, 0];
+    vp8x.extend_from_slice(&15u32.to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&7u32.to_le_bytes()[..3]);
+    let mut body = b"WEBP".to_vec();
+    body.extend(riff_chunk(b"VP8X", &vp8x));
+    body.extend_from_slice(&encoded[12..]);
+    body.extend(riff_chunk(
+        b"EXIF",
+        &tiff(3, &[(IMAGE_DESCRIPTION, "secret: 51.5007 N")]),
+    ));
+    body.extend(riff_chunk(b"XMP ", xmp("secret").as_bytes()));
+    let mut webp = b"RIFF".to_vec();
+    webp.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    webp.extend(body);
+
+    let (stripped, metadata) = strip_metadata(&webp, &MediaFormat::Webp).unwrap();
+    assert_eq!(metadata.copyright.as_deref(), Some("secret"));
+    assert!(!contains(&stripped, b"secret"));
+    assert_eq!(
+        u32::from_le_bytes(stripped[4..8].try_into().unwrap()) as usize,
+        stripped.len() - 8
+    );
+    // only the flag for the rewritten EXIF is left
+    assert_eq!(stripped[20], 0x08);
+    assert_eq!(orientation(&stripped), Some(3));
+    let decoded = image::load_from_memory(&stripped).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (16, 8));
+}
+
+#[test]
+fn strips_gif_comments() {
+    let gif = b"GIF89a\x01\0\x01\0\x80\0\0\xff\xff\xff\0\0\0\
+        \x21\xfe\x0esecret comment\0\
+        \x2c\0\0\0\0\x01\0\x01\0\0\x02\x02\x44\x01\0\
+        \x3bsecret";
+    let (stripped, metadata) = strip_metadata(gif, &MediaFormat::Gif).unwrap();
+    assert_eq!(metadata, EmbeddedMetadata::default());
+    assert!(!contains(&stripped, b"secret"));
+    let decoded = image::load_from_memory(&stripped).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (1, 1));
+}
+
+#[test]
+fn rejects_malformed_containers() {
+    let cases: [(&[u8], MediaFormat); 6] = [
+        // a segment longer than the file
+        (b"\xff\xd8\xff\xe1\xff\xffExif\0\0", MediaFormat::Jpeg),
+        // segments shorter than their own length field
+        (b"\xff\xd8\xff\xe1\0\0\xff\xd9", MediaFormat::Jpeg),
+        (b"\xff\xd8\xff\xe1\0\x01\xff\xd9", MediaFormat::Jpeg),
+        // no end chunk
+        (b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR", MediaFormat::Png),
+        (b"RIFF\x04\0\0\0WAVE", MediaFormat::Webp),
+        // an unknown block
+        (b"GIF89a\x01\0\x01\0\0\0\0\x99", MediaFormat::Gif),
+    ];
+    for (bytes, format) in cases {
+        assert!(
+            strip_metadata(bytes, &format).is_err(),
+            "for {:?}",
+            String::from_utf8_lossy(bytes)
+        );
+    }
+}
@@ -0,0 +1,1202 @@
+//! Table-driven tests for the rules in `polar/users.polar`, `polar/posts.polar`,
+//! `polar/tags.polar`, `polar/admin.polar`, `polar/collections.polar`, `polar/comments.polar` and
+//! `polar/revisions.polar`, and the permission grants they check.
+//!
+//! Every case is evaluated against a single oso instance built with `try_register_oso`, and all
+//! mismatches are reported together so one broken rule shows its full blast radius.
+
+use chrono::{Duration, Utc};
+use entity::{
+    comment, permission_grant, post, post_revision,
+    sea_orm_active_enums::{PostRating, PostStatus, UserRole},
+    user_account,
+};
+use mixini_server::{
+    actions::{
+        try_register_oso, CommentOn, Create, Delete, EditCollectionPosts, EditComment, EditTags,
+        Favorite, FindSimilar, Grant, Hide, ManageCollaborators, Read, Restore, Revert, Revoke,
+        UpdateCollection, UpdatePost, UpdateUser, ViewMedia,
+    },
+    collections::{CollectionResource, Favorites},
+    permissions::Grants,
+};
+use oso::{Oso, ToPolar};
+use std::{collections::HashSet, fmt, sync::Arc};
+use uuid::Uuid;
+use validator::Validate;
+
+use UserRole::*;
+
+/// Who is making the request.
+#[derive(Debug, Clone)]
+enum Actor {
+    /// An unauthenticated visitor.
+    Guest,
+    /// Some other user with the given role.
+    Other(UserRole),
+    /// The target user acting on themselves.
+    Myself,
+}
+
+/// A single-field changeset for `UpdateUser`.
+#[derive(Debug, Clone)]
+enum Change {
+    Name,
+    Email,
+    Role(UserRole),
+}
+
+/// What is being checked.
+#[derive(Debug, Clone)]
+enum Action {
+    Delete,
+    Update(Change),
+    ReadFields(&'static [&'static str]),
+}
+
+use Actor::*;
+use Change::*;
+
+const ALL_FIELDS: &[&str] = &["created_at", "updated_at", "name", "email", "role"];
+const PUBLIC_FIELDS: &[&str] = &["created_at", "name", "role"];
+
+/// `(actor, target role, action, expected allow)`. For `ReadFields` the expected field set is
+/// carried by the action itself and the boolean is ignored.
+const CASES: &[(Actor, UserRole, Action, bool)] = &[
+    // reading fields
+    (Guest, Member, Action::ReadFields(PUBLIC_FIELDS), true),
+    (Guest, Admin, Action::ReadFields(PUBLIC_FIELDS), true),
+    (
+        Other(Member),
+        Member,
+        Action::ReadFields(PUBLIC_FIELDS),
+        true,
+    ),
+    (
+        Other(Maintainer),
+        Creator,
+        Action::ReadFields(PUBLIC_FIELDS),
+        true,
+    ),
+    (Myself, Member, Action::ReadFields(ALL_FIELDS), true),
+    (Myself, Contributor, Action::ReadFields(ALL_FIELDS), true),
+    (
+        Other(Moderator),
+        Member,
+        Action::ReadFields(ALL_FIELDS),
+        true,
+    ),
+    (
+        Other(Moderator),
+        Admin,
+        Action::ReadFields(ALL_FIELDS),
+        true,
+    ),
+    (
+        Other(Admin),
+        Moderator,
+        Action::ReadFields(ALL_FIELDS),
+        true,
+    ),
+    // deleting
+    (Guest, Member, Action::Delete, false),
+    (Myself, Member, Action::Delete, true),
+    (Myself, Admin, Action::Delete, true),
+    (Other(Member), Member, Action::Delete, false),
+    (Other(Maintainer), Member, Action::Delete, false),
+    (Other(Moderator), Maintainer, Action::Delete, true),
+    (Other(Moderator), Member, Action::Delete, true),
+    (Other(Moderator), Moderator, Action::Delete, false),
+    (Other(Moderator), Admin, Action::Delete, false),
+    (Other(Admin), Moderator, Action::Delete, true),
+    (Other(Admin), Admin, Action::Delete, true),
+    // updating
+    (Guest, Member, Action::Update(Name), false),
+    (Myself, Member, Action::Update(Name), true),
+    (Myself, Member, Action::Update(Email), true),
+    (Myself, Member, Action::Update(Role(Admin)), false),
+    (Myself, Moderator, Action::Update(Role(Admin)), false),
+    (Other(Member), Member, Action::Update(Name), false),
+    (Other(Contributor), Member, Action::Update(Email), false),
+    (Other(Moderator), Moderator, Action::Update(Name), false),
+    (Other(Moderator), Admin, Action::Update(Role(Member)), false),
+    (Other(Moderator), Member, Action::Update(Email), true),
+    (
+        Other(Moderator),
+        Member,
+        Action::Update(Role(Maintainer)),
+        true,
+    ),
+    (
+        Other(Moderator),
+        Member,
+        Action::Update(Role(Moderator)),
+        false,
+    ),
+    (Other(Moderator), Member, Action::Update(Role(Admin)), false),
+    (Other(Admin), Moderator, Action::Update(Role(Member)), true),
+    (Other(Admin), Admin, Action::Update(Name), true),
+];
+
+fn user(role: &UserRole) -> user_account::Model {
+    let now = Utc::now().into();
+    user_account::Model {
+        id: Uuid::new_v4(),
+        created_at: now,
+        updated_at: now,
+        name: format!("{:?}", role).to_lowercase(),
+        email: format!("{:?}@mixini.test", role).to_lowercase(),
+        role: role.to_owned(),
+        password: String::new(),
+        verified: true,
+    }
+}
+
+fn changeset(change: &Change) -> UpdateUser {
+    let mut update = UpdateUser {
+        name: None,
+        email: None,
+        role: None,
+    };
+    match change {
+        Name => update.name = Some("renamed".to_owned()),
+        Email => update.email = Some("renamed@mixini.test".to_owned()),
+        Role(role) => update.role = Some(role.to_owned()),
+    }
+    update
+}
+
+/// The outcome of a single case, comparable against its expectation.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Allowed(bool),
+    Fields(HashSet<String>),
+    Error(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Allowed(true) => write!(f, "allow"),
+            Outcome::Allowed(false) => write!(f, "deny"),
+            Outcome::Fields(fields) => {
+                let mut fields: Vec<_> = fields.iter().map(String::as_str).collect();
+                fields.sort_unstable();
+                write!(f, "fields {:?}", fields)
+            }
+            Outcome::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+fn evaluate(oso: &Oso, actor: &Actor, target_role: &UserRole, action: &Action) -> Outcome {
+    let target = user(target_role);
+    let actor = match actor {
+        Guest => mixini_server::auth::Guest::default().to_polar(),
+        Other(role) => user(role).to_polar(),
+        Myself => target.to_owned().to_polar(),
+    };
+
+    let result = match action {
+        Action::Delete => oso.is_allowed(actor, Delete, target).map(Outcome::Allowed),
+        Action::Update(change) => {
+            let update = changeset(change);
+            let requested = update.fields();
+            oso.authorized_fields(actor, update, target)
+                .map(|fields| Outcome::Allowed(requested.is_subset(&fields)))
+        }
+        Action::ReadFields(_) => oso
+            .authorized_fields(actor, Read, target)
+            .map(Outcome::Fields),
+    };
+
+    result.unwrap_or_else(|e| Outcome::Error(e.to_string()))
+}
+
+fn expected(action: &Action, allowed: bool) -> Outcome {
+    match action {
+        Action::ReadFields(fields) => {
+            Outcome::Fields(fields.iter().map(|field| field.to_string()).collect())
+        }
+        _ => Outcome::Allowed(allowed),
+    }
+}
+
+/// Describe how `actual` differs from `expected`, naming missing and unexpected fields.
+fn diff(expected: &Outcome, actual: &Outcome) -> String {
+    match (expected, actual) {
+        (Outcome::Fields(expected), Outcome::Fields(actual)) => {
+            let mut missing: Vec<_> = expected.difference(actual).collect();
+            let mut unexpected: Vec<_> = actual.difference(expected).collect();
+            missing.sort_unstable();
+            unexpected.sort_unstable();
+            format!("missing {:?}, unexpected {:?}", missing, unexpected)
+        }
+        _ => format!("expected {}, got {}", expected, actual),
+    }
+}
+
+#[test]
+fn users_policy_matrix() {
+    let oso = try_register_oso(&Default::default()).expect("policy failed to load");
+
+    let failures: Vec<String> = CASES
+        .iter()
+        .filter_map(|(actor, target_role, action, allowed)| {
+            let expected = expected(action, *allowed);
+            let actual = evaluate(&oso, actor, target_role, action);
+            (expected != actual).then(|| {
+                format!(
+                    "  {:?} -> {:?}, {:?}: {}",
+                    actor,
+                    target_role,
+                    action,
+                    diff(&expected, &actual)
+                )
+            })
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} policy cases failed:\n{}",
+        failures.len(),
+        CASES.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn every_role_is_ranked() {
+    use sea_orm::Iterable;
+
+    for role in UserRole::iter() {
+        assert!(
+            UserRole::HIERARCHY.contains(&role),
+            "{:?} is missing from UserRole::HIERARCHY",
+            role
+        );
+    }
+}
+
+#[test]
+fn empty_user_changesets_fail_validation() {
+    // `allow_field` rules pass every actor for an empty set of fields
+    let empty = UpdateUser {
+        name: None,
+        email: None,
+        role: None,
+    };
+    assert!(empty.validate().is_err());
+    assert!(changeset(&Name).validate().is_ok());
+}
+
+#[test]
+fn empty_post_changesets_fail_validation() {
+    let mut update = UpdatePost {
+        title: None,
+        description: None,
+        source_urls: None,
+        rating: None,
+        status: None,
+    };
+    assert!(update.validate().is_err());
+    update.rating = Some(PostRating::Safe);
+    assert!(update.validate().is_ok());
+}
+
+#[test]
+fn policy_rules_are_split_with_descriptions() {
+    let text = "# Header\n\n## first rule\nallow(a, b, c) if\n    a = b;\n\nallow_field(_, _, _, \"x\");\n";
+    let rules = mixini_server::policy::parse_rules("test.polar", text);
+
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules[0].name, "allow");
+    assert_eq!(rules[0].line, 4);
+    assert_eq!(rules[0].description.as_deref(), Some("first rule"));
+    assert_eq!(rules[0].source, "allow(a, b, c) if\n    a = b;");
+    assert_eq!(rules[1].name, "allow_field");
+    assert_eq!(rules[1].description, None);
+}
+
+#[test]
+fn policy_rules_ignore_semicolons_in_strings_and_comments() {
+    let text = "## first rule\nallow(a, \"x;y\", c) if\n    # not the end;\n    a = \"\\\";\";\n\n## second rule\nallow(_, _, _); # trailing; comment\n";
+    let rules = mixini_server::policy::parse_rules("test.polar", text);
+
+    assert_eq!(rules.len(), 2);
+    assert_eq!(
+        rules[0].source,
+        "allow(a, \"x;y\", c) if\n    # not the end;\n    a = \"\\\";\";"
+    );
+    assert_eq!(rules[1].line, 7);
+    assert_eq!(rules[1].description.as_deref(), Some("second rule"));
+}
+
+fn post_by(uploader: &user_account::Model, status: PostStatus) -> post::Model {
+    let now = Utc::now().into();
+    post::Model {
+        id: Uuid::new_v4(),
+        created_at: now,
+        updated_at: now,
+        uploader_id: uploader.id,
+        title: "a post".to_owned(),
+        description: String::new(),
+        source_urls: serde_json::json!([]),
+        rating: PostRating::Safe,
+        status,
+        media_hash: None,
+        media_format: None,
+        media_size: None,
+        width: None,
+        height: None,
+        media_dhash: None,
+        camera: None,
+        software: None,
+        taken_at: None,
+        embedded_artist: None,
+        embedded_copyright: None,
+        favorite_count: 0,
+    }
+}
+
+#[test]
+fn posts_policy() {
+    let oso = try_register_oso(&Default::default()).expect("policy failed to load");
+    let guest = || mixini_server::auth::Guest::default().to_polar();
+    let uploader = user(&Member);
+    let stranger = user(&Member);
+    let moderator = user(&Moderator);
+    let active = post_by(&uploader, PostStatus::Active);
+    let hidden = post_by(&uploader, PostStatus::Hidden);
+    let mut unverified = user(&Member);
+    unverified.verified = false;
+
+    let status_change = UpdatePost {
+        title: None,
+        description: None,
+        source_urls: None,
+        rating: None,
+        status: Some(PostStatus::Hidden),
+    };
+    let can_update = |actor: &user_account::Model, update: &UpdatePost| {
+        oso.authorized_fields(actor.to_owned(), update.to_owned(), active.to_owned())
+            .map(|fields| update.fields().is_subset(&fields))
+    };
+
+    let cases = [
+        (
+            "guest reads active post",
+            oso.is_allowed(guest(), Read, active.to_owned()),
+            true,
+        ),
+        (
+            "guest reads hidden post",
+            oso.is_allowed(guest(), Read, hidden.to_owned()),
+            false,
+        ),
+        (
+            "stranger reads hidden post",
+            oso.is_allowed(stranger.to_owned(), Read, hidden.to_owned()),
+            false,
+        ),
+        (
+            "uploader reads hidden post",
+            oso.is_allowed(uploader.to_owned(), Read, hidden.to_owned()),
+            true,
+        ),
+        (
+            "moderator reads hidden post",
+            oso.is_allowed(moderator.to_owned(), Read, hidden.to_owned()),
+            true,
+        ),
+        (
+            "guest creates post",
+            oso.is_allowed(guest(), Create, "Post"),
+            false,
+        ),
+        (
+            "unverified user creates post",
+            oso.is_allowed(unverified, Create, "Post"),
+            false,
+        ),
+        (
+            "verified user creates post",
+            oso.is_allowed(stranger.to_owned(), Create, "Post"),
+            true,
+        ),
+        (
+            "guest finds similar posts",
+            oso.is_allowed(guest(), FindSimilar, "Post"),
+            false,
+        ),
+        (
+            "user finds similar posts",
+            oso.is_allowed(stranger.to_owned(), FindSimilar, "Post"),
+            true,
+        ),
+        (
+            "uploader changes status",
+            can_update(&uploader, &status_change),
+            false,
+        ),
+        (
+            "moderator changes status",
+            can_update(&moderator, &status_change),
+            true,
+        ),
+        (
+            "stranger deletes post",
+            oso.is_allowed(stranger, Delete, active.to_owned()),
+            false,
+        ),
+        (
+            "uploader deletes post",
+            oso.is_allowed(uploader.to_owned(), Delete, active.to_owned()),
+            true,
+        ),
+        (
+            "moderator deletes post",
+            oso.is_allowed(moderator.to_owned(), Delete, active.to_owned()),
+            true,
+        ),
+    ];
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|(case, actual, expected)| match actual {
+            Ok(actual) if actual == expected => None,
+            Ok(actual) => Some(format!("  {}: expected {}, got {}", case, expected, actual)),
+            Err(e) => Some(format!("  {}: error: {}", case, e)),
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} post policy cases failed:\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn media_policy() {
+    let oso = try_register_oso(&Default::default()).expect("policy failed to load");
+    let guest = || mixini_server::auth::Guest::default().to_polar();
+    let adult_guest = || {
+        mixini_server::auth::Guest {
+            age_gate_acknowledged: true,
+            ..Default::default()
+        }
+        .to_polar()
+    };
+    let uploader = user(&Member);
+    let stranger = user(&Member);
+    let safe = post_by(&uploader, PostStatus::Active);
+    let mut explicit = post_by(&uploader, PostStatus::Active);
+    explicit.rating = PostRating::Explicit;
+    let hidden = post_by(&uploader, PostStatus::Hidden);
+
+    let cases = [
+        (
+            "guest views safe media",
+            oso.is_allowed(guest(), ViewMedia, safe.to_owned()),
+            true,
+        ),
+        (
+            "guest views explicit media",
+            oso.is_allowed(guest(), ViewMedia, explicit.to_owned()),
+            false,
+        ),
+        (
+            "guest past the age gate views explicit media",
+            oso.is_allowed(adult_guest(), ViewMedia, explicit.to_owned()),
+            true,
+        ),
+        (
+            "user views explicit media",
+            oso.is_allowed(stranger.to_owned(), ViewMedia, explicit.to_owned()),
+            true,
+        ),
+        (
+            "guest past the age gate views hidden media",
+            oso.is_allowed(adult_guest(), ViewMedia, hidden.to_owned()),
+            false,
+        ),
+        (
+            "stranger views hidden media",
+            oso.is_allowed(stranger.to_owned(), ViewMedia, hidden.to_owned()),
+            false,
+        ),
+        (
+            "uploader views hidden media",
+            oso.is_allowed(uploader.to_owned(), ViewMedia, hidden.to_owned()),
+            true,
+        ),
+    ];
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|(case, actual, expected)| match actual {
+            Ok(actual) if actual == expected => None,
+            Ok(actual) => Some(format!("  {}: expected {}, got {}", case, expected, actual)),
+            Err(e) => Some(format!("  {}: error: {}", case, e)),
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} media policy cases failed:\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn tags_policy() {
+    let oso = try_register_oso(&Default::default()).expect("policy failed to load");
+    let guest = || mixini_server::auth::Guest::default().to_polar();
+    let member = user(&Member);
+    let contributor = user(&Contributor);
+    let maintainer = user(&Maintainer);
+    let active = post_by(&member, PostStatus::Active);
+
+    let cases = [
+        (
+            "guest edits tags",
+            oso.is_allowed(guest(), EditTags, active.to_owned()),
+            false,
+        ),
+        (
+            "uploading member edits tags",
+            oso.is_allowed(member.to_owned(), EditTags, active.to_owned()),
+            false,
+        ),
+        (
+            "contributor edits tags",
+            oso.is_allowed(contributor.to_owned(), EditTags, active.to_owned()),
+            true,
+        ),
+        (
+            "member creates tag",
+            oso.is_allowed(member.to_owned(), Create, "Tag"),
+            false,
+        ),
+        (
+            "contributor creates tag",
+            oso.is_allowed(contributor.to_owned(), Create, "Tag"),
+            true,
+        ),
+        (
+            "contributor creates alias",
+            oso.is_allowed(contributor.to_owned(), Create, "TagAlias"),
+            false,
+        ),
+        (
+            "maintainer creates alias",
+            oso.is_allowed(maintainer.to_owned(), Create, "TagAlias"),
+            true,
+        ),
+        (
+            "maintainer creates implication",
+            oso.is_allowed(maintainer, Create, "TagImplication"),
+            true,
+        ),
+    ];
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|(case, actual, expected)| match actual {
+            Ok(actual) if actual == expected => None,
+            Ok(actual) => Some(format!("  {}: expected {}, got {}", case, expected, actual)),
+            Err(e) => Some(format!("  {}: error: {}", case, e)),
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} tag policy cases failed:\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn collections_policy() {
+    let oso = try_register_oso(&Default::default()).expect("policy failed to load");
+    let guest = || mixini_server::auth::Guest::default().to_polar();
+    let owner = user(&Member);
+    let collaborator = user(&Member);
+    let stranger = user(&Member);
+    let moderator = user(&Moderator);
+    let collection = |is_public| CollectionResource {
+        id: Uuid::new_v4(),
+        owner_id: owner.id,
+        is_public,
+        collaborator_ids: vec![collaborator.id],
+    };
+    let private = collection(false);
+    let public = collection(true);
+    let favorites = |public| Favorites {
+        user_id: owner.id,
+        public,
+    };
+    let rename = UpdateCollection {
+        name: Some("renamed".to_owned()),
+        description: None,
+        is_public: None,
+    };
+    let hidden = post_by(&owner, PostStatus::Hidden);
+
+    let cases = [
+        (
+            "guest reads public collection",
+            oso.is_allowed(guest(), Read, public.to_owned()),
+            true,
+        ),
+        (
+            "guest reads private collection",
+            oso.is_allowed(guest(), Read, private.to_owned()),
+            false,
+        ),
+        (
+            "stranger reads private collection",
+            oso.is_allowed(stranger.to_owned(), Read, private.to_owned()),
+            false,
+        ),
+        (
+            "collaborator reads private collection",
+            oso.is_allowed(collaborator.to_owned(), Read, private.to_owned()),
+            true,
+        ),
+        (
+            "moderator reads private collection",
+            oso.is_allowed(moderator.to_owned(), Read, private.to_owned()),
+            true,
+        ),
+        (
+            "stranger edits posts of public collection",
+            oso.is_allowed(stranger.to_owned(), EditCollectionPosts, public.to_owned()),
+            false,
+        ),
+        (
+            "collaborator edits posts",
+            oso.is_allowed(
+                collaborator.to_owned(),
+                EditCollectionPosts,
+                private.to_owned(),
+            ),
+            true,
+        ),
+        (
+            "collaborator renames collection",
+            oso.is_allowed(
+                collaborator.to_owned(),
+                rename.to_owned(),
+                private.to_owned(),
+            ),
+            false,
+        ),
+        (
+            "owner renames collection",
+            oso.is_allowed(owner.to_owned(), rename.to_owned(), private.to_owned()),
+            true,
+        ),
+        (
+            "moderator renames collection",
+            oso.is_allowed(moderator.to_owned(), rename, private.to_owned()),
+            true,
+        ),
+        (
+            "collaborator manages collaborators",
+            oso.is_allowed(
+                collaborator.to_owned(),
+                ManageCollaborators,
+                private.to_owned(),
+            ),
+            false,
+        ),
+        (
+            "moderator manages collaborators",
+            oso.is_allowed(
+                moderator.to_owned(),
+                ManageCollaborators,
+                private.to_owned(),
+            ),
+            false,
+        ),
+        (
+            "owner manages collaborators",
+            oso.is_allowed(owner.to_owned(), ManageCollaborators, private.to_owned()),
+            true,
+        ),
+        (
+            "collaborator deletes collection",
+            oso.is_allowed(collaborator.to_owned(), Delete, private.to_owned()),
+            false,
+        ),
+        (
+            "owner deletes collection",
+            oso.is_allowed(owner.to_owned(), Delete, private),
+            true,
+        ),
+        (
+            "unverified user creates collection",
+            oso.is_allowed(
+                user_account::Model {
+                    verified: false,
+                    ..stranger.to_owned()
+                },
+                Create,
+                "Collection",
+            ),
+            false,
+        ),
+        (
+            "guest reads private favorites",
+            oso.is_allowed(guest(), Read, favorites(false)),
+            false,
+        ),
+        (
+            "guest reads public favorites",
+            oso.is_allowed(guest(), Read, favorites(true)),
+            true,
+        ),
+        (
+            "stranger reads private favorites",
+            oso.is_allowed(stranger.to_owned(), Read, favorites(false)),
+            false,
+        ),
+        (
+            "owner reads private favorites",
+            oso.is_allowed(owner.to_owned(), Read, favorites(false)),
+            true,
+        ),
+        (
+            "stranger favorites hidden post",
+            oso.is_allowed(stranger, Favorite, hidden.to_owned()),
+            false,
+        ),
+        (
+            "uploader favorites hidden post",
+            oso.is_allowed(owner.to_owned(), Favorite, hidden),
+            true,
+        ),
+    ];
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|(case, actual, expected)| match actual {
+            Ok(actual) if actual == expected => None,
+            Ok(actual) => Some(format!("  {}: expected {}, got {}", case, expected, actual)),
+            Err(e) => Some(format!("  {}: error: {}", case, e)),
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} collection policy cases failed:\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+fn comment_by(author: &user_account::Model, post: &post::Model, age: Duration) -> comment::Model {
+    let created_at = (Utc::now() - age).into();
+    comment::Model {
+        id: Uuid::new_v4(),
+        created_at,
+        updated_at: created_at,
+        post_id: post.id,
+        author_id: author.id,
+        parent_id: None,
+        depth: 0,
+        body: "a comment".to_owned(),
+        body_html: "<p>a comment</p>\n".to_owned(),
+        edited_at: None,
+        deleted_at: None,
+        deleted_by: None,
+        hidden_at: None,
+        hidden_by: None,
+    }
+}
+
+#[test]
+fn comments_policy() {
+    let oso = try_register_oso(&Default::default()).expect("policy failed to load");
+    let guest = || mixini_server::auth::Guest::default().to_polar();
+    let member = user(&Member);
+    let contributor = user(&Contributor);
+    let stranger = user(&Member);
+    let moderator = user(&Moderator);
+    let mut unverified = user(&Member);
+    unverified.verified = false;
+    let active = post_by(&member, PostStatus::Active);
+    let fresh = comment_by(&member, &active, Duration::minutes(5));
+    let half_hour = comment_by(&member, &active, Duration::minutes(30));
+    let contributor_half_hour = comment_by(&contributor, &active, Duration::minutes(30));
+    let contributor_old = comment_by(&contributor, &active, Duration::days(2));
+    let mut deleted = fresh.to_owned();
+    deleted.deleted_at = Some(Utc::now().into());
+    let mut hidden = fresh.to_owned();
+    hidden.hidden_at = Some(Utc::now().into());
+
+    let cases = [
+        (
+            "member comments on post",
+            oso.is_allowed(member.to_owned(), CommentOn, active.to_owned()),
+            true,
+        ),
+        (
+            "unverified user comments on post",
+            oso.is_allowed(unverified, CommentOn, active.to_owned()),
+            false,
+        ),
+        (
+            "guest comments on post",
+            oso.is_allowed(guest(), CommentOn, active),
+            false,
+        ),
+        (
+            "guest reads comment",
+            oso.is_allowed(guest(), Read, fresh.to_owned()),
+            true,
+        ),
+        (
+            "stranger reads deleted comment",
+            oso.is_allowed(stranger.to_owned(), Read, deleted.to_owned()),
+            false,
+        ),
+        (
+            "author reads own hidden comment",
+            oso.is_allowed(member.to_owned(), Read, hidden.to_owned()),
+            true,
+        ),
+        (
+            "moderator reads deleted comment",
+            oso.is_allowed(moderator.to_owned(), Read, deleted.to_owned()),
+            true,
+        ),
+        (
+            "member edits fresh comment",
+            oso.is_allowed(member.to_owned(), EditComment, fresh.to_owned()),
+            true,
+        ),
+        (
+            "member edits half hour old comment",
+            oso.is_allowed(member.to_owned(), EditComment, half_hour),
+            false,
+        ),
+        (
+            "contributor edits half hour old comment",
+            oso.is_allowed(contributor.to_owned(), EditComment, contributor_half_hour),
+            true,
+        ),
+        (
+            "contributor edits two day old comment",
+            oso.is_allowed(contributor, EditComment, contributor_old),
+            false,
+        ),
+        (
+            "stranger edits fresh comment",
+            oso.is_allowed(stranger.to_owned(), EditComment, fresh.to_owned()),
+            false,
+        ),
+        (
+            "author edits hidden comment",
+            oso.is_allowed(member.to_owned(), EditComment, hidden.to_owned()),
+            false,
+        ),
+        (
+            "author deletes comment",
+            oso.is_allowed(member.to_owned(), Delete, fresh.to_owned()),
+            true,
+        ),
+        (
+            "stranger deletes comment",
+            oso.is_allowed(stranger.to_owned(), Delete, fresh.to_owned()),
+            false,
+        ),
+        (
+            "moderator deletes deleted comment",
+            oso.is_allowed(moderator.to_owned(), Delete, deleted),
+            false,
+        ),
+        (
+            "author hides comment",
+            oso.is_allowed(member.to_owned(), Hide, fresh.to_owned()),
+            false,
+        ),
+        (
+            "moderator hides comment",
+            oso.is_allowed(moderator.to_owned(), Hide, fresh.to_owned()),
+            true,
+        ),
+        (
+            "moderator restores visible comment",
+            oso.is_allowed(moderator.to_owned(), Restore, fresh),
+            false,
+        ),
+        (
+            "author restores hidden comment",
+            oso.is_allowed(member, Restore, hidden.to_owned()),
+            false,
+        ),
+        (
+            "moderator restores hidden comment",
+            oso.is_allowed(moderator, Restore, hidden),
+            true,
+        ),
+    ];
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|(case, actual, expected)| match actual {
+            Ok(actual) if actual == expected => None,
+            Ok(actual) => Some(format!("  {}: expected {}, got {}", case, expected, actual)),
+            Err(e) => Some(format!("  {}: error: {}", case, e)),
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} comment policy cases failed:\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+fn revision_by(editor: &user_account::Model, version: i32) -> post_revision::Model {
+    post_revision::Model {
+        post_id: Uuid::new_v4(),
+        version,
+        created_at: Utc::now().into(),
+        editor_id: Some(editor.id),
+        editor_role: editor.role.to_owned(),
+        tags: serde_json::json!(["vandalized"]),
+        source_urls: serde_json::json!([]),
+        rating: PostRating::Explicit,
+        description: String::new(),
+        reverted_to: None,
+    }
+}
+
+#[test]
+fn revisions_policy() {
+    let oso = try_register_oso(&Default::default()).expect("policy failed to load");
+    let guest = || mixini_server::auth::Guest::default().to_polar();
+    let member = user(&Member);
+    let other_member = user(&Member);
+    let contributor = user(&Contributor);
+    let other_contributor = user(&Contributor);
+    let moderator = user(&Moderator);
+    let mut unverified = user(&Contributor);
+    unverified.verified = false;
+    let by_member = revision_by(&member, 2);
+    let by_contributor = revision_by(&contributor, 3);
+    let by_maintainer = revision_by(&user(&Maintainer), 4);
+
+    let cases = [
+        (
+            "guest reverts member revision",
+            oso.is_allowed(guest(), Revert, by_member.to_owned()),
+            false,
+        ),
+        (
+            "member reverts own revision",
+            oso.is_allowed(member.to_owned(), Revert, by_member.to_owned()),
+            true,
+        ),
+        (
+            "member reverts other member revision",
+            oso.is_allowed(other_member, Revert, by_member.to_owned()),
+            false,
+        ),
+        (
+            "member reverts contributor revision",
+            oso.is_allowed(member, Revert, by_contributor.to_owned()),
+            false,
+        ),
+        (
+            "contributor reverts member revision",
+            oso.is_allowed(contributor.to_owned(), Revert, by_member.to_owned()),
+            true,
+        ),
+        (
+            "unverified contributor reverts member revision",
+            oso.is_allowed(unverified, Revert, by_member.to_owned()),
+            false,
+        ),
+        (
+            "contributor reverts other contributor revision",
+            oso.is_allowed(other_contributor, Revert, by_contributor.to_owned()),
+            false,
+        ),
+        (
+            "contributor reverts maintainer revision",
+            oso.is_allowed(contributor, Revert, by_maintainer.to_owned()),
+            false,
+        ),
+        (
+            "moderator reverts maintainer revision",
+            oso.is_allowed(moderator, Revert, by_maintainer),
+            true,
+        ),
+    ];
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|(case, actual, expected)| match actual {
+            Ok(actual) if actual == expected => None,
+            Ok(actual) => Some(format!("  {}: expected {}, got {}", case, expected, actual)),
+            Err(e) => Some(format!("  {}: error: {}", case, e)),
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} revision policy cases failed:\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+fn grant(
+    user: &user_account::Model,
+    permission: &str,
+    scope: Option<(&str, Uuid)>,
+    expires_in: Option<Duration>,
+) -> permission_grant::Model {
+    permission_grant::Model {
+        id: Uuid::new_v4(),
+        created_at: Utc::now().into(),
+        user_id: user.id,
+        permission: permission.to_owned(),
+        resource_type: scope.map(|(resource_type, _)| resource_type.to_owned()),
+        resource_id: scope.map(|(_, resource_id)| resource_id),
+        expires_at: expires_in.map(|expires_in| (Utc::now() + expires_in).into()),
+        granted_by: None,
+    }
+}
+
+#[test]
+fn permissions_policy() {
+    let tag_manager = user(&Member);
+    let scoped = user(&Member);
+    let expired = user(&Member);
+    let other = user(&Member);
+    let admin = user(&Admin);
+    let tag = Uuid::new_v4();
+    let grants = Arc::new(Grants::new([
+        grant(&tag_manager, "tags.manage", None, Some(Duration::days(1))),
+        grant(&scoped, "tags.manage", Some(("tag", tag)), None),
+        grant(&expired, "tags.manage", None, Some(Duration::days(-1))),
+    ]));
+    let oso = try_register_oso(&grants).expect("policy failed to load");
+
+    let permission_cases = [
+        ("global grant", &tag_manager, None, true),
+        (
+            "global grant applies to scopes",
+            &tag_manager,
+            Some(("tag", tag)),
+            true,
+        ),
+        (
+            "scoped grant on its resource",
+            &scoped,
+            Some(("tag", tag)),
+            true,
+        ),
+        (
+            "scoped grant on another resource",
+            &scoped,
+            Some(("tag", Uuid::new_v4())),
+            false,
+        ),
+        ("scoped grant unscoped", &scoped, None, false),
+        ("expired grant", &expired, None, false),
+        ("no grant", &other, None, false),
+    ];
+    let mut failures: Vec<String> = permission_cases
+        .iter()
+        .filter(|(_, user, scope, expected)| {
+            grants.has_permission(user.id, "tags.manage", *scope) != *expected
+        })
+        .map(|(case, _, _, expected)| format!("  {}: expected {}", case, expected))
+        .collect();
+
+    let policy_cases = [
+        (
+            "granted member creates alias",
+            oso.is_allowed(tag_manager.to_owned(), Create, "TagAlias"),
+            true,
+        ),
+        (
+            "granted member creates implication",
+            oso.is_allowed(tag_manager.to_owned(), Create, "TagImplication"),
+            true,
+        ),
+        (
+            "scoped member creates alias",
+            oso.is_allowed(scoped.to_owned(), Create, "TagAlias"),
+            false,
+        ),
+        (
+            "expired member creates alias",
+            oso.is_allowed(expired.to_owned(), Create, "TagAlias"),
+            false,
+        ),
+        (
+            "admin grants permission",
+            oso.is_allowed(admin.to_owned(), Grant, other.to_owned()),
+            true,
+        ),
+        (
+            "granted member grants permission",
+            oso.is_allowed(tag_manager.to_owned(), Grant, other.to_owned()),
+            false,
+        ),
+        (
+            "admin revokes permission",
+            oso.is_allowed(admin, Revoke, tag_manager.to_owned()),
+            true,
+        ),
+        (
+            "member revokes permission",
+            oso.is_allowed(other, Revoke, tag_manager.to_owned()),
+            false,
+        ),
+    ];
+    failures.extend(
+        policy_cases
+            .iter()
+            .filter_map(|(case, actual, expected)| match actual {
+                Ok(actual) if actual == expected => None,
+                Ok(actual) => Some(format!("  {}: expected {}, got {}", case, expected, actual)),
+                Err(e) => Some(format!("  {}: error: {}", case, e)),
+            }),
+    );
+
+    // revoking is refreshing without the grant
+    grants.replace(Vec::<permission_grant::Model>::new());
+    if oso
+        .is_allowed(tag_manager, Create, "TagAlias")
+        .unwrap_or(true)
+    {
+        failures.push("  revoked member creates alias: expected false".to_owned());
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} permission cases failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
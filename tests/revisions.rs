@@ -0,0 +1,212 @@
+//! Tests for comparing, recording and restoring the community-edited content of posts.
+
+use chrono::Utc;
+use entity::{
+    post, post_revision,
+    sea_orm_active_enums::{PostRating, PostStatus, UserRole},
+    user_account,
+};
+use mixini_server::revisions::{
+    next_revision, Change, DiffLine, LineOp, ListDiff, PostSnapshot, SnapshotDiff,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+fn snapshot(tags: &[&str], rating: PostRating, description: &str) -> PostSnapshot {
+    PostSnapshot {
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        source_urls: vec!["https://mixini.test/1".to_owned()],
+        rating,
+        description: description.to_owned(),
+    }
+}
+
+fn user(role: UserRole) -> user_account::Model {
+    let now = Utc::now().into();
+    user_account::Model {
+        id: Uuid::new_v4(),
+        created_at: now,
+        updated_at: now,
+        name: format!("{:?}", role).to_lowercase(),
+        email: format!("{:?}@mixini.test", role).to_lowercase(),
+        role,
+        password: String::new(),
+        verified: true,
+    }
+}
+
+/// A post by `uploader` with the content of `content`.
+fn post_by(uploader: &user_account::Model, content: &PostSnapshot) -> post::Model {
+    let now = Utc::now().into();
+    post::Model {
+        id: Uuid::new_v4(),
+        created_at: now,
+        updated_at: now,
+        uploader_id: uploader.id,
+        title: "a post".to_owned(),
+        description: content.description.to_owned(),
+        source_urls: json!(content.source_urls),
+        rating: content.rating.to_owned(),
+        status: PostStatus::Active,
+        media_hash: None,
+        media_format: None,
+        media_size: None,
+        width: None,
+        height: None,
+        media_dhash: None,
+        camera: None,
+        software: None,
+        taken_at: None,
+        embedded_artist: None,
+        embedded_copyright: None,
+        favorite_count: 0,
+    }
+}
+
+/// Version `version` of `post` with the content `content`, by `editor`.
+fn revision(
+    post: &post::Model,
+    version: i32,
+    editor: &user_account::Model,
+    content: &PostSnapshot,
+) -> post_revision::Model {
+    post_revision::Model {
+        post_id: post.id,
+        version,
+        created_at: Utc::now().into(),
+        editor_id: Some(editor.id),
+        editor_role: editor.role.to_owned(),
+        tags: json!(content.tags),
+        source_urls: json!(content.source_urls),
+        rating: content.rating.to_owned(),
+        description: content.description.to_owned(),
+        reverted_to: None,
+    }
+}
+
+#[test]
+fn diffs_every_changed_field() {
+    let from = snapshot(
+        &["cat", "outdoors"],
+        PostRating::Safe,
+        "a cat\nin a field\n",
+    );
+    let to = snapshot(
+        &["cat", "sleeping"],
+        PostRating::Questionable,
+        "a cat\nasleep\n",
+    );
+
+    let diff = SnapshotDiff::new(&from, &to);
+
+    assert_eq!(
+        diff.tags,
+        ListDiff {
+            added: vec!["sleeping".to_owned()],
+            removed: vec!["outdoors".to_owned()],
+        }
+    );
+    assert_eq!(diff.source_urls, ListDiff::default());
+    assert_eq!(
+        diff.rating,
+        Some(Change {
+            from: PostRating::Safe,
+            to: PostRating::Questionable,
+        })
+    );
+    assert_eq!(
+        diff.description,
+        Some(vec![
+            DiffLine {
+                op: LineOp::Equal,
+                text: "a cat\n".to_owned(),
+            },
+            DiffLine {
+                op: LineOp::Delete,
+                text: "in a field\n".to_owned(),
+            },
+            DiffLine {
+                op: LineOp::Insert,
+                text: "asleep\n".to_owned(),
+            },
+        ])
+    );
+    assert_eq!(
+        from.changed_fields(&to),
+        vec!["tags", "rating", "description"]
+    );
+}
+
+#[test]
+fn unchanged_snapshots_have_empty_diffs() {
+    let same = snapshot(&["cat"], PostRating::Safe, "a cat");
+
+    let diff = SnapshotDiff::new(&same, &same.to_owned());
+
+    assert_eq!(diff.tags, ListDiff::default());
+    assert_eq!(diff.rating, None);
+    assert_eq!(diff.description, None);
+    assert!(same.changed_fields(&same).is_empty());
+}
+
+#[test]
+fn reverts_are_recorded_as_new_revisions_by_the_reverter() {
+    let uploader = user(UserRole::Member);
+    let vandal = user(UserRole::Member);
+    let moderator = user(UserRole::Moderator);
+    let original = snapshot(&["cat"], PostRating::Safe, "a cat");
+    let vandalized = snapshot(&["cat", "dog"], PostRating::Explicit, "not a cat");
+    let post = post_by(&uploader, &vandalized);
+    let first = revision(&post, 1, &uploader, &original);
+    let latest = revision(&post, 2, &vandal, &vandalized);
+
+    let restored = PostSnapshot::of(&first)
+        .unwrap()
+        .restore(post.to_owned())
+        .unwrap();
+    assert_eq!(restored.rating.unwrap(), PostRating::Safe);
+    assert_eq!(restored.description.unwrap(), "a cat");
+    assert_eq!(restored.source_urls.unwrap(), json!(original.source_urls));
+    // only the content kept in revisions is restored
+    assert_eq!(restored.title.unwrap(), post.title);
+    assert_eq!(restored.status.unwrap(), post.status);
+
+    let reverted = next_revision(
+        post.id,
+        Some(&latest),
+        PostSnapshot::of(&first).unwrap(),
+        &moderator,
+        Some(first.version),
+    )
+    .unwrap()
+    .expect("the revert wasn't recorded");
+    assert_eq!(reverted.post_id.unwrap(), post.id);
+    assert_eq!(reverted.version.unwrap(), 3);
+    assert_eq!(reverted.editor_id.unwrap(), Some(moderator.id));
+    assert_eq!(reverted.editor_role.unwrap(), UserRole::Moderator);
+    assert_eq!(reverted.reverted_to.unwrap(), Some(1));
+    assert_eq!(reverted.tags.unwrap(), json!(["cat"]));
+    assert_eq!(reverted.rating.unwrap(), PostRating::Safe);
+    assert_eq!(reverted.description.unwrap(), "a cat");
+}
+
+#[test]
+fn unchanged_content_records_no_revision() {
+    let uploader = user(UserRole::Member);
+    let editor = user(UserRole::Member);
+    let content = snapshot(&["cat"], PostRating::Safe, "a cat");
+    let post = post_by(&uploader, &content);
+    let first = revision(&post, 1, &uploader, &content);
+
+    assert!(
+        next_revision(post.id, Some(&first), content.to_owned(), &editor, Some(1))
+            .unwrap()
+            .is_none()
+    );
+
+    let recorded = next_revision(post.id, None, content, &editor, None)
+        .unwrap()
+        .expect("the first revision wasn't recorded");
+    assert_eq!(recorded.version.unwrap(), 1);
+    assert_eq!(recorded.editor_id.unwrap(), Some(editor.id));
+}
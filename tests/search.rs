@@ -0,0 +1,179 @@
+//! Tests for parsing search queries and compiling them into SQL.
+
+use chrono::Utc;
+use entity::{
+    prelude::*,
+    sea_orm_active_enums::{PostRating, TagCategory},
+    tag,
+};
+use mixini_server::{
+    constants::MAX_SEARCH_NESTING,
+    search::{
+        headline_html, parse_query, search_condition, text_condition, validate_text, Expr, Order,
+        Term,
+    },
+};
+use sea_orm::{DbBackend, EntityTrait, QueryFilter, QueryTrait};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+fn tag(name: &str) -> Expr {
+    Expr::Term(Term::Tag(name.to_owned()))
+}
+
+#[test]
+fn parses_the_full_language() {
+    let query = parse_query(
+        "cat -dog (red ~ blue) rating:safe order:score artist:*foo*",
+        10,
+    )
+    .expect("query failed to parse");
+
+    assert_eq!(query.order, Some(Order::Score));
+    assert_eq!(query.terms, 6);
+    assert_eq!(
+        query.expr,
+        Expr::All(vec![
+            tag("cat"),
+            Expr::Not(Box::new(tag("dog"))),
+            Expr::Any(vec![tag("red"), tag("blue")]),
+            Expr::Term(Term::Rating(PostRating::Safe)),
+            Expr::Term(Term::CategoryTag(TagCategory::Artist, "*foo*".to_owned())),
+        ])
+    );
+}
+
+#[test]
+fn parentheses_in_tag_names_are_not_groups() {
+    let query = parse_query("(ribbon_(hair) ~ bow)", 10).expect("query failed to parse");
+    assert_eq!(
+        query.expr,
+        Expr::All(vec![Expr::Any(vec![tag("ribbon_(hair)"), tag("bow")])])
+    );
+}
+
+#[test]
+fn errors_point_at_the_offending_position() {
+    let cases = [
+        ("cat (dog", 4, "Unclosed `(`"),
+        ("cat dog)", 7, "Unmatched `)`"),
+        ("cat ~", 5, "Expected a term"),
+        (
+            "rating:nope",
+            7,
+            "Rating must be one of safe, questionable, or explicit",
+        ),
+        ("cat colour:red", 4, "Unknown search key `colour`"),
+        (
+            "(order:new)",
+            1,
+            "`order:` can only be used at the top level",
+        ),
+        ("a b c d", 6, "Searches are limited to 3 terms"),
+    ];
+
+    for (input, position, message) in cases {
+        let error = parse_query(input, 3).expect_err(input);
+        assert_eq!(
+            (error.position, error.message.as_str()),
+            (position, message),
+            "for {:?}",
+            input
+        );
+    }
+}
+
+#[test]
+fn limits_nesting() {
+    let at_limit =
+        format!("{}cat", "-(".repeat(MAX_SEARCH_NESTING / 2)) + &")".repeat(MAX_SEARCH_NESTING / 2);
+    assert!(parse_query(&at_limit, 3).is_ok());
+
+    // far deeper than would overflow the stack without a limit
+    let groups = format!("{}cat{}", "(".repeat(100_000), ")".repeat(100_000));
+    let negations = format!("{}cat", "-".repeat(100_000));
+    let message = format!(
+        "Groups and negations can be nested at most {} deep",
+        MAX_SEARCH_NESTING
+    );
+    for input in [groups, negations, "(".repeat(100_000)] {
+        let error = parse_query(&input, 3).expect_err("parsed past the nesting limit");
+        assert_eq!(
+            (error.position, error.message.as_str()),
+            (MAX_SEARCH_NESTING, message.as_str()),
+            "for {:?}...",
+            &input[..8]
+        );
+    }
+}
+
+#[test]
+fn compiles_to_subqueries_over_tags() {
+    let now = Utc::now().into();
+    let cat = tag::Model {
+        id: Uuid::new_v4(),
+        created_at: now,
+        updated_at: now,
+        name: "cat".to_owned(),
+        category: TagCategory::General,
+        post_count: 1,
+        wiki: String::new(),
+    };
+    let tags = HashMap::from([("kitty".to_owned(), cat.to_owned())]);
+
+    let query = parse_query("kitty -missing *_ear*", 10).expect("query failed to parse");
+    let sql = Post::find()
+        .filter(search_condition(&query.expr, &tags))
+        .build(DbBackend::Postgres)
+        .to_string();
+
+    assert!(
+        sql.contains(&cat.id.to_string()),
+        "alias not resolved in: {}",
+        sql
+    );
+    assert!(
+        sql.contains("FALSE"),
+        "unknown tag should match nothing in: {}",
+        sql
+    );
+    assert!(
+        sql.contains("LIKE") && sql.contains("ear%"),
+        "wildcard not compiled in: {}",
+        sql
+    );
+}
+
+#[test]
+fn text_matches_posts_and_tag_wikis() {
+    let sql = Post::find()
+        .filter(text_condition("striped \"tabby cat\""))
+        .build(DbBackend::Postgres)
+        .to_string();
+
+    assert!(
+        sql.contains(r#""post"."search_vector" @@ websearch_to_tsquery"#),
+        "missing post match in: {}",
+        sql
+    );
+    assert!(
+        sql.contains(r#""tag"."search_vector" @@ websearch_to_tsquery"#),
+        "missing tag wiki match in: {}",
+        sql
+    );
+    assert!(validate_text(&"a".repeat(256)).is_ok());
+    assert_eq!(validate_text(&"a".repeat(257)).unwrap_err().position, 256);
+}
+
+#[test]
+fn headlines_escape_everything_but_matches() {
+    // As returned by `ts_headline` for a post titled `<script>alert(1)</script>` searched for
+    // "alert".
+    let headline = "<script>\u{e000}alert\u{e001}(1)</script> <img src=x onerror='x'> & \"";
+
+    assert_eq!(
+        headline_html(headline),
+        "&lt;script&gt;<mark>alert</mark>(1)&lt;/script&gt; \
+         &lt;img src=x onerror=&#39;x&#39;&gt; &amp; &quot;"
+    );
+}
@@ -0,0 +1,63 @@
+//! Tests for perceptual hashing and the similarity index.
+
+use image::{imageops::FilterType, DynamicImage, Rgb, RgbImage};
+use mixini_server::media::{dhash, hamming_distance, BkTree};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use uuid::Uuid;
+
+/// A diagonal gradient, which has plenty of structure for a hash to pick up.
+fn gradient(width: u32, height: u32, flipped: bool) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+        let x = if flipped { width - 1 - x } else { x };
+        let value = ((x * 255 / width + y * 255 / height) / 2) as u8;
+        Rgb([value, value / 2, 255 - value])
+    }))
+}
+
+#[test]
+fn similar_images_hash_alike() {
+    let original = gradient(640, 480, false);
+    let resized = original.resize_exact(320, 240, FilterType::Lanczos3);
+    let recompressed = {
+        let mut bytes = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, 40)
+            .encode_image(&original)
+            .unwrap();
+        image::load_from_memory(&bytes).unwrap()
+    };
+    let different = gradient(640, 480, true);
+
+    let hash = dhash(&original);
+    assert!(hamming_distance(hash, dhash(&resized)) <= 4);
+    assert!(hamming_distance(hash, dhash(&recompressed)) <= 4);
+    assert!(hamming_distance(hash, dhash(&different)) > 16);
+}
+
+#[test]
+fn bk_tree_finds_what_a_linear_scan_finds() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut tree = BkTree::new();
+    let mut hashes = Vec::new();
+    for _ in 0..2000 {
+        let hash: u64 = rng.gen();
+        let id = Uuid::new_v4();
+        tree.insert(hash, id);
+        hashes.push((hash, id));
+    }
+    // a post with the same hash as another is kept too
+    let twin = Uuid::new_v4();
+    tree.insert(hashes[0].0, twin);
+    hashes.push((hashes[0].0, twin));
+    assert_eq!(tree.len(), 2001);
+
+    for (query, max_distance) in [(hashes[0].0, 0), (hashes[7].0, 20), (rng.gen(), 24)] {
+        let mut expected: Vec<(Uuid, u32)> = hashes
+            .iter()
+            .map(|(hash, id)| (*id, hamming_distance(*hash, query)))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .collect();
+        expected.sort_unstable_by_key(|(id, distance)| (*distance, *id));
+
+        assert_eq!(tree.find(query, max_distance), expected);
+    }
+}
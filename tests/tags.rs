@@ -0,0 +1,31 @@
+//! Tests for tag name parsing and validation in `mixini_server::tags`.
+
+use mixini_server::tags::{parse_tag_names, validate_tag_name, validate_tag_names};
+
+#[test]
+fn tag_names_are_normalized_and_deduplicated() {
+    assert_eq!(
+        parse_tag_names("  Blue_Sky blue_sky\tCAT  cat's_eye "),
+        vec!["blue_sky", "cat", "cat's_eye"]
+    );
+}
+
+#[test]
+fn tag_names_are_validated() {
+    for valid in ["cat", "blue_sky", "ribbon_(hair)", "wow!", "1.5x", "a-b"] {
+        assert!(
+            validate_tag_name(valid).is_ok(),
+            "{} should be valid",
+            valid
+        );
+    }
+    for invalid in ["-cat", "~cat", "(cat)", "c*t", "rating:safe", "a/b", "", &"a".repeat(65)] {
+        assert!(
+            validate_tag_name(invalid).is_err(),
+            "{} should be invalid",
+            invalid
+        );
+    }
+    assert!(validate_tag_names("cat dog").is_ok());
+    assert!(validate_tag_names("cat -dog").is_err());
+}